@@ -0,0 +1,102 @@
+// Filtering and keyboard navigation shared by the index and image pages of the review gallery.
+(function () {
+  const params = new URLSearchParams(window.location.search);
+  const minInput = document.getElementById("min-confidence");
+  const minValue = document.getElementById("min-confidence-value");
+  const categoryInputs = Array.from(
+    document.querySelectorAll("input[data-category]")
+  );
+
+  // Restore the filter carried over from the previous page
+  if (params.has("min")) {
+    minInput.value = params.get("min");
+  }
+  if (params.has("categories")) {
+    const categories = params.get("categories").split(",");
+    categoryInputs.forEach((input) => {
+      input.checked = categories.includes(input.dataset.category);
+    });
+  }
+
+  function filter() {
+    return {
+      min: parseFloat(minInput.value),
+      categories: categoryInputs
+        .filter((input) => input.checked)
+        .map((input) => input.dataset.category),
+    };
+  }
+
+  function apply() {
+    const { min, categories } = filter();
+    minValue.textContent = min.toFixed(2);
+
+    // Boxes on image pages
+    document.querySelectorAll("[data-box]").forEach((box) => {
+      const visible =
+        categories.includes(box.dataset.category) &&
+        parseFloat(box.dataset.confidence) >= min;
+      box.style.display = visible ? "" : "none";
+    });
+
+    // Cards on the index
+    document.querySelectorAll("[data-card]").forEach((card) => {
+      const detections = JSON.parse(card.dataset.detections);
+      const visible = card.dataset.error
+        ? categories.includes("Error")
+        : detections.length === 0
+        ? categories.includes("Empty")
+        : detections.some(
+            ([category, confidence]) =>
+              categories.includes(category) && confidence >= min
+          );
+      card.style.display = visible ? "" : "none";
+    });
+
+    // Keep the filter when navigating between pages
+    const search =
+      "?min=" + min + "&categories=" + encodeURIComponent(categories.join(","));
+    document.querySelectorAll("a[data-nav]").forEach((link) => {
+      link.search = search;
+    });
+  }
+
+  function visibleCards() {
+    return Array.from(document.querySelectorAll("[data-card]")).filter(
+      (card) => card.style.display !== "none"
+    );
+  }
+
+  document.addEventListener("keydown", (event) => {
+    if (event.target.type === "range") {
+      return;
+    }
+
+    const cards = visibleCards();
+    if (cards.length > 0) {
+      // Move focus between the cards on the index, Enter opens the focused image
+      const step = { ArrowLeft: -1, ArrowRight: 1 }[event.key];
+      if (step) {
+        const current = cards.findIndex((card) =>
+          card.contains(document.activeElement)
+        );
+        const next = cards[Math.min(Math.max(current + step, 0), cards.length - 1)];
+        next.querySelector("a").focus();
+        event.preventDefault();
+      }
+      return;
+    }
+
+    const nav = { ArrowLeft: "prev", ArrowRight: "next", Escape: "index" }[
+      event.key
+    ];
+    const link = nav && document.querySelector('a[data-nav="' + nav + '"]');
+    if (link) {
+      window.location.href = link.href;
+    }
+  });
+
+  minInput.addEventListener("input", apply);
+  categoryInputs.forEach((input) => input.addEventListener("change", apply));
+  apply();
+})();
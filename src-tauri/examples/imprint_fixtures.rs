@@ -0,0 +1,124 @@
+//! Draw the synthetic information bar fixtures used by the imprint tests
+//!
+//! Each fixture is a noisy stand-in for a scene with a bar drawn along its top or bottom edge in
+//! one of the styles trail cameras print, not a photo taken by a camera. The text is drawn with
+//! DejaVu Sans, so the fonts must be installed, e.g. from the `fonts-dejavu-core` package.
+//!
+//! ```sh
+//! cargo run --example imprint_fixtures
+//! ```
+//!
+
+use ab_glyph::{FontRef, PxScale};
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_filled_rect_mut, draw_text_mut};
+use imageproc::rect::Rect;
+use std::path::Path;
+
+const SANS: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+const CONDENSED_BOLD: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSansCondensed-Bold.ttf";
+
+const WIDTH: u32 = 480;
+const HEIGHT: u32 = 270;
+
+/// A scene of smooth bands with noise, so its edge rows are never plain like a bar
+fn scene(seed: u32) -> RgbImage {
+    let mut state = seed;
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        let noise = (state >> 24) as i32 % 24 - 12;
+        let base = 90 + ((x as f32 / 23.0).sin() * 30.0 + (y as f32 / 17.0).cos() * 25.0) as i32;
+        let value = |offset: i32| (base + offset + noise).clamp(0, 255) as u8;
+        Rgb([value(-10), value(15), value(-20)])
+    })
+}
+
+fn save(image: &RgbImage, dir: &Path, name: &str) {
+    let mut file = std::fs::File::create(dir.join(name)).unwrap();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut file, 85)
+        .encode_image(image)
+        .unwrap();
+}
+
+fn main() {
+    let sans = std::fs::read(SANS).unwrap();
+    let sans = FontRef::try_from_slice(&sans).unwrap();
+    let condensed_bold = std::fs::read(CONDENSED_BOLD).unwrap();
+    let condensed_bold = FontRef::try_from_slice(&condensed_bold).unwrap();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/imprint");
+
+    // Black bar at the bottom with a moon icon, Celsius, dates month first with dashes
+    let mut image = scene(1);
+    draw_filled_rect_mut(
+        &mut image,
+        Rect::at(0, 246).of_size(WIDTH, 24),
+        Rgb([0, 0, 0]),
+    );
+    draw_filled_circle_mut(&mut image, (14, 258), 7, Rgb([230, 230, 230]));
+    draw_text_mut(
+        &mut image,
+        Rgb([255, 255, 255]),
+        34,
+        250,
+        PxScale::from(17.0),
+        &sans,
+        "23°C      06-01-2023  21:14:05",
+    );
+    save(&image, &dir, "month_first_dashes.jpg");
+
+    // Dark grey bar in a condensed bold font, Fahrenheit, a 12 hour clock
+    let mut image = scene(2);
+    draw_filled_rect_mut(
+        &mut image,
+        Rect::at(0, 244).of_size(WIDTH, 26),
+        Rgb([40, 40, 40]),
+    );
+    draw_text_mut(
+        &mut image,
+        Rgb([240, 240, 240]),
+        8,
+        249,
+        PxScale::from(18.0),
+        &condensed_bold,
+        "73°F   06/01/2023   9:14 PM",
+    );
+    save(&image, &dir, "month_first_12_hour.jpg");
+
+    // Black bar with one character per 10 pixel cell, AM attached to the time
+    let mut image = scene(3);
+    draw_filled_rect_mut(
+        &mut image,
+        Rect::at(0, 248).of_size(WIDTH, 22),
+        Rgb([0, 0, 0]),
+    );
+    for (i, c) in "11/30/2023 06:02:45AM   25°F".chars().enumerate() {
+        draw_text_mut(
+            &mut image,
+            Rgb([255, 255, 255]),
+            10 + 10 * i as i32,
+            251,
+            PxScale::from(16.0),
+            &sans,
+            &c.to_string(),
+        );
+    }
+    save(&image, &dir, "month_first_fixed_width.jpg");
+
+    // Light bar at the top with dark text, dates day first with dots
+    let mut image = scene(4);
+    draw_filled_rect_mut(
+        &mut image,
+        Rect::at(0, 0).of_size(WIDTH, 24),
+        Rgb([225, 225, 225]),
+    );
+    draw_text_mut(
+        &mut image,
+        Rgb([20, 20, 20]),
+        12,
+        3,
+        PxScale::from(17.0),
+        &sans,
+        "5°C  29.02.2024 13:45:10",
+    );
+    save(&image, &dir, "day_first_top.jpg");
+}
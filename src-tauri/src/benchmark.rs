@@ -0,0 +1,314 @@
+//! Benchmark module
+//!
+//! Measures the throughput of a model configuration, and compares a variant of the model, e.g.
+//! FP16 or INT8-quantized, against the reference model on a set of images, reporting how much
+//! faster it runs and how closely its detections agree.
+//!
+
+use crate::error::{Error, Result};
+use crate::structures::CamTrapDetection;
+use crate::yolov5::model::{BBox, StageTimings};
+use crate::Detector;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The overlap above which a candidate detection of the same class matches a reference detection
+pub const MATCH_IOU: f32 = 0.5;
+
+/// How a candidate model compares to the reference model
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BenchmarkReport {
+    pub images: usize,
+
+    /// Total inference time, excluding decoding the images
+    pub reference_time: Duration,
+    pub candidate_time: Duration,
+
+    pub reference_detections: usize,
+    pub candidate_detections: usize,
+
+    /// Reference detections with a matching candidate detection
+    pub matched: usize,
+
+    /// Mean overlap of the matched detections
+    pub mean_iou: f32,
+
+    /// Mean absolute difference in confidence of the matched detections
+    pub mean_confidence_delta: f32,
+}
+
+impl BenchmarkReport {
+    /// How many times faster the candidate is than the reference
+    pub fn speedup(&self) -> f64 {
+        self.reference_time.as_secs_f64() / self.candidate_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The share of reference detections the candidate also finds
+    pub fn recall(&self) -> f32 {
+        ratio(self.matched, self.reference_detections)
+    }
+
+    /// The share of candidate detections the reference also finds
+    pub fn precision(&self) -> f32 {
+        ratio(self.matched, self.candidate_detections)
+    }
+}
+
+fn ratio(matched: usize, total: usize) -> f32 {
+    if total == 0 {
+        1.0
+    } else {
+        matched as f32 / total as f32
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_image = |time: Duration| time.as_secs_f64() * 1000.0 / self.images.max(1) as f64;
+
+        writeln!(f, "Images: {}", self.images)?;
+        writeln!(
+            f,
+            "Reference: {:.1} ms/image, {} detections",
+            per_image(self.reference_time),
+            self.reference_detections
+        )?;
+        writeln!(
+            f,
+            "Candidate: {:.1} ms/image, {} detections",
+            per_image(self.candidate_time),
+            self.candidate_detections
+        )?;
+        writeln!(f, "Speedup: {:.2}x", self.speedup())?;
+        writeln!(
+            f,
+            "Agreement: {:.1}% recall, {:.1}% precision",
+            self.recall() * 100.0,
+            self.precision() * 100.0
+        )?;
+        write!(
+            f,
+            "Matched detections: {:.3} mean IoU, {:.3} mean confidence difference",
+            self.mean_iou, self.mean_confidence_delta
+        )
+    }
+}
+
+/// How fast a model configuration processes images
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ThroughputReport {
+    pub images: usize,
+
+    /// Total time including decoding, as when processing a folder
+    pub total: Duration,
+
+    pub decode: Duration,
+    pub model: StageTimings,
+}
+
+impl ThroughputReport {
+    pub fn images_per_sec(&self) -> f64 {
+        self.images as f64 / self.total.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for ThroughputReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_image = |time: Duration| time.as_secs_f64() * 1000.0 / self.images.max(1) as f64;
+
+        writeln!(f, "Images: {}", self.images)?;
+        writeln!(f, "Throughput: {:.2} images/s", self.images_per_sec())?;
+        writeln!(f, "Decode: {:.1} ms/image", per_image(self.decode))?;
+        writeln!(
+            f,
+            "Preprocess: {:.1} ms/image",
+            per_image(self.model.preprocess)
+        )?;
+        writeln!(
+            f,
+            "Inference: {:.1} ms/image",
+            per_image(self.model.inference)
+        )?;
+        write!(
+            f,
+            "Postprocess: {:.1} ms/image",
+            per_image(self.model.postprocess)
+        )
+    }
+}
+
+/// Run the detector on every image, timing each stage
+///
+/// The model is run once before timing starts so session warm-up is not counted.
+pub fn throughput(detector: &mut Detector, images: &[PathBuf]) -> Result<ThroughputReport> {
+    let first = images
+        .first()
+        .ok_or_else(|| Error::InvalidInput(String::from("No images to benchmark")))?;
+    detector.detect(&image::open(first)?)?;
+
+    let mut report = ThroughputReport {
+        images: images.len(),
+        ..Default::default()
+    };
+    let start = Instant::now();
+
+    for path in images {
+        let decode_start = Instant::now();
+        let image = image::open(path)?;
+        report.decode += decode_start.elapsed();
+
+        detector.detect(&image)?;
+        let timings = detector.model().timings();
+        report.model.preprocess += timings.preprocess;
+        report.model.inference += timings.inference;
+        report.model.postprocess += timings.postprocess;
+    }
+
+    report.total = start.elapsed();
+    Ok(report)
+}
+
+fn bbox(detection: &CamTrapDetection) -> BBox {
+    BBox {
+        x: detection.x,
+        y: detection.y,
+        w: detection.width,
+        h: detection.height,
+    }
+}
+
+/// Match candidate detections to reference detections of the same class, most confident first
+///
+/// Returns the reference index, candidate index and overlap of each match.
+pub fn match_detections(
+    reference: &[CamTrapDetection],
+    candidate: &[CamTrapDetection],
+) -> Vec<(usize, usize, f32)> {
+    let mut order: Vec<usize> = (0..reference.len()).collect();
+    order.sort_by(|a, b| {
+        reference[*b]
+            .confidence
+            .total_cmp(&reference[*a].confidence)
+    });
+
+    let mut taken = vec![false; candidate.len()];
+    let mut matches = vec![];
+    for r in order {
+        let best = candidate
+            .iter()
+            .enumerate()
+            .filter(|(c, detection)| {
+                !taken[*c] && detection.class_index == reference[r].class_index
+            })
+            .map(|(c, detection)| (c, bbox(&reference[r]).iou(&bbox(detection))))
+            .filter(|(_, iou)| *iou >= MATCH_IOU)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((c, iou)) = best {
+            taken[c] = true;
+            matches.push((r, c, iou));
+        }
+    }
+    matches
+}
+
+/// Run both models on every image and compare their speed and detections
+///
+/// Each model is run once before timing starts so session warm-up is not counted.
+pub fn benchmark(
+    reference: &mut Detector,
+    candidate: &mut Detector,
+    images: &[PathBuf],
+) -> Result<BenchmarkReport> {
+    let first = images
+        .first()
+        .ok_or_else(|| Error::InvalidInput(String::from("No images to benchmark")))?;
+    let warm_up = image::open(first)?;
+    reference.detect(&warm_up)?;
+    candidate.detect(&warm_up)?;
+
+    let mut report = BenchmarkReport {
+        images: images.len(),
+        ..Default::default()
+    };
+    let mut iou_sum = 0.0;
+    let mut confidence_delta_sum = 0.0;
+
+    for path in images {
+        let image = image::open(path)?;
+
+        let start = Instant::now();
+        let expected = reference.detect(&image)?;
+        report.reference_time += start.elapsed();
+
+        let start = Instant::now();
+        let actual = candidate.detect(&image)?;
+        report.candidate_time += start.elapsed();
+
+        report.reference_detections += expected.len();
+        report.candidate_detections += actual.len();
+        for (r, c, iou) in match_detections(&expected, &actual) {
+            report.matched += 1;
+            iou_sum += iou;
+            confidence_delta_sum += (expected[r].confidence - actual[c].confidence).abs();
+        }
+    }
+
+    if report.matched > 0 {
+        report.mean_iou = iou_sum / report.matched as f32;
+        report.mean_confidence_delta = confidence_delta_sum / report.matched as f32;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{DetectionSource, ReviewStatus};
+
+    fn detection(x: f32, class_index: u32, confidence: f32) -> CamTrapDetection {
+        CamTrapDetection {
+            x,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+            class_index,
+            confidence,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        }
+    }
+
+    #[test]
+    fn test_match_detections() {
+        let reference = vec![
+            detection(0.0, 0, 0.9),
+            detection(50.0, 1, 0.8),
+            detection(100.0, 0, 0.3),
+        ];
+        let candidate = vec![
+            // Same place as the first, but a different class
+            detection(1.0, 1, 0.9),
+            detection(1.0, 0, 0.85),
+            detection(51.0, 1, 0.7),
+            detection(200.0, 0, 0.5),
+        ];
+
+        let matches = match_detections(&reference, &candidate);
+        let pairs: Vec<(usize, usize)> = matches.iter().map(|(r, c, _)| (*r, *c)).collect();
+        assert_eq!(pairs, vec![(0, 1), (1, 2)]);
+        assert!(matches.iter().all(|(_, _, iou)| *iou > 0.8));
+
+        let report = BenchmarkReport {
+            reference_detections: reference.len(),
+            candidate_detections: candidate.len(),
+            matched: matches.len(),
+            ..Default::default()
+        };
+        assert!((report.recall() - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(report.precision(), 0.5);
+    }
+}
@@ -0,0 +1,490 @@
+//! Command line interface
+//!
+//! Runs the detector without the desktop app, e.g. on a field station server.
+//!
+
+use camtrap_detector::benchmark::{benchmark, throughput};
+use camtrap_detector::evaluation::{evaluate, read_annotations};
+use camtrap_detector::review::LabelSelection;
+use camtrap_detector::server::{DetectionServer, ServerOptions};
+use camtrap_detector::watch::{watch, CategoryActions, FileAction, WatchOptions};
+use camtrap_detector::yolov5::helpers::enumerate_images;
+use camtrap_detector::yolov5::{ExecutionOptions, InferenceOptions, MergeMethod};
+use camtrap_detector::{Detector, Error};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+
+const USAGE: &str = "Usage: camtrap <command> --model <model.onnx> [options]
+
+Commands:
+  watch <folder>   Watch a folder, running detection on new images as they arrive
+  serve            Run a local HTTP API for detection
+  info             Load the model and report how it is being run
+  evaluate <ground-truth.json> <results.json>
+                   Measure the accuracy of results against COCO Camera Traps, MegaDetector or
+                   reviewed JSON export annotations, no model is needed
+  benchmark <folder> [--candidate <model.onnx>]
+                   Report the throughput of --model on the images in a folder, or compare the
+                   speed and detections of another model, e.g. an FP16 or INT8 variant, against it
+
+The watch and serve commands run until stopped with Ctrl+C.
+
+Model options, for every command:
+  --model <file>            The MegaDetector ONNX model
+  --provider <provider>     auto, cpu, coreml, tensorrt, cuda or directml [default: auto]
+  --intra-threads <n>       The number of threads used within each operation
+  --inter-threads <n>       The number of threads used to run operations in parallel
+  --tile-size <pixels>      Also detect in overlapping tiles of this size, for small animals
+  --tile-overlap <value>    The fraction each tile overlaps its neighbour [default: 0.2]
+  --tta                     Test-time augmentation, also running flipped and rescaled images
+  --tta-scales <list>       Comma separated input scales for --tta [default: 1,0.83,1.17]
+  --tta-merge <method>      How passes are combined for --tta, wbf or nms [default: wbf]
+
+Watch options:
+  --output <file>        A JSON Lines file to append each result to
+  --confidence <value>   The minimum confidence of detections to keep [default: 0.2]
+  --recursive            Also watch subfolders
+  --existing             Also process the images already in the folder
+  --settle <seconds>     How long a file must be unchanged before it is processed [default: 5]
+  --poll <seconds>       How often to look for new files [default: 2]
+  --empty <action>       What to do with empty images, `move:<dir>` or `copy:<dir>`
+  --animal <action>      What to do with images of animals
+  --human <action>       What to do with images of humans
+  --vehicle <action>     What to do with images of vehicles
+                         Relative directories are inside the watched folder
+
+Serve options:
+  --address <host:port>    The address to listen on [default: 127.0.0.1:8080]
+  --max-concurrent <n>     The maximum number of requests handled at once [default: 4]
+  --confidence <value>     The default minimum confidence of detections [default: 0.2]
+
+Evaluate options:
+  --confidence <value>   The confidence threshold to report precision and recall at [default: 0.2]
+  --json                 Print the report as JSON
+
+Benchmark options:
+  --candidate <file>     A model variant to compare, run with the same model options
+  --confidence <value>   The minimum confidence of detections to compare [default: 0.2]
+  --recursive            Also use the images in subfolders
+
+The server's /batch endpoint reads any path it is given, so only listen on a trusted network.";
+
+/// Parse an action of the form `move:<dir>` or `copy:<dir>`
+fn parse_action(value: &str) -> Result<FileAction, String> {
+    match value.split_once(':') {
+        Some(("move", dir)) if !dir.is_empty() => Ok(FileAction::Move(PathBuf::from(dir))),
+        Some(("copy", dir)) if !dir.is_empty() => Ok(FileAction::Copy(PathBuf::from(dir))),
+        _ if value == "keep" => Ok(FileAction::Keep),
+        _ => Err(format!(
+            "Invalid action `{}`, expected `keep`, `move:<dir>` or `copy:<dir>`",
+            value
+        )),
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value `{}` for {}", value, flag))
+}
+
+fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("Invalid value `{}` for {}", value, flag))
+}
+
+/// The model to load and how to run it, shared by every command
+#[derive(Debug, Default)]
+struct ModelArgs {
+    path: Option<PathBuf>,
+    execution: ExecutionOptions,
+    inference: InferenceOptions,
+}
+
+impl ModelArgs {
+    /// Parse a model option, returning false if `arg` is not one
+    fn parse<'a>(
+        &mut self,
+        arg: &str,
+        mut value: impl FnMut() -> Result<&'a str, String>,
+    ) -> Result<bool, String> {
+        match arg {
+            "--model" => self.path = Some(PathBuf::from(value()?)),
+            "--provider" => {
+                self.execution.provider = value()?.parse().map_err(|err: Error| err.to_string())?
+            }
+            "--intra-threads" => self.execution.intra_threads = Some(parse_count(arg, value()?)?),
+            "--inter-threads" => self.execution.inter_threads = Some(parse_count(arg, value()?)?),
+            "--tile-size" => {
+                let size = parse_count(arg, value()?)? as u32;
+                self.inference
+                    .tiling
+                    .get_or_insert_with(Default::default)
+                    .size = size;
+            }
+            "--tile-overlap" => {
+                let overlap = parse_number(arg, value()?)?;
+                if !(0.0..0.9).contains(&overlap) {
+                    return Err(format!("Invalid value `{}` for {}", overlap, arg));
+                }
+                self.inference
+                    .tiling
+                    .get_or_insert_with(Default::default)
+                    .overlap = overlap;
+            }
+            "--tta" => {
+                self.inference.tta.get_or_insert_with(Default::default);
+            }
+            "--tta-scales" => {
+                let scales = value()?
+                    .split(',')
+                    .map(|scale| parse_number(arg, scale.trim()))
+                    .collect::<Result<Vec<f32>, String>>()?;
+                if scales.is_empty() || scales.iter().any(|scale| *scale <= 0.0) {
+                    return Err(format!("Invalid value for {}", arg));
+                }
+                self.inference
+                    .tta
+                    .get_or_insert_with(Default::default)
+                    .scales = scales;
+            }
+            "--tta-merge" => {
+                let merge = match value()? {
+                    "wbf" => MergeMethod::WeightedBoxFusion,
+                    "nms" => MergeMethod::Nms,
+                    other => return Err(format!("Invalid value `{}` for {}", other, arg)),
+                };
+                self.inference
+                    .tta
+                    .get_or_insert_with(Default::default)
+                    .merge = merge;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn validate(self) -> Result<Self, String> {
+        match self.path {
+            Some(_) => Ok(self),
+            None => Err(String::from("Missing --model")),
+        }
+    }
+
+    fn build(self, confidence_threshold: f32) -> Result<Detector, Box<dyn std::error::Error>> {
+        Ok(Detector::builder()
+            .model_path(self.path.ok_or("Missing --model")?)
+            .confidence_threshold(confidence_threshold)
+            .execution_options(self.execution)
+            .inference_options(self.inference)
+            .build()?)
+    }
+}
+
+/// Parse the arguments of the watch command, returning the options and the model to load
+fn parse_watch(args: &[String]) -> Result<(WatchOptions, ModelArgs), String> {
+    let mut root = None;
+    let mut model = ModelArgs::default();
+    let mut options = WatchOptions {
+        root: PathBuf::new(),
+        recursive: false,
+        confidence_threshold: 0.2,
+        settle_secs: 5.0,
+        poll_secs: 2.0,
+        include_existing: false,
+        output: None,
+        actions: CategoryActions::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if model.parse(arg, &mut value)? {
+            continue;
+        }
+
+        match arg.as_str() {
+            "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--confidence" => options.confidence_threshold = parse_number(arg, value()?)?,
+            "--recursive" => options.recursive = true,
+            "--existing" => options.include_existing = true,
+            "--settle" => options.settle_secs = parse_number(arg, value()?)?,
+            "--poll" => options.poll_secs = parse_number(arg, value()?)?,
+            "--empty" => options.actions.empty = parse_action(value()?)?,
+            "--animal" => options.actions.animal = parse_action(value()?)?,
+            "--human" => options.actions.human = parse_action(value()?)?,
+            "--vehicle" => options.actions.vehicle = parse_action(value()?)?,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            folder if root.is_none() => root = Some(PathBuf::from(folder)),
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+
+    options.root = root.ok_or("Missing folder to watch")?;
+    Ok((options, model.validate()?))
+}
+
+fn run_watch(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (options, model) = parse_watch(args)?;
+    if !options.root.is_dir() {
+        return Err(format!("{} is not a folder", options.root.display()).into());
+    }
+
+    let mut detector = model.build(options.confidence_threshold)?;
+
+    eprintln!("Watching {}", options.root.display());
+
+    // Runs until the process is stopped, results are appended as they are made so none are lost
+    let stop = AtomicBool::new(false);
+    watch(
+        &options,
+        &mut detector,
+        &stop,
+        |image| match &image.error {
+            Some(error) => eprintln!("{}: {}", image.file, error),
+            None => println!("{}: {} detections", image.file, image.detections.len()),
+        },
+        |error| eprintln!("{}", error),
+    );
+
+    Ok(())
+}
+
+/// Parse the arguments of the serve command, returning the options and the model to load
+fn parse_serve(args: &[String]) -> Result<(ServerOptions, ModelArgs), String> {
+    let mut model = ModelArgs::default();
+    let mut options = ServerOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if model.parse(arg, &mut value)? {
+            continue;
+        }
+
+        match arg.as_str() {
+            "--address" => options.address = value()?.to_string(),
+            "--max-concurrent" => options.max_concurrent = parse_count(arg, value()?)?,
+            "--confidence" => options.confidence_threshold = parse_number(arg, value()?)?,
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+
+    Ok((options, model.validate()?))
+}
+
+fn run_serve(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (options, model) = parse_serve(args)?;
+
+    let detector = model.build(options.confidence_threshold)?;
+    eprintln!("Running on {}", detector.system_info().provider);
+    let server = DetectionServer::bind(options, Mutex::new(detector))?;
+
+    if let Some(addr) = server.local_addr() {
+        eprintln!("Listening on http://{}", addr);
+    }
+
+    server.run(&AtomicBool::new(false));
+
+    Ok(())
+}
+
+fn run_info(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut model = ModelArgs::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if !model.parse(arg, &mut value)? {
+            return Err(format!("Unknown option {}", arg).into());
+        }
+    }
+
+    let detector = model.validate()?.build(0.2)?;
+    println!("{}", detector.system_info());
+
+    Ok(())
+}
+
+fn run_evaluate(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files = vec![];
+    let mut confidence_threshold = 0.2;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--confidence" => {
+                let value = args.next().ok_or("Missing value for --confidence")?;
+                confidence_threshold = parse_number(arg, value)?;
+            }
+            "--json" => json = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag).into()),
+            file if files.len() < 2 => files.push(PathBuf::from(file)),
+            extra => return Err(format!("Unexpected argument {}", extra).into()),
+        }
+    }
+    let [ground_truth, results] = <[PathBuf; 2]>::try_from(files)
+        .map_err(|_| "Expected a ground truth file and a results file")?;
+
+    // Only reviewed labels of an export are trusted as ground truth
+    let ground_truth =
+        read_annotations(std::fs::File::open(ground_truth)?, LabelSelection::Reviewed)?;
+    let results = read_annotations(std::fs::File::open(results)?, LabelSelection::Model)?;
+    let report = evaluate(&ground_truth, &results, confidence_threshold);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+
+    Ok(())
+}
+
+fn run_benchmark(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut model = ModelArgs::default();
+    let mut folder = None;
+    let mut candidate = None;
+    let mut confidence_threshold = 0.2;
+    let mut recursive = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if model.parse(arg, &mut value)? {
+            continue;
+        }
+
+        match arg.as_str() {
+            "--candidate" => candidate = Some(PathBuf::from(value()?)),
+            "--confidence" => confidence_threshold = parse_number(arg, value()?)?,
+            "--recursive" => recursive = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag).into()),
+            path if folder.is_none() => folder = Some(PathBuf::from(path)),
+            extra => return Err(format!("Unexpected argument {}", extra).into()),
+        }
+    }
+
+    let folder = folder.ok_or("Missing folder of images")?;
+    let images = enumerate_images(folder, recursive);
+    let candidate = candidate.map(|path| ModelArgs {
+        path: Some(path),
+        execution: model.execution.clone(),
+        inference: model.inference.clone(),
+    });
+    let mut reference = model.validate()?.build(confidence_threshold)?;
+
+    match candidate {
+        Some(candidate) => {
+            let mut candidate = candidate.build(confidence_threshold)?;
+            println!("{}", benchmark(&mut reference, &mut candidate, &images)?);
+        }
+        None => {
+            println!("Execution provider: {}", reference.system_info().provider);
+            println!("{}", throughput(&mut reference, &images)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("watch") => run_watch(&args[1..]),
+        Some("serve") => run_serve(&args[1..]),
+        Some("info") => run_info(&args[1..]),
+        Some("evaluate") => run_evaluate(&args[1..]),
+        Some("benchmark") => run_benchmark(&args[1..]),
+        Some("--help") | Some("-h") | None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("Unknown command {}\n\n{}", command, USAGE).into()),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camtrap_detector::yolov5::{ExecutionProviderKind, TileOptions};
+
+    #[test]
+    fn test_parse_watch() {
+        let args: Vec<String> = [
+            "/data/incoming",
+            "--model",
+            "md.onnx",
+            "--recursive",
+            "--confidence",
+            "0.5",
+            "--empty",
+            "move:/data/empty",
+            "--provider",
+            "cpu",
+            "--intra-threads",
+            "2",
+            "--tile-size",
+            "1024",
+            "--tta-scales",
+            "1,1.25",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        let (options, model) = parse_watch(&args).unwrap();
+        assert_eq!(options.root, PathBuf::from("/data/incoming"));
+        assert_eq!(model.path, Some(PathBuf::from("md.onnx")));
+        assert_eq!(model.execution.provider, ExecutionProviderKind::Cpu);
+        assert_eq!(model.execution.intra_threads, Some(2));
+        assert_eq!(
+            model.inference.tiling,
+            Some(TileOptions {
+                size: 1024,
+                overlap: 0.2
+            })
+        );
+        let tta = model.inference.tta.unwrap();
+        assert_eq!(tta.scales, vec![1.0, 1.25]);
+        assert!(tta.flip);
+        assert!(options.recursive);
+        assert_eq!(options.confidence_threshold, 0.5);
+        assert_eq!(
+            options.actions.empty,
+            FileAction::Move(PathBuf::from("/data/empty"))
+        );
+        assert_eq!(options.actions.animal, FileAction::Keep);
+
+        assert!(parse_watch(&args[..1]).is_err());
+        assert!(parse_watch(&[args[0].clone(), "--provider".into(), "gpu".into()]).is_err());
+        assert!(parse_action("delete:/data").is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Detector module
+//!
+//! The main entry point for running MegaDetector from Rust: load a model once with
+//! [`Detector::builder`], then run it on images, files or whole folders.
+//!
+
+use crate::error::{Error, Result};
+use crate::imprint::{fill_from_imprint, ImprintLayout};
+use crate::masks::MaskOptions;
+use crate::megadetector::MODEL_INPUT_SIZE;
+use crate::metadata::ImageMetadata;
+use crate::structures::{
+    unix_time_now, CamTrapDetection, CamTrapImageDetections, DetectionSource, FileStamp,
+    ReviewStatus, RunInfo,
+};
+use crate::yolov5::helpers::enumerate_images;
+use crate::yolov5::{ExecutionOptions, InferenceOptions, SystemInfo, YoloModel};
+use image::{DynamicImage, GenericImageView};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Builds a [`Detector`]
+#[derive(Debug, Clone)]
+pub struct DetectorBuilder {
+    model_path: Option<PathBuf>,
+    input_size: (usize, usize),
+    confidence_threshold: f32,
+    nms_threshold: f32,
+    execution: ExecutionOptions,
+    inference: InferenceOptions,
+    imprint: Option<ImprintLayout>,
+    masks: MaskOptions,
+}
+
+impl Default for DetectorBuilder {
+    fn default() -> Self {
+        Self {
+            model_path: None,
+            input_size: MODEL_INPUT_SIZE,
+            confidence_threshold: 0.2,
+            nms_threshold: 0.45,
+            execution: ExecutionOptions::default(),
+            inference: InferenceOptions::default(),
+            imprint: None,
+            masks: MaskOptions::default(),
+        }
+    }
+}
+
+impl DetectorBuilder {
+    /// The ONNX model to load, required
+    pub fn model_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.model_path = Some(path.into());
+        self
+    }
+
+    /// The input size of the model, defaults to that of MegaDetector v1000
+    pub fn input_size(mut self, width: usize, height: usize) -> Self {
+        self.input_size = (width, height);
+        self
+    }
+
+    /// The minimum confidence of detections to keep, defaults to 0.2
+    pub fn confidence_threshold(mut self, threshold: f32) -> Self {
+        self.confidence_threshold = threshold;
+        self
+    }
+
+    /// The overlap above which the less confident of two detections is removed, defaults to 0.45
+    pub fn nms_threshold(mut self, threshold: f32) -> Self {
+        self.nms_threshold = threshold;
+        self
+    }
+
+    /// The execution provider and threads, defaults to the first available accelerator
+    pub fn execution_options(mut self, options: ExecutionOptions) -> Self {
+        self.execution = options;
+        self
+    }
+
+    /// Slower inference modes for better recall, defaults to none. Building fails if tiles are
+    /// smaller than the minimum tile size.
+    pub fn inference_options(mut self, options: InferenceOptions) -> Self {
+        self.inference = options;
+        self
+    }
+
+    /// Read the timestamp and temperature printed in the information bar of images in the given
+    /// layout when their EXIF data has none, defaults to off
+    pub fn imprint(mut self, layout: ImprintLayout) -> Self {
+        self.imprint = Some(layout);
+        self
+    }
+
+    /// Areas of the frame in which detections are dropped, e.g. the information bar, defaults to
+    /// none
+    pub fn masks(mut self, options: MaskOptions) -> Self {
+        self.masks = options;
+        self
+    }
+
+    /// Load the model
+    pub fn build(self) -> Result<Detector> {
+        let model_path = self
+            .model_path
+            .ok_or_else(|| Error::InvalidInput(String::from("No model path given")))?;
+        let mut model = YoloModel::new_with_options(
+            &model_path.to_string_lossy(),
+            self.input_size,
+            &self.execution,
+        )?;
+        model.set_inference_options(self.inference)?;
+
+        Ok(Detector {
+            model,
+            model_file: model_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            model_sha256: sha256_file(&model_path)?,
+            input_size: self.input_size,
+            confidence_threshold: self.confidence_threshold,
+            nms_threshold: self.nms_threshold,
+            imprint: self.imprint,
+            masks: self.masks,
+        })
+    }
+}
+
+/// A loaded model, ready to detect animals, humans and vehicles
+pub struct Detector {
+    model: YoloModel,
+    model_file: String,
+    model_sha256: String,
+    input_size: (usize, usize),
+    confidence_threshold: f32,
+    nms_threshold: f32,
+    imprint: Option<ImprintLayout>,
+    masks: MaskOptions,
+}
+
+impl Detector {
+    pub fn builder() -> DetectorBuilder {
+        DetectorBuilder::default()
+    }
+
+    /// The minimum confidence of detections kept
+    pub fn confidence_threshold(&self) -> f32 {
+        self.confidence_threshold
+    }
+
+    pub fn set_confidence_threshold(&mut self, threshold: f32) {
+        self.confidence_threshold = threshold;
+    }
+
+    /// Slower inference modes used for better recall
+    pub fn inference_options(&self) -> &InferenceOptions {
+        self.model.inference_options()
+    }
+
+    /// Fails if tiles are smaller than the minimum tile size
+    pub fn set_inference_options(&mut self, options: InferenceOptions) -> Result<()> {
+        self.model.set_inference_options(options)
+    }
+
+    /// The layout of the information bar read when EXIF data has no timestamp or temperature, if
+    /// any
+    pub fn imprint(&self) -> Option<ImprintLayout> {
+        self.imprint
+    }
+
+    pub fn set_imprint(&mut self, layout: Option<ImprintLayout>) {
+        self.imprint = layout;
+    }
+
+    /// The areas of the frame in which detections are dropped
+    pub fn masks(&self) -> &MaskOptions {
+        &self.masks
+    }
+
+    pub fn set_masks(&mut self, options: MaskOptions) {
+        self.masks = options;
+    }
+
+    /// The underlying model, e.g. to check which accelerators are available
+    pub fn model(&self) -> &YoloModel {
+        &self.model
+    }
+
+    /// Which execution provider is running the model, and with how many threads
+    pub fn system_info(&self) -> SystemInfo {
+        self.model.system_info()
+    }
+
+    /// How results made now are produced, to record in exports
+    ///
+    /// The run starts now; call [`RunInfo::finish`] once the images are processed.
+    pub fn run_info(&self) -> RunInfo {
+        RunInfo {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            model_file: self.model_file.clone(),
+            model_sha256: self.model_sha256.clone(),
+            input_size: self.input_size,
+            confidence_threshold: self.confidence_threshold,
+            nms_threshold: self.nms_threshold,
+            execution_provider: self.model.provider().to_string(),
+            inference: self.model.inference_options().clone(),
+            started: unix_time_now(),
+            finished: None,
+            os: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            images: 0,
+        }
+    }
+
+    /// Run detection on a decoded image, returning boxes in pixels
+    pub fn detect(&mut self, image: &DynamicImage) -> Result<Vec<CamTrapDetection>> {
+        Ok(self
+            .model
+            .detect(
+                image,
+                Some(self.confidence_threshold),
+                Some(self.nms_threshold),
+            )?
+            .into_iter()
+            .map(|d| CamTrapDetection {
+                class_index: d.class as u32,
+                confidence: d.score,
+                x: d.bbox.x,
+                y: d.bbox.y,
+                width: d.bbox.w,
+                height: d.bbox.h,
+                source: DetectionSource::Model,
+                review: ReviewStatus::Unreviewed,
+            })
+            .collect())
+    }
+
+    /// Run detection on an image file, dropping detections in its exclusion masks
+    pub fn detect_path(&mut self, path: impl AsRef<Path>) -> Result<CamTrapImageDetections> {
+        let mut result = detect_path_with(path.as_ref(), self.imprint, |image| self.detect(image))?;
+        self.masks.apply(&mut result);
+        Ok(result)
+    }
+
+    /// Run detection on every image in a folder, and its subfolders if `recursive`
+    ///
+    /// Images that cannot be processed are included with their error rather than failing the
+    /// whole folder.
+    pub fn detect_dir(
+        &mut self,
+        dir: impl AsRef<Path>,
+        recursive: bool,
+    ) -> Result<Vec<CamTrapImageDetections>> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Err(Error::InvalidInput(format!(
+                "{} is not a folder",
+                dir.display()
+            )));
+        }
+
+        Ok(enumerate_images(dir.to_path_buf(), recursive)
+            .into_iter()
+            .map(|file| {
+                self.detect_path(&file)
+                    .unwrap_or_else(|err| CamTrapImageDetections::failed(&file, err))
+            })
+            .collect())
+    }
+}
+
+/// The SHA-256 of a file as lowercase hex
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Open an image file and run the given detection on it, reading its information bar for
+/// metadata missing from its EXIF data if a layout is given
+pub(crate) fn detect_path_with(
+    path: &Path,
+    imprint: Option<ImprintLayout>,
+    detect: impl FnOnce(&DynamicImage) -> Result<Vec<CamTrapDetection>>,
+) -> Result<CamTrapImageDetections> {
+    let stamp = FileStamp::read(path).ok();
+    let image_bytes = std::fs::read(path)?;
+    let format = image::guess_format(&image_bytes)?;
+    let image = image::load_from_memory_with_format(&image_bytes, format)?;
+    let (width, height) = image.dimensions();
+
+    // Missing or malformed EXIF data should not stop the image from being processed
+    let mut metadata = ImageMetadata::from_image_bytes(image_bytes, format)
+        .ok()
+        .flatten();
+    if let Some(layout) = imprint {
+        fill_from_imprint(&mut metadata, &image, layout);
+    }
+
+    Ok(CamTrapImageDetections {
+        file: path.to_string_lossy().to_string(),
+        error: None,
+        image_width: Some(width),
+        image_height: Some(height),
+        detections: detect(&image)?,
+        review: ReviewStatus::Unreviewed,
+        stamp,
+        metadata,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_without_model() {
+        assert!(matches!(
+            Detector::builder().confidence_threshold(0.5).build(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let path = std::env::temp_dir().join("camtrap-sha256.txt");
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            sha256_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+//! Error module
+//!
+//! The error type returned throughout the library.
+//!
+
+/// An error from detecting, reviewing or exporting
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A file could not be read or written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// An image could not be decoded or encoded
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+
+    /// The EXIF or other metadata of an image is malformed
+    #[error("Invalid image metadata: {0}")]
+    Metadata(String),
+
+    /// The model could not be loaded or run
+    #[error("Model error: {0}")]
+    Model(String),
+
+    /// Results could not be read or written as JSON
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// Results could not be written as CSV
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    /// Results could not be written as Parquet
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    /// Results could not be written to an SQLite database
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// An argument is invalid, e.g. a detection index out of range
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+impl From<ort::Error> for Error {
+    fn from(err: ort::Error) -> Self {
+        Error::Model(err.to_string())
+    }
+}
+
+impl From<ndarray::ShapeError> for Error {
+    fn from(err: ndarray::ShapeError) -> Self {
+        Error::Model(err.to_string())
+    }
+}
+
+impl From<img_parts::Error> for Error {
+    fn from(err: img_parts::Error) -> Self {
+        Error::Metadata(err.to_string())
+    }
+}
+
+/// A result with the library's error type
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,777 @@
+//! Evaluation module
+//!
+//! Measures the accuracy of a set of results against ground truth annotations, to justify the
+//! choice of model and confidence threshold.
+//!
+//! Ground truth and results can be read from COCO Camera Traps, MegaDetector batch output or this
+//! app's JSON export. When every ground truth annotation has a box, detections are matched to
+//! boxes of the same category at an IoU of 0.5. When some only label the whole image, e.g. COCO
+//! Camera Traps species labels, each category is instead evaluated on whether it is present in
+//! the image.
+//!
+
+use crate::error::{Error, Result};
+use crate::exports::json::CamTrapJSONContainer;
+use crate::megadetector::{model_categories, Category};
+use crate::review::LabelSelection;
+use crate::structures::CamTrapImageDetections;
+use crate::yolov5::model::BBox;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+/// The overlap above which a detection matches a ground truth box of the same category
+pub const MATCH_IOU: f32 = 0.5;
+
+/// The confidence from which MegaDetector batch output detections are taken as ground truth
+///
+/// The format has no record of which detections a person reviewed, so those below the threshold
+/// commonly used with MegaDetector v5 are taken to be false positives left in the file.
+pub const MEGADETECTOR_LABEL_THRESHOLD: f32 = 0.2;
+
+/// A labelled or detected object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub class_index: u32,
+
+    /// The box as a fraction of the image size, `None` when the label applies to the whole image
+    pub bbox: Option<BBox>,
+
+    /// 1.0 for ground truth
+    pub confidence: f32,
+}
+
+/// The annotations of one image
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedImage {
+    /// The image path relative to the dataset, with `/` separators
+    pub file: String,
+
+    /// No annotations means the image is empty
+    pub annotations: Vec<Annotation>,
+}
+
+impl AnnotatedImage {
+    /// Convert the selected detections of an image, `None` if it failed or has no size
+    pub fn from_detections(
+        image: &CamTrapImageDetections,
+        selection: LabelSelection,
+    ) -> Option<Self> {
+        if image.error.is_some() {
+            return None;
+        }
+        let width = image.image_width? as f32;
+        let height = image.image_height? as f32;
+
+        Some(Self {
+            file: normalize_path(&image.file),
+            annotations: selection
+                .select(image)
+                .detections
+                .iter()
+                .map(|detection| Annotation {
+                    class_index: detection.class_index,
+                    bbox: Some(BBox {
+                        x: detection.x / width,
+                        y: detection.y / height,
+                        w: detection.width / width,
+                        h: detection.height / height,
+                    }),
+                    confidence: detection.confidence,
+                })
+                .collect(),
+        })
+    }
+}
+
+fn normalize_path(file: &str) -> String {
+    file.replace('\\', "/").trim_start_matches("./").to_string()
+}
+
+/// Map a category name onto a class index, `None` for empty
+///
+/// Names that are not empty, human or vehicle are taken to be animal species.
+fn class_from_name(name: &str) -> Option<u32> {
+    let category = match name.trim().to_lowercase().as_str() {
+        "empty" | "blank" | "none" => Category::EMPTY,
+        "human" | "humans" | "person" | "people" => Category::HUMAN,
+        "vehicle" | "vehicles" | "car" => Category::VEHICLE,
+        _ => Category::ANIMAL,
+    };
+    category.model_index
+}
+
+#[derive(Deserialize)]
+struct CctImage {
+    id: Value,
+    file_name: String,
+    width: Option<f32>,
+    height: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct CctAnnotation {
+    image_id: Value,
+    category_id: Value,
+    bbox: Option<[f32; 4]>,
+}
+
+#[derive(Deserialize)]
+struct CctCategory {
+    id: Value,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CctDataset {
+    images: Vec<CctImage>,
+    annotations: Vec<CctAnnotation>,
+    categories: Vec<CctCategory>,
+}
+
+fn from_cct(value: Value) -> Result<Vec<AnnotatedImage>> {
+    let dataset: CctDataset = serde_json::from_value(value)?;
+    let classes: HashMap<String, Option<u32>> = dataset
+        .categories
+        .iter()
+        .map(|category| (category.id.to_string(), class_from_name(&category.name)))
+        .collect();
+
+    let mut images: Vec<AnnotatedImage> = dataset
+        .images
+        .iter()
+        .map(|image| AnnotatedImage {
+            file: normalize_path(&image.file_name),
+            annotations: vec![],
+        })
+        .collect();
+    let index: HashMap<String, usize> = dataset
+        .images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| (image.id.to_string(), i))
+        .collect();
+
+    for annotation in dataset.annotations {
+        let i = *index.get(&annotation.image_id.to_string()).ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "Annotation for unknown image {}",
+                annotation.image_id
+            ))
+        })?;
+        let class_index = match classes.get(&annotation.category_id.to_string()) {
+            Some(Some(class_index)) => *class_index,
+            Some(None) => continue,
+            None => {
+                return Err(Error::InvalidInput(format!(
+                    "Annotation with unknown category {}",
+                    annotation.category_id
+                )))
+            }
+        };
+
+        // Boxes are in pixels, so need the image size to be compared
+        let image = &dataset.images[i];
+        let bbox = match (annotation.bbox, image.width, image.height) {
+            (Some([x, y, w, h]), Some(width), Some(height)) => Some(BBox {
+                x: x / width,
+                y: y / height,
+                w: w / width,
+                h: h / height,
+            }),
+            _ => None,
+        };
+
+        images[i].annotations.push(Annotation {
+            class_index,
+            bbox,
+            confidence: 1.0,
+        });
+    }
+
+    Ok(images)
+}
+
+#[derive(Deserialize)]
+struct MegaDetectorDetection {
+    category: String,
+    conf: f32,
+    bbox: [f32; 4],
+}
+
+#[derive(Deserialize)]
+struct MegaDetectorImage {
+    file: String,
+    #[serde(default)]
+    detections: Option<Vec<MegaDetectorDetection>>,
+    #[serde(default)]
+    failure: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MegaDetectorOutput {
+    images: Vec<MegaDetectorImage>,
+    detection_categories: HashMap<String, String>,
+}
+
+fn from_megadetector(value: Value, selection: LabelSelection) -> Result<Vec<AnnotatedImage>> {
+    let output: MegaDetectorOutput = serde_json::from_value(value)?;
+    let labels = selection == LabelSelection::Reviewed;
+
+    Ok(output
+        .images
+        .into_iter()
+        .filter(|image| image.failure.is_none())
+        .map(|image| AnnotatedImage {
+            file: normalize_path(&image.file),
+            annotations: image
+                .detections
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|detection| !labels || detection.conf >= MEGADETECTOR_LABEL_THRESHOLD)
+                .filter_map(|detection| {
+                    let name = output.detection_categories.get(&detection.category)?;
+                    let [x, y, w, h] = detection.bbox;
+                    Some(Annotation {
+                        class_index: class_from_name(name)?,
+                        bbox: Some(BBox { x, y, w, h }),
+                        confidence: if labels { 1.0 } else { detection.conf },
+                    })
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Read annotations from COCO Camera Traps, MegaDetector batch output or a JSON export
+///
+/// `selection` picks the detections of a JSON export, e.g. [`LabelSelection::Reviewed`] for
+/// ground truth and [`LabelSelection::Model`] for results. MegaDetector batch output has no review
+/// record, so as ground truth only its detections from [`MEGADETECTOR_LABEL_THRESHOLD`] are kept.
+pub fn read_annotations(
+    reader: impl Read,
+    selection: LabelSelection,
+) -> Result<Vec<AnnotatedImage>> {
+    let value: Value = serde_json::from_reader(reader)?;
+
+    if value.get("annotations").is_some() {
+        from_cct(value)
+    } else if value.get("detection_categories").is_some() {
+        from_megadetector(value, selection)
+    } else {
+        let container: CamTrapJSONContainer = serde_json::from_value(value)?;
+        Ok(container
+            .into_detections()
+            .iter()
+            .filter_map(|image| AnnotatedImage::from_detections(image, selection))
+            .collect())
+    }
+}
+
+/// Accuracy for one category
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassMetrics {
+    pub category: String,
+
+    /// The number of ground truth objects, or images containing the category
+    pub ground_truth: usize,
+
+    /// The number of detections at or above the confidence threshold
+    pub detections: usize,
+
+    pub precision: f32,
+    pub recall: f32,
+
+    /// The area under the precision-recall curve, `None` without ground truth
+    pub average_precision: Option<f32>,
+}
+
+/// Accuracy over every category at one confidence threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct CurvePoint {
+    pub threshold: f32,
+    pub precision: f32,
+    pub recall: f32,
+
+    /// The share of images correctly found to be empty or not
+    pub empty_accuracy: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvaluationReport {
+    /// The number of ground truth images with results
+    pub images: usize,
+
+    /// The number of ground truth images without results, which are not evaluated
+    pub missing: usize,
+
+    /// Whether detections were matched to boxes, rather than to categories present in the image
+    pub box_level: bool,
+
+    pub confidence_threshold: f32,
+    pub classes: Vec<ClassMetrics>,
+
+    /// The mean average precision over categories with ground truth, at an IoU of 0.5 when box
+    /// level
+    pub map50: Option<f32>,
+
+    /// The share of images correctly found to be empty or not at the confidence threshold
+    pub empty_accuracy: f32,
+
+    /// Precision, recall and empty accuracy from 0.05 to 0.95 confidence
+    pub curve: Vec<CurvePoint>,
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |value: f32| format!("{:.1}%", value * 100.0);
+
+        writeln!(
+            f,
+            "Images: {} evaluated, {} without results",
+            self.images, self.missing
+        )?;
+        writeln!(
+            f,
+            "Matching: {}",
+            if self.box_level {
+                "boxes at IoU 0.5"
+            } else {
+                "categories present in each image"
+            }
+        )?;
+        writeln!(f, "Confidence threshold: {}", self.confidence_threshold)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<10} {:>12} {:>10} {:>10} {:>8} {:>8}",
+            "Category", "Ground truth", "Detections", "Precision", "Recall", "AP"
+        )?;
+        for class in &self.classes {
+            writeln!(
+                f,
+                "{:<10} {:>12} {:>10} {:>10} {:>8} {:>8}",
+                class.category,
+                class.ground_truth,
+                class.detections,
+                percent(class.precision),
+                percent(class.recall),
+                class.average_precision.map_or("-".to_string(), percent)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "mAP@0.5: {}",
+            self.map50.map_or("-".to_string(), percent)
+        )?;
+        writeln!(
+            f,
+            "Empty/non-empty accuracy: {}",
+            percent(self.empty_accuracy)
+        )?;
+        writeln!(f)?;
+        write!(
+            f,
+            "{:>9} {:>10} {:>8} {:>15}",
+            "Threshold", "Precision", "Recall", "Empty accuracy"
+        )?;
+        for point in &self.curve {
+            write!(
+                f,
+                "\n{:>9.2} {:>10} {:>8} {:>15}",
+                point.threshold,
+                percent(point.precision),
+                percent(point.recall),
+                percent(point.empty_accuracy)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        1.0
+    } else {
+        count as f32 / total as f32
+    }
+}
+
+/// The detections of one category over the dataset, most confident first, and whether each
+/// matched a ground truth object
+struct ClassMatches {
+    ground_truth: usize,
+    detections: Vec<(f32, bool)>,
+}
+
+impl ClassMatches {
+    fn at(&self, threshold: f32) -> (usize, usize) {
+        let detections = self
+            .detections
+            .iter()
+            .take_while(|(confidence, _)| *confidence >= threshold);
+        let count = detections.clone().count();
+        let matched = detections.filter(|(_, matched)| *matched).count();
+        (count, matched)
+    }
+
+    /// All-point interpolated average precision
+    fn average_precision(&self) -> Option<f32> {
+        if self.ground_truth == 0 {
+            return None;
+        }
+
+        let mut points = vec![];
+        let mut matched = 0;
+        for (i, (_, is_match)) in self.detections.iter().enumerate() {
+            matched += *is_match as usize;
+            points.push((
+                matched as f32 / self.ground_truth as f32,
+                matched as f32 / (i + 1) as f32,
+            ));
+        }
+
+        // Each precision becomes the best at that or any higher recall
+        let mut best = 0.0f32;
+        for point in points.iter_mut().rev() {
+            best = best.max(point.1);
+            point.1 = best;
+        }
+
+        let mut ap = 0.0;
+        let mut previous_recall = 0.0;
+        for (recall, precision) in points {
+            ap += (recall - previous_recall) * precision;
+            previous_recall = recall;
+        }
+        Some(ap)
+    }
+}
+
+/// Reduce annotations to the most confident one of each category, for image level evaluation
+fn per_category(annotations: &[Annotation]) -> Vec<Annotation> {
+    let mut best: Vec<Annotation> = vec![];
+    for annotation in annotations {
+        match best
+            .iter_mut()
+            .find(|best| best.class_index == annotation.class_index)
+        {
+            Some(best) if best.confidence >= annotation.confidence => {}
+            Some(best) => best.confidence = annotation.confidence,
+            None => best.push(Annotation {
+                bbox: None,
+                ..annotation.clone()
+            }),
+        }
+    }
+    best
+}
+
+fn match_class(
+    class_index: u32,
+    pairs: &[(Vec<Annotation>, Vec<Annotation>)],
+    box_level: bool,
+) -> ClassMatches {
+    let mut ground_truth = 0;
+    let mut detections = vec![];
+    for (image, (expected, actual)) in pairs.iter().enumerate() {
+        ground_truth += expected
+            .iter()
+            .filter(|annotation| annotation.class_index == class_index)
+            .count();
+        detections.extend(
+            actual
+                .iter()
+                .filter(|annotation| annotation.class_index == class_index)
+                .map(|annotation| (image, annotation)),
+        );
+    }
+    detections.sort_by(|a, b| b.1.confidence.total_cmp(&a.1.confidence));
+
+    let mut taken: Vec<Vec<bool>> = pairs
+        .iter()
+        .map(|(expected, _)| vec![false; expected.len()])
+        .collect();
+    let detections = detections
+        .into_iter()
+        .map(|(image, detection)| {
+            let best = pairs[image]
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(i, expected)| !taken[image][*i] && expected.class_index == class_index)
+                .filter_map(
+                    |(i, expected)| match (box_level, &expected.bbox, &detection.bbox) {
+                        (false, _, _) => Some((i, 1.0)),
+                        (true, Some(expected), Some(actual)) => {
+                            Some((i, expected.iou(actual))).filter(|(_, iou)| *iou >= MATCH_IOU)
+                        }
+                        _ => None,
+                    },
+                )
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some((i, _)) = best {
+                taken[image][i] = true;
+            }
+            (detection.confidence, best.is_some())
+        })
+        .collect();
+
+    ClassMatches {
+        ground_truth,
+        detections,
+    }
+}
+
+/// Evaluate results against ground truth, matching images by their relative path
+pub fn evaluate(
+    ground_truth: &[AnnotatedImage],
+    results: &[AnnotatedImage],
+    confidence_threshold: f32,
+) -> EvaluationReport {
+    let results: HashMap<&str, &AnnotatedImage> = results
+        .iter()
+        .map(|image| (image.file.as_str(), image))
+        .collect();
+
+    let box_level = ground_truth
+        .iter()
+        .flat_map(|image| &image.annotations)
+        .all(|annotation| annotation.bbox.is_some());
+
+    let pairs: Vec<(Vec<Annotation>, Vec<Annotation>)> = ground_truth
+        .iter()
+        .filter_map(|expected| {
+            let actual = results.get(expected.file.as_str())?;
+            Some(if box_level {
+                (expected.annotations.clone(), actual.annotations.clone())
+            } else {
+                (
+                    per_category(&expected.annotations),
+                    per_category(&actual.annotations),
+                )
+            })
+        })
+        .collect();
+
+    let categories: Vec<(Category, u32)> = model_categories()
+        .filter_map(|category| Some((category, category.model_index?)))
+        .collect();
+    let matches: Vec<ClassMatches> = categories
+        .iter()
+        .map(|(_, class_index)| match_class(*class_index, &pairs, box_level))
+        .collect();
+
+    let empty_accuracy = |threshold: f32| {
+        let correct = pairs
+            .iter()
+            .filter(|(expected, actual)| {
+                let detected = actual
+                    .iter()
+                    .any(|annotation| annotation.confidence >= threshold);
+                detected != expected.is_empty()
+            })
+            .count();
+        ratio(correct, pairs.len())
+    };
+
+    let classes: Vec<ClassMetrics> = categories
+        .iter()
+        .zip(&matches)
+        .map(|((category, _), class)| {
+            let (detections, matched) = class.at(confidence_threshold);
+            ClassMetrics {
+                category: category.name.to_string(),
+                ground_truth: class.ground_truth,
+                detections,
+                precision: ratio(matched, detections),
+                recall: ratio(matched, class.ground_truth),
+                average_precision: class.average_precision(),
+            }
+        })
+        .filter(|class| class.ground_truth > 0 || class.detections > 0)
+        .collect();
+
+    let average_precisions: Vec<f32> = classes
+        .iter()
+        .filter_map(|class| class.average_precision)
+        .collect();
+    let map50 = (!average_precisions.is_empty())
+        .then(|| average_precisions.iter().sum::<f32>() / average_precisions.len() as f32);
+
+    let ground_truth_total: usize = matches.iter().map(|class| class.ground_truth).sum();
+    let curve = (1..20)
+        .map(|step| {
+            let threshold = step as f32 * 0.05;
+            let (detections, matched) = matches
+                .iter()
+                .map(|class| class.at(threshold))
+                .fold((0, 0), |total, class| {
+                    (total.0 + class.0, total.1 + class.1)
+                });
+            CurvePoint {
+                threshold,
+                precision: ratio(matched, detections),
+                recall: ratio(matched, ground_truth_total),
+                empty_accuracy: empty_accuracy(threshold),
+            }
+        })
+        .collect();
+
+    EvaluationReport {
+        images: pairs.len(),
+        missing: ground_truth.len() - pairs.len(),
+        box_level,
+        confidence_threshold,
+        classes,
+        map50,
+        empty_accuracy: empty_accuracy(confidence_threshold),
+        curve,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_megadetector_output() {
+        let file = std::fs::File::open("./tests/fixtures/ena.md.4.1.0.json").unwrap();
+        let results = read_annotations(file, LabelSelection::Model).unwrap();
+
+        // Confident detections as ground truth, so at that threshold the results are perfect
+        let ground_truth: Vec<AnnotatedImage> = results
+            .iter()
+            .map(|image| AnnotatedImage {
+                file: image.file.clone(),
+                annotations: image
+                    .annotations
+                    .iter()
+                    .filter(|annotation| annotation.confidence >= 0.5)
+                    .cloned()
+                    .map(|annotation| Annotation {
+                        confidence: 1.0,
+                        ..annotation
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let report = evaluate(&ground_truth, &results, 0.5);
+        assert_eq!(report.images, results.len());
+        assert_eq!(report.missing, 0);
+        assert!(report.box_level);
+        assert_eq!(report.empty_accuracy, 1.0);
+        for class in &report.classes {
+            assert_eq!(class.precision, 1.0);
+            assert_eq!(class.recall, 1.0);
+            assert_eq!(class.average_precision, Some(1.0));
+        }
+        assert_eq!(report.map50, Some(1.0));
+
+        // Lower thresholds only add false positives
+        let low = &report.curve[0];
+        assert!(low.precision < 1.0);
+        assert_eq!(low.recall, 1.0);
+    }
+
+    #[test]
+    fn test_megadetector_ground_truth() {
+        let output = r#"{
+            "images": [
+                {"file": "a/1.jpg", "detections": [
+                    {"category": "1", "conf": 0.9, "bbox": [0.1, 0.1, 0.2, 0.2]},
+                    {"category": "2", "conf": 0.05, "bbox": [0.5, 0.5, 0.1, 0.1]}
+                ]},
+                {"file": "a/2.jpg", "failure": "Unreadable"}
+            ],
+            "detection_categories": {"1": "animal", "2": "person", "3": "vehicle"}
+        }"#;
+
+        let results = read_annotations(output.as_bytes(), LabelSelection::Model).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].annotations.len(), 2);
+        assert_eq!(results[0].annotations[1].confidence, 0.05);
+
+        // Low confidence detections are not taken as true objects
+        let ground_truth = read_annotations(output.as_bytes(), LabelSelection::Reviewed).unwrap();
+        assert_eq!(ground_truth[0].annotations.len(), 1);
+        assert_eq!(ground_truth[0].annotations[0].confidence, 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_image_level_labels() {
+        let ground_truth = r#"{
+            "images": [
+                {"id": 1, "file_name": "a\\1.jpg", "width": 100, "height": 100},
+                {"id": 2, "file_name": "a\\2.jpg", "width": 100, "height": 100},
+                {"id": 3, "file_name": "a\\3.jpg", "width": 100, "height": 100},
+                {"id": 4, "file_name": "a\\4.jpg", "width": 100, "height": 100}
+            ],
+            "annotations": [
+                {"image_id": 1, "category_id": 5},
+                {"image_id": 2, "category_id": 0},
+                {"image_id": 3, "category_id": 7, "bbox": [10, 10, 20, 20]}
+            ],
+            "categories": [
+                {"id": 0, "name": "empty"},
+                {"id": 5, "name": "red fox"},
+                {"id": 7, "name": "human"}
+            ]
+        }"#;
+        let ground_truth =
+            read_annotations(ground_truth.as_bytes(), LabelSelection::Reviewed).unwrap();
+        assert_eq!(ground_truth[0].file, "a/1.jpg");
+        assert!(ground_truth[1].annotations.is_empty());
+        assert_eq!(
+            ground_truth[2].annotations[0].bbox,
+            Some(BBox {
+                x: 0.1,
+                y: 0.1,
+                w: 0.2,
+                h: 0.2
+            })
+        );
+
+        let detection = |class_index, confidence| Annotation {
+            class_index,
+            bbox: Some(BBox {
+                x: 0.5,
+                y: 0.5,
+                w: 0.1,
+                h: 0.1,
+            }),
+            confidence,
+        };
+        let image = |file: &str, annotations| AnnotatedImage {
+            file: file.to_string(),
+            annotations,
+        };
+        let results = vec![
+            image("a/1.jpg", vec![detection(0, 0.9), detection(0, 0.4)]),
+            image("a/2.jpg", vec![detection(0, 0.3)]),
+            image("a/3.jpg", vec![detection(1, 0.8)]),
+        ];
+
+        let report = evaluate(&ground_truth, &results, 0.5);
+        assert_eq!(report.images, 3);
+        assert_eq!(report.missing, 1);
+        assert!(!report.box_level);
+        assert_eq!(report.empty_accuracy, 1.0);
+
+        let animal = &report.classes[0];
+        assert_eq!(animal.category, "Animal");
+        assert_eq!(animal.ground_truth, 1);
+        assert_eq!(animal.detections, 1);
+        assert_eq!(animal.average_precision, Some(1.0));
+
+        // At 0.25 the empty image has an animal detection
+        assert!((report.curve[4].threshold - 0.25).abs() < 1e-6);
+        assert!((report.curve[4].empty_accuracy - 2.0 / 3.0).abs() < 1e-6);
+        assert!((report.curve[4].precision - 2.0 / 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,465 @@
+//! Contact sheet export
+//!
+//! Builds grids of thumbnails, one or more sheets per folder or per category, so hundreds of
+//! detections can be checked at a glance.
+//!
+
+use crate::exports::image::{match_criteria, FilterCriteria};
+use crate::megadetector::Category;
+use crate::structures::{CamTrapDetection, CamTrapImageDetections};
+use crate::util::magic_image::MagicImage;
+use crate::{Error, Result};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How images are grouped into sheets
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum ContactSheetGrouping {
+    /// One set of sheets per folder, relative to the base directory
+    #[default]
+    Folder,
+
+    /// One set of sheets per top category
+    Category,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactSheetCriteria {
+    #[serde(default)]
+    group_by: ContactSheetGrouping,
+
+    /// Maximum number of tiles on each sheet
+    #[serde(default = "default_tiles_per_sheet")]
+    tiles_per_sheet: usize,
+
+    /// Number of tiles in each row
+    #[serde(default = "default_columns")]
+    columns: u32,
+
+    /// Width and height of each tile in pixels
+    #[serde(default = "default_tile_size")]
+    tile_size: u32,
+
+    /// Crop each tile to its top detection rather than showing the whole image
+    #[serde(default)]
+    crop: bool,
+}
+
+/// The largest width and height of a sheet in pixels, the most a JPEG can hold
+const MAX_SHEET_SIZE: u64 = 65_535;
+
+fn default_tiles_per_sheet() -> usize {
+    48
+}
+
+fn default_columns() -> u32 {
+    8
+}
+
+fn default_tile_size() -> u32 {
+    256
+}
+
+impl Default for ContactSheetCriteria {
+    fn default() -> Self {
+        Self {
+            group_by: ContactSheetGrouping::default(),
+            tiles_per_sheet: default_tiles_per_sheet(),
+            columns: default_columns(),
+            tile_size: default_tile_size(),
+            crop: false,
+        }
+    }
+}
+
+/// The detection with the highest confidence in an image
+fn top_detection(image: &CamTrapImageDetections) -> Option<&CamTrapDetection> {
+    image
+        .detections
+        .iter()
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+}
+
+/// The name of a detection's category
+fn category_name(detection: &CamTrapDetection) -> &'static str {
+    Category::name_of(detection.class_index)
+}
+
+/// The name of the group an image belongs to, used as the sheets' directory
+fn group_name(
+    image: &CamTrapImageDetections,
+    base_dir: &Path,
+    grouping: ContactSheetGrouping,
+) -> String {
+    match grouping {
+        ContactSheetGrouping::Folder => pathdiff::diff_paths(&image.file, base_dir)
+            .and_then(|path| path.parent().map(|parent| parent.to_path_buf()))
+            .map(|parent| parent.to_string_lossy().to_string())
+            .filter(|parent| !parent.is_empty())
+            .unwrap_or_else(|| String::from(".")),
+        ContactSheetGrouping::Category => match (&image.error, top_detection(image)) {
+            (Some(_), _) => String::from("Error"),
+            (None, Some(detection)) => category_name(detection).to_string(),
+            (None, None) => String::from("Empty"),
+        },
+    }
+}
+
+/// Keep the end of a long file name, which is usually the most distinctive part
+fn shorten(text: &str, max_chars: usize) -> String {
+    let chars = text.chars().count();
+    if chars <= max_chars {
+        return text.to_string();
+    }
+
+    let tail: String = text.chars().skip(chars - max_chars + 1).collect();
+    format!("…{}", tail)
+}
+
+/// Render a single tile, the image scaled to fit with its file name and top detection overlaid
+fn render_tile(
+    image_meta: &CamTrapImageDetections,
+    base_dir: &Path,
+    criteria: &ContactSheetCriteria,
+) -> DynamicImage {
+    let tile_size = criteria.tile_size;
+    let background = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        tile_size,
+        tile_size,
+        Rgba([32, 32, 32, 255]),
+    ));
+    let mut tile = MagicImage::new(background, ImageFormat::Png);
+    let font_size = (tile_size as f32 * 0.07).max(10.0);
+    let top = top_detection(image_meta);
+
+    match MagicImage::open(&image_meta.file) {
+        Ok(source) => {
+            let source = source.image();
+            let source = match (criteria.crop, top) {
+                (true, Some(detection)) => {
+                    // Include a little context around the detection
+                    let pad_x = detection.width * 0.1;
+                    let pad_y = detection.height * 0.1;
+                    let x = (detection.x - pad_x).max(0.0) as u32;
+                    let y = (detection.y - pad_y).max(0.0) as u32;
+                    let width = ((detection.width + pad_x * 2.0) as u32)
+                        .min(source.width().saturating_sub(x))
+                        .max(1);
+                    let height = ((detection.height + pad_y * 2.0) as u32)
+                        .min(source.height().saturating_sub(y))
+                        .max(1);
+                    source.crop_imm(x, y, width, height)
+                }
+                _ => source.clone(),
+            };
+
+            let thumbnail = source.thumbnail(tile_size, tile_size);
+            tile.overlay(
+                &thumbnail,
+                ((tile_size - thumbnail.width()) / 2) as i64,
+                ((tile_size - thumbnail.height()) / 2) as i64,
+            );
+        }
+        Err(err) => {
+            tile.draw_label(
+                0,
+                (tile_size / 2) as i32,
+                &shorten(&err.to_string(), 24),
+                font_size,
+                Rgba([128, 0, 0, 255]),
+                Rgba([255, 255, 255, 255]),
+            );
+        }
+    }
+
+    let label = match (&image_meta.error, top) {
+        (Some(_), _) => String::from("Error"),
+        (None, Some(detection)) => {
+            format!("{} {:.2}", category_name(detection), detection.confidence)
+        }
+        (None, None) => String::from("Empty"),
+    };
+    tile.draw_label(
+        0,
+        0,
+        &label,
+        font_size,
+        Rgba([0, 0, 0, 192]),
+        Rgba([255, 255, 255, 255]),
+    );
+
+    let file_name = pathdiff::diff_paths(&image_meta.file, base_dir)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|| image_meta.file.clone());
+    let max_chars = (tile_size as f32 / (font_size * 0.6)) as usize;
+    tile.draw_label(
+        0,
+        tile_size as i32,
+        &shorten(&file_name, max_chars.max(4)),
+        font_size,
+        Rgba([0, 0, 0, 192]),
+        Rgba([255, 255, 255, 255]),
+    );
+
+    tile.into_image()
+}
+
+/// Export contact sheets of the images matching the filter criteria into the output directory
+///
+/// Sheets are written as `<group>/sheet-001.jpg`, `<group>/sheet-002.jpg`, ... and their paths
+/// returned. `on_progress` is called with the number of sheets written so far, the total and
+/// the sheet just written. Setting `cancel` stops any sheets not yet started from being written.
+///
+/// Fails before writing anything if a full sheet would be wider or taller than a JPEG can be.
+pub fn export_contact_sheets(
+    results: &[CamTrapImageDetections],
+    base_dir: PathBuf,
+    output_dir: PathBuf,
+    filter_criteria: FilterCriteria,
+    mut criteria: ContactSheetCriteria,
+    cancel: &AtomicBool,
+    on_progress: impl Fn(usize, usize, &str) + Sync,
+) -> Result<Vec<PathBuf>> {
+    let tiles_per_sheet = criteria.tiles_per_sheet.max(1);
+    let columns = criteria.columns.max(1);
+    criteria.tile_size = criteria.tile_size.max(1);
+    let tile_size = criteria.tile_size;
+
+    let full_columns = (columns as u64).min(tiles_per_sheet as u64);
+    let full_rows = (tiles_per_sheet as u64).div_ceil(columns as u64);
+    if full_columns.max(full_rows) * tile_size as u64 > MAX_SHEET_SIZE {
+        return Err(Error::InvalidInput(format!(
+            "Sheets of {} by {} tiles of {} pixels are larger than the maximum of {} pixels",
+            full_columns, full_rows, tile_size, MAX_SHEET_SIZE
+        )));
+    }
+
+    let mut groups: BTreeMap<String, Vec<&CamTrapImageDetections>> = BTreeMap::new();
+    for image in results
+        .iter()
+        .filter(|image| match_criteria(image, &filter_criteria))
+    {
+        groups
+            .entry(group_name(image, &base_dir, criteria.group_by))
+            .or_default()
+            .push(image);
+    }
+
+    let output_dir = &output_dir;
+    let sheets: Vec<(PathBuf, &[&CamTrapImageDetections])> = groups
+        .iter()
+        .flat_map(|(group, images)| {
+            images
+                .chunks(tiles_per_sheet)
+                .enumerate()
+                .map(move |(i, chunk)| {
+                    let path = output_dir
+                        .join(group)
+                        .join(format!("sheet-{:03}.jpg", i + 1));
+                    (path, chunk)
+                })
+        })
+        .collect();
+
+    let total = sheets.len();
+    let mut paths = vec![];
+
+    for (path, images) in sheets {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let tiles: Vec<DynamicImage> = images
+            .par_iter()
+            .map(|image| render_tile(image, &base_dir, &criteria))
+            .collect();
+
+        let rows = (tiles.len() as u32).div_ceil(columns);
+        let sheet_columns = columns.min(tiles.len() as u32);
+        let mut sheet = MagicImage::new(
+            DynamicImage::ImageRgb8(image::RgbImage::new(
+                sheet_columns * tile_size,
+                rows * tile_size,
+            )),
+            ImageFormat::Jpeg,
+        );
+
+        for (i, tile) in tiles.iter().enumerate() {
+            let column = i as u32 % columns;
+            let row = i as u32 / columns;
+            sheet.overlay(tile, (column * tile_size) as i64, (row * tile_size) as i64);
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        sheet.save(&path, Some(85))?;
+
+        on_progress(paths.len() + 1, total, &path.to_string_lossy());
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{DetectionSource, ReviewStatus};
+
+    fn create_image(file: &str, detections: Vec<(u32, f32)>) -> CamTrapImageDetections {
+        CamTrapImageDetections {
+            file: file.to_string(),
+            detections: detections
+                .into_iter()
+                .map(|(class_index, confidence)| CamTrapDetection {
+                    class_index,
+                    x: 0.0,
+                    y: 0.0,
+                    width: 4.0,
+                    height: 4.0,
+                    confidence,
+                    source: DetectionSource::Model,
+                    review: ReviewStatus::Unreviewed,
+                })
+                .collect(),
+            error: None,
+            image_width: None,
+            image_height: None,
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_group_name() {
+        let base_dir = Path::new("/data");
+        let image = create_image("/data/site-a/cam-1/IMG_0001.JPG", vec![(1, 0.5), (0, 0.9)]);
+
+        assert_eq!(
+            group_name(&image, base_dir, ContactSheetGrouping::Folder),
+            "site-a/cam-1"
+        );
+        assert_eq!(
+            group_name(&image, base_dir, ContactSheetGrouping::Category),
+            "Animal"
+        );
+        assert_eq!(
+            group_name(
+                &create_image("/data/IMG_0002.JPG", vec![]),
+                base_dir,
+                ContactSheetGrouping::Folder
+            ),
+            "."
+        );
+        assert_eq!(
+            group_name(
+                &create_image("/data/IMG_0002.JPG", vec![]),
+                base_dir,
+                ContactSheetGrouping::Category
+            ),
+            "Empty"
+        );
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("IMG_0001.JPG", 20), "IMG_0001.JPG");
+        assert_eq!(shorten("site-a/IMG_0001.JPG", 10), "…_0001.JPG");
+    }
+
+    #[test]
+    fn test_export_contact_sheets() {
+        let base_dir = std::env::temp_dir().join("camtrap-contact-sheets");
+        let output_dir = base_dir.join("output");
+        std::fs::create_dir_all(&base_dir).unwrap();
+
+        let image_path = base_dir.join("animal.png");
+        image::RgbImage::new(32, 16).save(&image_path).unwrap();
+        let image_path = image_path.to_str().unwrap();
+
+        let results: Vec<CamTrapImageDetections> = (0..5)
+            .map(|_| create_image(image_path, vec![(0, 0.9)]))
+            .chain([create_image(
+                base_dir.join("missing.png").to_str().unwrap(),
+                vec![(1, 0.8)],
+            )])
+            .collect();
+
+        let criteria = ContactSheetCriteria {
+            group_by: ContactSheetGrouping::Category,
+            tiles_per_sheet: 4,
+            columns: 2,
+            tile_size: 64,
+            crop: true,
+        };
+        let filter_criteria = || -> FilterCriteria {
+            serde_json::from_str(
+                r#"{"animals":"Include","humans":"Include","vehicles":"Include","empty":"Include"}"#,
+            )
+            .unwrap()
+        };
+
+        let sheets = export_contact_sheets(
+            &results,
+            base_dir.clone(),
+            output_dir.clone(),
+            filter_criteria(),
+            criteria.clone(),
+            &AtomicBool::new(false),
+            |_, _, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(
+            sheets,
+            vec![
+                output_dir.join("Animal").join("sheet-001.jpg"),
+                output_dir.join("Animal").join("sheet-002.jpg"),
+                output_dir.join("Human").join("sheet-001.jpg"),
+            ]
+        );
+        assert_eq!(image::image_dimensions(&sheets[0]).unwrap(), (128, 128));
+        assert_eq!(image::image_dimensions(&sheets[1]).unwrap(), (64, 64));
+
+        // A zero tile size is raised to one pixel rather than making empty sheets
+        let sheets = export_contact_sheets(
+            &[create_image(image_path, vec![(0, 0.9)])],
+            base_dir.clone(),
+            output_dir.clone(),
+            filter_criteria(),
+            ContactSheetCriteria {
+                tile_size: 0,
+                ..criteria.clone()
+            },
+            &AtomicBool::new(false),
+            |_, _, _| {},
+        )
+        .unwrap();
+        assert_eq!(image::image_dimensions(&sheets[0]).unwrap(), (1, 1));
+
+        // Sheets too large for a JPEG are rejected rather than overflowing
+        let result = export_contact_sheets(
+            &[create_image(image_path, vec![(0, 0.9)])],
+            base_dir.clone(),
+            output_dir.clone(),
+            filter_criteria(),
+            ContactSheetCriteria {
+                tiles_per_sheet: 1000,
+                columns: 1000,
+                tile_size: u32::MAX,
+                ..criteria
+            },
+            &AtomicBool::new(false),
+            |_, _, _| {},
+        );
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+
+        std::fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
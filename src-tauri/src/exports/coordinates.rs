@@ -0,0 +1,151 @@
+//! Coordinates module
+//!
+//! Converts detection boxes from the pixel coordinates the model produces into the coordinate
+//! system an export is written in, and back when the export is read.
+//!
+
+use crate::structures::CamTrapDetection;
+use serde::{Deserialize, Serialize};
+
+/// How the box of a detection is written in an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoordinateSystem {
+    /// Top-left corner, width and height in pixels
+    #[default]
+    Pixels,
+
+    /// Top-left corner, width and height as fractions (0-1) of the image size, as MegaDetector
+    Normalized,
+
+    /// Center, width and height as fractions (0-1) of the image size, as YOLO labels
+    NormalizedCenter,
+
+    /// Top-left and bottom-right corners in pixels
+    Xyxy,
+}
+
+impl CoordinateSystem {
+    /// The names of the four box values, used as CSV columns
+    pub fn columns(&self) -> [&'static str; 4] {
+        match self {
+            Self::Pixels | Self::Normalized => ["x", "y", "width", "height"],
+            Self::NormalizedCenter => ["x_center", "y_center", "width", "height"],
+            Self::Xyxy => ["x_min", "y_min", "x_max", "y_max"],
+        }
+    }
+
+    /// The top-left corner, width and height in pixels of a box written in this coordinate system
+    pub fn to_pixels(&self, values: [f32; 4], width: u32, height: u32) -> [f32; 4] {
+        let (width, height) = (width as f32, height as f32);
+        let [a, b, c, d] = values;
+
+        match self {
+            Self::Pixels => values,
+            Self::Normalized => [a * width, b * height, c * width, d * height],
+            Self::NormalizedCenter => [
+                (a - c / 2.0) * width,
+                (b - d / 2.0) * height,
+                c * width,
+                d * height,
+            ],
+            Self::Xyxy => [a, b, c - a, d - b],
+        }
+    }
+}
+
+/// The coordinate system and precision of the boxes in an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordinateOptions {
+    pub system: CoordinateSystem,
+
+    /// Round each value to this many decimal places, or keep full precision when `None`
+    pub decimals: Option<u32>,
+}
+
+impl CoordinateOptions {
+    /// The box of a detection in the image of the given size, in this coordinate system
+    pub fn convert(&self, detection: &CamTrapDetection, width: u32, height: u32) -> [f32; 4] {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        let (x, y, w, h) = (detection.x, detection.y, detection.width, detection.height);
+
+        let values = match self.system {
+            CoordinateSystem::Pixels => [x, y, w, h],
+            CoordinateSystem::Normalized => [x / width, y / height, w / width, h / height],
+            CoordinateSystem::NormalizedCenter => [
+                (x + w / 2.0) / width,
+                (y + h / 2.0) / height,
+                w / width,
+                h / height,
+            ],
+            CoordinateSystem::Xyxy => [x, y, x + w, y + h],
+        };
+
+        values.map(|value| self.round(value))
+    }
+
+    fn round(&self, value: f32) -> f32 {
+        match self.decimals {
+            Some(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                ((value as f64 * scale).round() / scale) as f32
+            }
+            None => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{DetectionSource, ReviewStatus};
+
+    #[test]
+    fn test_convert() {
+        let detection = CamTrapDetection {
+            x: 10.25,
+            y: 20.5,
+            width: 30.0,
+            height: 40.0,
+            class_index: 0,
+            confidence: 0.9,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+        let convert =
+            |system, decimals| CoordinateOptions { system, decimals }.convert(&detection, 200, 100);
+
+        assert_eq!(
+            convert(CoordinateSystem::Pixels, None),
+            [10.25, 20.5, 30.0, 40.0]
+        );
+        assert_eq!(
+            convert(CoordinateSystem::Pixels, Some(0)),
+            [10.0, 21.0, 30.0, 40.0]
+        );
+        assert_eq!(
+            convert(CoordinateSystem::Normalized, Some(3)),
+            [0.051, 0.205, 0.15, 0.4]
+        );
+        assert_eq!(
+            convert(CoordinateSystem::NormalizedCenter, Some(3)),
+            [0.126, 0.405, 0.15, 0.4]
+        );
+        assert_eq!(
+            convert(CoordinateSystem::Xyxy, None),
+            [10.25, 20.5, 40.25, 60.5]
+        );
+
+        for system in [
+            CoordinateSystem::Pixels,
+            CoordinateSystem::Normalized,
+            CoordinateSystem::NormalizedCenter,
+            CoordinateSystem::Xyxy,
+        ] {
+            let pixels = system.to_pixels(convert(system, None), 200, 100);
+            for (actual, expected) in pixels.iter().zip([10.25, 20.5, 30.0, 40.0]) {
+                assert!((actual - expected).abs() < 1e-4, "{:?}", system);
+            }
+        }
+    }
+}
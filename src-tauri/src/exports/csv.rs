@@ -1,5 +1,10 @@
-use crate::megadetector::CATEGORIES;
+use super::coordinates::CoordinateOptions;
+use crate::megadetector::Category;
+use crate::metadata::ImageMetadata;
+use crate::structures::{CamTrapImageDetections, DetectionSource, ReviewStatus};
+use crate::Result;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CamTrapCSVDetection {
@@ -15,25 +20,48 @@ pub struct CamTrapCSVDetection {
     /// Image height in pixels
     pub image_height: Option<u32>,
 
-    /// X coordinate of the top-left corner of the detection
-    pub x: Option<u32>,
+    /// X coordinate of the top-left corner of the detection, or as given by the coordinate system
+    pub x: Option<f32>,
 
-    /// Y coordinate of the top-left corner of the detection
-    pub y: Option<u32>,
+    /// Y coordinate of the top-left corner of the detection, or as given by the coordinate system
+    pub y: Option<f32>,
 
-    /// Width of the detection in pixels
-    pub width: Option<u32>,
+    /// Width of the detection in pixels, or as given by the coordinate system
+    pub width: Option<f32>,
 
-    /// Height of the detection in pixels
-    pub height: Option<u32>,
+    /// Height of the detection in pixels, or as given by the coordinate system
+    pub height: Option<f32>,
 
     /// Category of the detection
     pub category: Option<String>,
 
     /// Confidence of the detection
     pub confidence: Option<f32>,
+
+    /// Whether the detection was predicted by the model or added by a person
+    pub source: Option<DetectionSource>,
+
+    /// Whether a person has verified or rejected the detection, or the image when empty
+    pub review: Option<ReviewStatus>,
+
+    /// How the image was captured, written as the [`METADATA_COLUMNS`]
+    #[serde(default)]
+    pub metadata: ImageMetadata,
 }
 
+/// The names of the columns the fields of [`ImageMetadata`] are written as, in the same order
+pub const METADATA_COLUMNS: [&str; 9] = [
+    "timestamp",
+    "camera_make",
+    "camera_model",
+    "camera_serial_number",
+    "temperature",
+    "moon_phase",
+    "trigger",
+    "sequence_index",
+    "sequence_length",
+];
+
 impl CamTrapCSVDetection {
     /// Create a new error detection
     pub fn new_error(file: String, error: String) -> Self {
@@ -48,11 +76,14 @@ impl CamTrapCSVDetection {
             height: None,
             category: None,
             confidence: None,
+            source: None,
+            review: None,
+            metadata: ImageMetadata::default(),
         }
     }
 
     /// Create a new empty detection
-    pub fn new_empty(file: String) -> Self {
+    pub fn new_empty(file: String, review: ReviewStatus) -> Self {
         Self {
             file,
             error: None,
@@ -62,34 +93,189 @@ impl CamTrapCSVDetection {
             y: None,
             width: None,
             height: None,
-            category: Some(String::from("Empty")),
+            category: Some(Category::EMPTY.name.to_string()),
             confidence: None,
+            source: None,
+            review: Some(review),
+            metadata: ImageMetadata::default(),
         }
     }
 
-    /// Create a new detection
+    /// Create a new detection, with its box in the given coordinate system
     pub fn new_detection(
         file: String,
         image_width: u32,
         image_height: u32,
         detection: &crate::structures::CamTrapDetection,
+        coordinates: &CoordinateOptions,
     ) -> Self {
+        let [x, y, width, height] = coordinates.convert(detection, image_width, image_height);
+
         Self {
             file,
             error: None,
             image_width: Some(image_width),
             image_height: Some(image_height),
-            x: Some(detection.x as u32),
-            y: Some(detection.y as u32),
-            width: Some(detection.width as u32),
-            height: Some(detection.height as u32),
-            category: Some(
-                CATEGORIES
-                    .get(detection.class_index as usize + 1)
-                    .unwrap()
-                    .to_string(),
-            ),
+            x: Some(x),
+            y: Some(y),
+            width: Some(width),
+            height: Some(height),
+            category: Some(Category::name_of(detection.class_index).to_string()),
             confidence: Some(detection.confidence),
+            source: Some(detection.source),
+            review: Some(detection.review),
+            metadata: ImageMetadata::default(),
+        }
+    }
+
+    /// Set how the image was captured, leaving the metadata columns blank if unknown
+    pub fn with_metadata(mut self, metadata: Option<&ImageMetadata>) -> Self {
+        self.metadata = metadata.cloned().unwrap_or_default();
+        self
+    }
+}
+
+/// Write results as CSV, one row per detection and a single row for each empty or failed image
+///
+/// The results are written as they are iterated so large sets need not be held in memory twice.
+/// The box columns are named after the coordinate system, e.g. `x_min` to `y_max` for corners.
+pub fn write_csv(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+    coordinates: &CoordinateOptions,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+
+    // The same columns as `CamTrapCSVDetection`, with the box columns renamed
+    let [x, y, width, height] = coordinates.system.columns();
+    let columns = [
+        "file",
+        "error",
+        "image_width",
+        "image_height",
+        x,
+        y,
+        width,
+        height,
+        "category",
+        "confidence",
+        "source",
+        "review",
+    ];
+    writer.write_record(columns.iter().chain(&METADATA_COLUMNS))?;
+
+    for result in results {
+        let metadata = result.metadata.as_ref();
+        if let Some(error) = &result.error {
+            writer.serialize(
+                CamTrapCSVDetection::new_error(result.file.clone(), error.clone())
+                    .with_metadata(metadata),
+            )?;
+        } else if result.detections.is_empty() {
+            writer.serialize(
+                CamTrapCSVDetection::new_empty(result.file.clone(), result.review)
+                    .with_metadata(metadata),
+            )?;
+        } else {
+            for detection in &result.detections {
+                writer.serialize(
+                    CamTrapCSVDetection::new_detection(
+                        result.file.clone(),
+                        result.image_width.unwrap_or(0),
+                        result.image_height.unwrap_or(0),
+                        detection,
+                        coordinates,
+                    )
+                    .with_metadata(metadata),
+                )?;
+            }
         }
     }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exports::coordinates::CoordinateSystem;
+    use crate::structures::CamTrapDetection;
+
+    #[test]
+    fn test_write_csv() {
+        let detection = CamTrapDetection {
+            x: 1.5,
+            y: 2.0,
+            width: 3.0,
+            height: 4.0,
+            class_index: 1,
+            confidence: 0.5,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+        let image = |file: &str, error: Option<&str>, detections: Vec<CamTrapDetection>| {
+            CamTrapImageDetections {
+                file: file.to_string(),
+                error: error.map(|e| e.to_string()),
+                image_width: Some(10),
+                image_height: Some(10),
+                detections,
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            }
+        };
+        let mut results = vec![
+            image("a.jpg", None, vec![detection.clone(), detection]),
+            image("b.jpg", None, vec![]),
+            image("c.jpg", Some("Unreadable"), vec![]),
+        ];
+        results[1].metadata = Some(ImageMetadata {
+            timestamp: Some(String::from("2023-06-01 21:14:05")),
+            model: Some(String::from("HC600")),
+            temperature: Some(-2.5),
+            moon_phase: Some(crate::metadata::MoonPhase::Full),
+            ..Default::default()
+        });
+
+        let mut output = vec![];
+        write_csv(results.clone(), &CoordinateOptions::default(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "file,error,image_width,image_height,x,y,width,height,category,confidence,source,review,\
+             timestamp,camera_make,camera_model,camera_serial_number,temperature,moon_phase,\
+             trigger,sequence_index,sequence_length"
+        );
+        assert_eq!(
+            lines[1],
+            "a.jpg,,10,10,1.5,2.0,3.0,4.0,Human,0.5,Model,Unreviewed,,,,,,,,,"
+        );
+        assert_eq!(
+            lines[3],
+            "b.jpg,,,,,,,,Empty,,,Unreviewed,2023-06-01 21:14:05,,HC600,,-2.5,Full,,,"
+        );
+        assert_eq!(lines[4], "c.jpg,Unreadable,,,,,,,,,,,,,,,,,,,");
+
+        let coordinates = CoordinateOptions {
+            system: CoordinateSystem::Xyxy,
+            decimals: Some(0),
+        };
+        let mut output = vec![];
+        write_csv(results, &coordinates, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(lines[0].contains(",x_min,y_min,x_max,y_max,"));
+        assert_eq!(
+            lines[1],
+            "a.jpg,,10,10,2.0,2.0,5.0,6.0,Human,0.5,Model,Unreviewed,,,,,,,,,"
+        );
+    }
 }
@@ -0,0 +1,364 @@
+//! HTML gallery export
+//!
+//! Writes a static website for reviewing results without the app installed: an index of every
+//! image and a page per image with its detections drawn over the original as an SVG overlay.
+//!
+//! Images are referenced by relative paths rather than copied, so the gallery keeps working
+//! offline when moved along with the images, e.g. on a USB drive.
+//!
+
+use crate::megadetector::Category;
+use crate::structures::{CamTrapDetection, CamTrapImageDetections};
+use crate::Result;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+const GALLERY_CSS: &str = include_str!("../../assets/gallery/gallery.css");
+const GALLERY_JS: &str = include_str!("../../assets/gallery/gallery.js");
+
+/// Categories that can be filtered on, alongside images without detections or with errors
+const FILTER_CATEGORIES: [&str; 5] = [
+    Category::ANIMAL.name,
+    Category::HUMAN.name,
+    Category::VEHICLE.name,
+    Category::EMPTY.name,
+    "Error",
+];
+
+/// Escape text for use in HTML content and attribute values
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode a path for use as a relative URL, always separated by `/`
+fn url_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::ParentDir => Some(String::from("..")),
+            Component::CurDir => Some(String::from(".")),
+            Component::RootDir => None,
+            Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().to_string()),
+            Component::Normal(part) => Some(
+                part.to_string_lossy()
+                    .bytes()
+                    .map(|b| match b {
+                        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                            (b as char).to_string()
+                        }
+                        _ => format!("%{:02X}", b),
+                    })
+                    .collect(),
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The URL of an image relative to a page in `page_dir`, falling back to an absolute `file://`
+/// URL when no relative path exists (e.g. a different drive on Windows)
+fn image_url(file: &str, page_dir: &Path) -> String {
+    match pathdiff::diff_paths(file, page_dir) {
+        Some(path) if path.is_relative() => url_path(&path),
+        _ => format!("file:///{}", url_path(Path::new(file))),
+    }
+}
+
+/// The name of a detection's category
+fn category_name(detection: &CamTrapDetection) -> &'static str {
+    Category::name_of(detection.class_index)
+}
+
+/// The file name of the page for the image at `index`
+fn page_name(index: usize) -> String {
+    format!("{:06}.html", index + 1)
+}
+
+/// The header shared by every page, holding the filter controls
+///
+/// `assets` is the relative path from the page to the directory holding the CSS and JS.
+fn write_header(html: &mut String, title: &str, nav: &str, assets: &str) -> std::fmt::Result {
+    write!(
+        html,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="stylesheet" href="{assets}gallery.css">
+</head>
+<body>
+<header>
+<h1>{title}</h1>
+{nav}
+<label>Min. confidence <input id="min-confidence" type="range" min="0" max="1" step="0.01" value="0"> <span id="min-confidence-value"></span></label>
+"#,
+        title = escape_html(title),
+    )?;
+
+    for category in FILTER_CATEGORIES {
+        writeln!(
+            html,
+            r#"<label><input type="checkbox" data-category="{category}" checked> {category}</label>"#
+        )?;
+    }
+
+    writeln!(html, "</header>")
+}
+
+fn write_footer(html: &mut String, assets: &str) -> std::fmt::Result {
+    writeln!(
+        html,
+        r#"<script src="{assets}gallery.js"></script>
+</body>
+</html>"#
+    )
+}
+
+/// Render the index page listing every image
+fn render_index(
+    results: &[CamTrapImageDetections],
+    base_dir: &Path,
+    output_dir: &Path,
+) -> Result<String, std::fmt::Error> {
+    let mut html = String::new();
+    write_header(&mut html, "CamTrap Detector Results", "", "")?;
+    writeln!(html, r#"<main class="grid">"#)?;
+
+    for (index, image) in results.iter().enumerate() {
+        let name = relative_name(&image.file, base_dir);
+        let detections: Vec<(&str, f32)> = image
+            .detections
+            .iter()
+            .map(|d| (category_name(d), d.confidence))
+            .collect();
+        let detections_json = serde_json::to_string(&detections).unwrap_or_default();
+
+        let summary = match (&image.error, detections.is_empty()) {
+            (Some(error), _) => format!(r#"<span class="error">{}</span>"#, escape_html(error)),
+            (None, true) => String::from("Empty"),
+            (None, false) => escape_html(
+                &detections
+                    .iter()
+                    .map(|(category, confidence)| format!("{} {:.2}", category, confidence))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        };
+
+        writeln!(
+            html,
+            r#"<div class="card" data-card data-detections="{detections}"{error}><a data-nav="image" href="images/{page}"><img loading="lazy" src="{src}" alt="{name}"><div class="caption">{name}<br>{summary}</div></a></div>"#,
+            detections = escape_html(&detections_json),
+            error = if image.error.is_some() {
+                " data-error"
+            } else {
+                ""
+            },
+            page = page_name(index),
+            src = escape_html(&image_url(&image.file, output_dir)),
+            name = escape_html(&name),
+        )?;
+    }
+
+    writeln!(html, "</main>")?;
+    write_footer(&mut html, "")?;
+    Ok(html)
+}
+
+/// Render the page for a single image with its detections overlaid
+fn render_image_page(
+    results: &[CamTrapImageDetections],
+    index: usize,
+    base_dir: &Path,
+    pages_dir: &Path,
+) -> Result<String, std::fmt::Error> {
+    let image = &results[index];
+    let name = relative_name(&image.file, base_dir);
+
+    let mut nav = String::from(r#"<a data-nav="index" href="../index.html">Index</a>"#);
+    if index > 0 {
+        write!(
+            nav,
+            r#" <a data-nav="prev" href="{}">&larr; Previous</a>"#,
+            page_name(index - 1)
+        )?;
+    }
+    if index + 1 < results.len() {
+        write!(
+            nav,
+            r#" <a data-nav="next" href="{}">Next &rarr;</a>"#,
+            page_name(index + 1)
+        )?;
+    }
+
+    let mut html = String::new();
+    write_header(&mut html, &name, &nav, "../")?;
+    writeln!(html, r#"<main><div class="viewer">"#)?;
+    writeln!(
+        html,
+        r#"<img src="{}" alt="{}">"#,
+        escape_html(&image_url(&image.file, pages_dir)),
+        escape_html(&name),
+    )?;
+
+    if let (Some(width), Some(height)) = (image.image_width, image.image_height) {
+        writeln!(
+            html,
+            r#"<svg viewBox="0 0 {width} {height}" preserveAspectRatio="none">"#
+        )?;
+        for detection in &image.detections {
+            let category = category_name(detection);
+            writeln!(
+                html,
+                r#"<g data-box data-category="{category}" data-confidence="{confidence}" class="category-{category}"><rect x="{x}" y="{y}" width="{w}" height="{h}"/><text x="{x}" y="{y}" dy="-4">{category} {confidence:.2}</text></g>"#,
+                confidence = detection.confidence,
+                x = detection.x,
+                y = detection.y,
+                w = detection.width,
+                h = detection.height,
+            )?;
+        }
+        writeln!(html, "</svg>")?;
+    }
+
+    writeln!(html, "</div>")?;
+    write!(html, r#"<p class="details">"#)?;
+    match &image.error {
+        Some(error) => write!(html, r#"<span class="error">{}</span>"#, escape_html(error))?,
+        None => write!(
+            html,
+            "{} detections &middot; &larr;/&rarr; to navigate, Esc for the index",
+            image.detections.len()
+        )?,
+    }
+    writeln!(html, "</p></main>")?;
+    write_footer(&mut html, "../")?;
+    Ok(html)
+}
+
+/// The path of an image relative to the base directory, for display
+fn relative_name(file: &str, base_dir: &Path) -> String {
+    pathdiff::diff_paths(file, base_dir)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.to_string())
+}
+
+/// Export a static HTML gallery of the results into the output directory
+pub fn export_html(
+    results: &[CamTrapImageDetections],
+    base_dir: PathBuf,
+    output_dir: PathBuf,
+) -> Result<()> {
+    let pages_dir = output_dir.join("images");
+    std::fs::create_dir_all(&pages_dir)?;
+
+    std::fs::write(output_dir.join("gallery.css"), GALLERY_CSS)?;
+    std::fs::write(output_dir.join("gallery.js"), GALLERY_JS)?;
+    std::fs::write(
+        output_dir.join("index.html"),
+        render_index(results, &base_dir, &output_dir).map_err(std::io::Error::other)?,
+    )?;
+
+    for index in 0..results.len() {
+        std::fs::write(
+            pages_dir.join(page_name(index)),
+            render_image_page(results, index, &base_dir, &pages_dir)
+                .map_err(std::io::Error::other)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{DetectionSource, ReviewStatus};
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_image_url() {
+        assert_eq!(
+            image_url("/data/site a/IMG#1.JPG", Path::new("/data/gallery/images")),
+            "../../site%20a/IMG%231.JPG"
+        );
+    }
+
+    #[test]
+    fn test_export_html() {
+        let output_dir = std::env::temp_dir().join("camtrap-html-gallery");
+        let base_dir = output_dir.parent().unwrap().to_path_buf();
+
+        let results = vec![
+            CamTrapImageDetections {
+                file: base_dir.join("a.jpg").to_str().unwrap().to_string(),
+                error: None,
+                image_width: Some(640),
+                image_height: Some(480),
+                detections: vec![CamTrapDetection {
+                    x: 10.0,
+                    y: 20.0,
+                    width: 30.0,
+                    height: 40.0,
+                    class_index: 1,
+                    confidence: 0.9,
+                    source: DetectionSource::Model,
+                    review: ReviewStatus::Unreviewed,
+                }],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections {
+                file: base_dir.join("b.jpg").to_str().unwrap().to_string(),
+                error: Some(String::from("Unreadable <image>")),
+                image_width: None,
+                image_height: None,
+                detections: vec![],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+        ];
+
+        export_html(&results, base_dir, output_dir.clone()).unwrap();
+
+        let index = std::fs::read_to_string(output_dir.join("index.html")).unwrap();
+        assert!(index.contains(r#"href="images/000001.html""#));
+        assert!(index.contains(r#"src="../a.jpg""#));
+        assert!(index.contains("Unreadable &lt;image&gt;"));
+
+        let first = std::fs::read_to_string(output_dir.join("images/000001.html")).unwrap();
+        assert!(first.contains(r#"src="../../a.jpg""#));
+        assert!(first.contains(r#"viewBox="0 0 640 480""#));
+        assert!(first.contains(r#"data-category="Human""#));
+        assert!(first.contains(r#"data-nav="next" href="000002.html""#));
+        assert!(!first.contains(r#"data-nav="prev""#));
+
+        let second = std::fs::read_to_string(output_dir.join("images/000002.html")).unwrap();
+        assert!(second.contains(r#"data-nav="prev" href="000001.html""#));
+        assert!(!second.contains("<svg"));
+
+        assert!(output_dir.join("gallery.js").exists());
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
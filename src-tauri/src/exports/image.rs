@@ -1,8 +1,12 @@
+use crate::megadetector::Category;
 use crate::structures::{CamTrapDetection, CamTrapImageDetections};
 use crate::util::magic_image::MagicImage;
+use crate::Result;
 use image::Rgba;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 #[derive(Serialize, Deserialize)]
 pub enum IncludeCriteria {
@@ -20,17 +24,17 @@ pub struct FilterCriteria {
 }
 
 /// Check if an image matches the filter criteria
-fn match_criteria(image: &CamTrapImageDetections, criteria: &FilterCriteria) -> bool {
+pub(crate) fn match_criteria(image: &CamTrapImageDetections, criteria: &FilterCriteria) -> bool {
     let mut has_animals = false;
     let mut has_humans = false;
     let mut has_vehicles = false;
     let has_empty = image.detections.is_empty();
 
     for detection in &image.detections {
-        match detection.class_index {
-            0 => has_animals = true,
-            1 => has_humans = true,
-            2 => has_vehicles = true,
+        match Category::from_model_index(detection.class_index) {
+            Some(Category::ANIMAL) => has_animals = true,
+            Some(Category::HUMAN) => has_humans = true,
+            Some(Category::VEHICLE) => has_vehicles = true,
             _ => {}
         }
     }
@@ -79,71 +83,315 @@ pub struct DrawCriteria {
     animals: bool,
     humans: bool,
     vehicles: bool,
+
+    /// JPEG quality (1-100) used when an image has to be re-encoded, defaults to the encoder's
+    /// default quality when not set.
+    #[serde(default)]
+    jpeg_quality: Option<u8>,
+
+    /// Outline thickness as a fraction of the image's shorter side, so boxes remain visible on
+    /// large images. Outlines are always at least 1 pixel wide.
+    #[serde(default = "default_line_thickness")]
+    line_thickness: f32,
+
+    /// Draw the category and confidence above each box
+    #[serde(default)]
+    labels: bool,
+
+    /// Colours to draw each category with
+    #[serde(default)]
+    colors: CategoryColors,
+
+    /// Opacity (0-1) of the fill drawn inside each box, no fill is drawn when 0
+    #[serde(default)]
+    fill_opacity: f32,
+
+    /// Regions of the image to obscure for privacy
+    #[serde(default)]
+    redact: RedactCriteria,
+}
+
+fn default_line_thickness() -> f32 {
+    0.003
+}
+
+/// RGB colours used to draw each category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryColors {
+    pub animals: [u8; 3],
+    pub humans: [u8; 3],
+    pub vehicles: [u8; 3],
+}
+
+impl Default for CategoryColors {
+    fn default() -> Self {
+        Self {
+            animals: Category::ANIMAL.color,
+            humans: Category::HUMAN.color,
+            vehicles: Category::VEHICLE.color,
+        }
+    }
+}
+
+impl CategoryColors {
+    /// The colour for a detection's class
+    fn get(&self, class_index: u32) -> Rgba<u8> {
+        let [r, g, b] = match Category::from_model_index(class_index) {
+            Some(Category::ANIMAL) => self.animals,
+            Some(Category::HUMAN) => self.humans,
+            Some(Category::VEHICLE) => self.vehicles,
+            _ => [0, 0, 0],
+        };
+        Rgba([r, g, b, 255])
+    }
+}
+
+/// How detections are obscured
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum RedactMethod {
+    #[default]
+    Blur,
+    Pixelate,
+}
+
+/// Which detections to obscure and how, so people and number plates are not published
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedactCriteria {
+    humans: bool,
+    vehicles: bool,
+
+    #[serde(default)]
+    method: RedactMethod,
+
+    /// Padding added to each side of a detection as a fraction of its width/height
+    #[serde(default)]
+    padding: f32,
+
+    /// Remove GPS location, camera/lens serial numbers and the maker note from the EXIF data
+    #[serde(default)]
+    strip_private_exif: bool,
+}
+
+/// Check if a detection should be redacted
+fn should_redact(detection: &CamTrapDetection, criteria: &RedactCriteria) -> bool {
+    match Category::from_model_index(detection.class_index) {
+        Some(Category::HUMAN) => criteria.humans,
+        Some(Category::VEHICLE) => criteria.vehicles,
+        _ => false,
+    }
+}
+
+/// The region of a detection to redact, grown by the padding on each side
+fn redact_region(detection: &CamTrapDetection, padding: f32) -> (i32, i32, u32, u32) {
+    let pad_x = detection.width * padding.max(0.0);
+    let pad_y = detection.height * padding.max(0.0);
+
+    (
+        (detection.x - pad_x).floor() as i32,
+        (detection.y - pad_y).floor() as i32,
+        (detection.width + pad_x * 2.0).ceil() as u32,
+        (detection.height + pad_y * 2.0).ceil() as u32,
+    )
+}
+
+/// Pick black or white text, whichever is more legible on the background
+fn label_text_color(background: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b, _] = background.0;
+    let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    if luminance > 150.0 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
 }
 
 /// Check if a detection should be drawn
 fn should_draw(detection: &CamTrapDetection, criteria: &DrawCriteria) -> bool {
-    match detection.class_index {
-        0 => criteria.animals,
-        1 => criteria.humans,
-        2 => criteria.vehicles,
+    match Category::from_model_index(detection.class_index) {
+        Some(Category::ANIMAL) => criteria.animals,
+        Some(Category::HUMAN) => criteria.humans,
+        Some(Category::VEHICLE) => criteria.vehicles,
         _ => false,
     }
 }
 
+/// Check if an image will be modified by drawing or redacting any of its detections
+///
+/// When nothing is modified the original file can be copied as-is rather than being re-encoded.
+fn modifies_image(image: &CamTrapImageDetections, criteria: &DrawCriteria) -> bool {
+    criteria.redact.strip_private_exif
+        || image.detections.iter().any(|detection| {
+            should_draw(detection, criteria) || should_redact(detection, &criteria.redact)
+        })
+}
+
+/// A single image that could not be exported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageExportFailure {
+    /// The file path of the source image
+    pub file: String,
+
+    /// Why the image could not be exported
+    pub error: String,
+}
+
+/// The outcome of an image export
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageExportReport {
+    /// The number of images written to the output directory
+    pub exported: usize,
+
+    /// The images that could not be exported
+    pub failures: Vec<ImageExportFailure>,
+
+    /// Whether the export was cancelled before all images were written
+    pub cancelled: bool,
+}
+
+/// Export a single image into the output directory, drawing any matching detections
+fn export_single_image(
+    image_meta: &CamTrapImageDetections,
+    base_dir: &Path,
+    output_dir: &Path,
+    draw_criteria: &DrawCriteria,
+) -> Result<()> {
+    let image_rel_path = pathdiff::diff_paths(&image_meta.file, base_dir).ok_or_else(|| {
+        crate::Error::InvalidInput(String::from(
+            "Unable to make the image path relative to the base directory",
+        ))
+    })?;
+
+    let out_image_path = output_dir.join(image_rel_path);
+
+    // Create directory / parents if they don't exist
+    if let Some(out_image_dir) = out_image_path.parent() {
+        std::fs::create_dir_all(out_image_dir)?;
+    }
+
+    // Copy the original bytes when there is nothing to change to avoid a lossy re-encode
+    if !modifies_image(image_meta, draw_criteria) {
+        std::fs::copy(&image_meta.file, &out_image_path)?;
+        return Ok(());
+    }
+
+    let mut image = MagicImage::open(&image_meta.file)?;
+
+    let shorter_side = image.width().min(image.height()) as f32;
+    let thickness = (shorter_side * draw_criteria.line_thickness).round() as u32;
+    let font_size = (shorter_side * 0.025).max(12.0);
+
+    // Redact before drawing so the boxes and labels remain visible on top
+    let redact = &draw_criteria.redact;
+    let mut redacted = false;
+    for detection in &image_meta.detections {
+        if !should_redact(detection, redact) {
+            continue;
+        }
+
+        let (x, y, width, height) = redact_region(detection, redact.padding);
+        // Scale the strength to the region so faces and plates are unreadable at any size
+        let strength = (width.max(height) as f32 / 10.0).max(8.0);
+        match redact.method {
+            RedactMethod::Blur => image.blur_region(x, y, width, height, strength),
+            RedactMethod::Pixelate => image.pixelate_region(x, y, width, height, strength as u32),
+        }
+        redacted = true;
+    }
+
+    if redacted {
+        image.strip_exif_thumbnail()?;
+    }
+    if redact.strip_private_exif {
+        image.strip_private_exif()?;
+    }
+
+    for detection in &image_meta.detections {
+        if !should_draw(detection, draw_criteria) {
+            continue;
+        }
+
+        let color = draw_criteria.colors.get(detection.class_index);
+        let (x, y) = (detection.x as i32, detection.y as i32);
+        let (width, height) = (detection.width as u32, detection.height as u32);
+
+        if draw_criteria.fill_opacity > 0.0 {
+            let mut fill = color;
+            fill.0[3] = (draw_criteria.fill_opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+            image.fill_rect(x, y, width, height, fill);
+        }
+
+        image.draw_bounding_box(x, y, width, height, thickness, color);
+
+        if draw_criteria.labels {
+            let category = Category::name_of(detection.class_index);
+            let label = format!("{} {:.2}", category, detection.confidence);
+            image.draw_label(x, y, &label, font_size, color, label_text_color(color));
+        }
+    }
+
+    image.save(out_image_path, draw_criteria.jpeg_quality)
+}
+
+/// Export the images matching the filter criteria into the output directory
+///
+/// Images are exported in parallel. Failures are collected into the returned report rather than
+/// aborting the export. `on_progress` is called with the number of images handled so far, the
+/// total and the file just handled. Setting `cancel` stops any images not yet started from being
+/// exported.
 pub fn export_image(
-    results: Vec<CamTrapImageDetections>,
+    results: &[CamTrapImageDetections],
     base_dir: PathBuf,
     output_dir: PathBuf,
     filter_criteria: FilterCriteria,
     draw_criteria: DrawCriteria,
-) -> Result<(), Box<dyn std::error::Error>> {
+    cancel: &AtomicBool,
+    on_progress: impl Fn(usize, usize, &str) + Sync,
+) -> ImageExportReport {
     // Filter out images that don't match the criteria
-    let results: Vec<_> = results
-        .into_iter()
+    let results: Vec<&CamTrapImageDetections> = results
+        .iter()
         .filter(|image| match_criteria(image, &filter_criteria))
         .collect();
 
-    // TODO: Parallelisee this
-    for image_meta in results.iter() {
-        let mut image = MagicImage::open(&image_meta.file).unwrap();
-
-        for detection in &image_meta.detections {
-            if should_draw(detection, &draw_criteria) {
-                let color = match detection.class_index {
-                    0 => Rgba([255, 255, 255, 255]),
-                    1 => Rgba([255, 0, 0, 255]),
-                    2 => Rgba([0, 0, 255, 255]),
-                    _ => Rgba([0, 0, 0, 255]),
-                };
-
-                image.draw_bounding_box(
-                    (detection.x) as i32,
-                    (detection.y) as i32,
-                    (detection.width) as u32,
-                    (detection.height) as u32,
-                    color,
-                );
+    let total = results.len();
+    let handled = AtomicUsize::new(0);
+
+    let outcomes: Vec<Option<Result<(), ImageExportFailure>>> = results
+        .par_iter()
+        .map(|image_meta| {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
             }
-        }
 
-        let image_rel_path = pathdiff::diff_paths(&image_meta.file, &base_dir).unwrap();
+            let outcome = export_single_image(image_meta, &base_dir, &output_dir, &draw_criteria)
+                .map_err(|err| ImageExportFailure {
+                    file: image_meta.file.clone(),
+                    error: err.to_string(),
+                });
 
-        let out_image_path = output_dir.join(image_rel_path);
-        let out_image_dir = out_image_path.parent().unwrap();
+            let current = handled.fetch_add(1, Ordering::Relaxed) + 1;
+            on_progress(current, total, &image_meta.file);
 
-        // Create directory / parents if they don't exist
-        std::fs::create_dir_all(out_image_dir).unwrap();
+            Some(outcome)
+        })
+        .collect();
 
-        image.save(out_image_path).unwrap();
+    let mut report = ImageExportReport::default();
+    for outcome in outcomes {
+        match outcome {
+            Some(Ok(())) => report.exported += 1,
+            Some(Err(failure)) => report.failures.push(failure),
+            None => report.cancelled = true,
+        }
     }
 
-    Ok(())
+    report
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structures::{DetectionSource, ReviewStatus};
 
     fn create_image(class_indexes: Vec<u32>) -> CamTrapImageDetections {
         CamTrapImageDetections {
@@ -157,11 +405,16 @@ mod tests {
                     width: 0.0,
                     height: 0.0,
                     confidence: 1.0,
+                    source: DetectionSource::Model,
+                    review: ReviewStatus::Unreviewed,
                 })
                 .collect(),
             error: None,
             image_width: None,
             image_height: None,
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
         }
     }
 
@@ -262,4 +515,142 @@ mod tests {
         assert!(!match_criteria(&human_and_vehicle_image, &criteria));
         assert!(!match_criteria(&animal_human_and_vehicle_image, &criteria));
     }
+
+    #[test]
+    fn test_modifies_image() {
+        let criteria = DrawCriteria {
+            animals: true,
+            humans: false,
+            vehicles: false,
+            jpeg_quality: None,
+            line_thickness: default_line_thickness(),
+            labels: false,
+            colors: CategoryColors::default(),
+            fill_opacity: 0.0,
+            redact: RedactCriteria::default(),
+        };
+
+        assert!(modifies_image(&create_image(vec![0]), &criteria));
+        assert!(modifies_image(&create_image(vec![1, 0]), &criteria));
+        assert!(!modifies_image(&create_image(vec![1, 2]), &criteria));
+        assert!(!modifies_image(&create_image(vec![]), &criteria));
+
+        let criteria = DrawCriteria {
+            redact: RedactCriteria {
+                humans: true,
+                ..Default::default()
+            },
+            ..criteria
+        };
+
+        assert!(modifies_image(&create_image(vec![1]), &criteria));
+        assert!(!modifies_image(&create_image(vec![2]), &criteria));
+
+        let criteria = DrawCriteria {
+            redact: RedactCriteria {
+                strip_private_exif: true,
+                ..Default::default()
+            },
+            ..criteria
+        };
+
+        assert!(modifies_image(&create_image(vec![]), &criteria));
+    }
+
+    #[test]
+    fn test_redact_region() {
+        let detection = CamTrapDetection {
+            class_index: 1,
+            x: 10.0,
+            y: 20.0,
+            width: 100.0,
+            height: 50.0,
+            confidence: 1.0,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+
+        assert_eq!(redact_region(&detection, 0.0), (10, 20, 100, 50));
+        assert_eq!(redact_region(&detection, 0.1), (0, 15, 120, 60));
+    }
+
+    fn all_criteria() -> (FilterCriteria, DrawCriteria) {
+        (
+            FilterCriteria {
+                animals: IncludeCriteria::Include,
+                humans: IncludeCriteria::Include,
+                vehicles: IncludeCriteria::Include,
+                empty: IncludeCriteria::Include,
+            },
+            DrawCriteria {
+                animals: true,
+                humans: true,
+                vehicles: true,
+                jpeg_quality: None,
+                line_thickness: default_line_thickness(),
+                labels: true,
+                colors: CategoryColors::default(),
+                fill_opacity: 0.25,
+                redact: RedactCriteria {
+                    humans: true,
+                    vehicles: true,
+                    method: RedactMethod::Pixelate,
+                    padding: 0.1,
+                    strip_private_exif: true,
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn test_export_image_collects_failures() {
+        let base_dir = std::env::temp_dir().join("camtrap-export-image-failures");
+        let output_dir = base_dir.join("output");
+        std::fs::create_dir_all(&base_dir).unwrap();
+
+        let good_path = base_dir.join("good.png");
+        image::RgbImage::new(8, 8).save(&good_path).unwrap();
+
+        let mut good = create_image(vec![0]);
+        good.file = good_path.to_str().unwrap().to_string();
+        let mut missing = create_image(vec![0]);
+        missing.file = base_dir.join("missing.png").to_str().unwrap().to_string();
+
+        let (filter_criteria, draw_criteria) = all_criteria();
+        let report = export_image(
+            &[good, missing],
+            base_dir.clone(),
+            output_dir.clone(),
+            filter_criteria,
+            draw_criteria,
+            &AtomicBool::new(false),
+            |_, _, _| {},
+        );
+
+        assert_eq!(report.exported, 1);
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].file.ends_with("missing.png"));
+        assert!(!report.cancelled);
+        assert!(output_dir.join("good.png").exists());
+
+        std::fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_image_cancelled() {
+        let (filter_criteria, draw_criteria) = all_criteria();
+        let report = export_image(
+            &[create_image(vec![0]), create_image(vec![])],
+            PathBuf::from("."),
+            std::env::temp_dir().join("camtrap-export-image-cancelled"),
+            filter_criteria,
+            draw_criteria,
+            &AtomicBool::new(true),
+            |_, _, _| panic!("no images should be exported once cancelled"),
+        );
+
+        assert_eq!(report.exported, 0);
+        assert!(report.failures.is_empty());
+        assert!(report.cancelled);
+    }
 }
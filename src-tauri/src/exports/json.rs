@@ -1,9 +1,23 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{megadetector::CATEGORIES, structures};
+use super::coordinates::{CoordinateOptions, CoordinateSystem};
+use crate::{
+    megadetector::{Category, CATEGORIES},
+    metadata::ImageMetadata,
+    structures, Result,
+};
+use std::io::{Read, Write};
+use std::path::Path;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CamTrapJSONContainer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<structures::RunInfo>,
+
+    /// How the boxes of the detections are written, pixels in exports from older versions
+    #[serde(default)]
+    pub coordinates: CoordinateSystem,
+
     pub images: Vec<CamTrapJSONImageDetections>,
     pub categories: Vec<CamTrapJSONCategory>,
 }
@@ -17,17 +31,32 @@ pub struct CamTrapJSONCategory {
 impl CamTrapJSONContainer {
     pub fn new(images: Vec<CamTrapJSONImageDetections>) -> Self {
         CamTrapJSONContainer {
+            info: None,
+            coordinates: CoordinateSystem::Pixels,
             images,
-            categories: CATEGORIES
-                .iter()
-                .enumerate()
-                .map(|(i, c)| CamTrapJSONCategory {
-                    name: c.to_string(),
-                    id: i,
-                })
-                .collect(),
+            categories: json_categories(),
         }
     }
+
+    /// The images, with the boxes of their detections converted back to pixels
+    pub fn into_detections(self) -> Vec<structures::CamTrapImageDetections> {
+        let coordinates = self.coordinates;
+
+        self.images
+            .into_iter()
+            .map(|image| image.into_pixels(coordinates))
+            .collect()
+    }
+}
+
+fn json_categories() -> Vec<CamTrapJSONCategory> {
+    CATEGORIES
+        .iter()
+        .map(|category| CamTrapJSONCategory {
+            name: category.name.to_string(),
+            id: category.id as usize,
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +70,15 @@ pub struct CamTrapJSONImageDetections {
     pub image_height: Option<u32>,
 
     pub detections: Vec<CamTrapJSONDetection>,
+
+    #[serde(default)]
+    pub review: structures::ReviewStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp: Option<structures::FileStamp>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ImageMetadata>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +89,12 @@ pub struct CamTrapJSONDetection {
     pub height: f32,
     pub category: u32,
     pub confidence: f32,
+
+    #[serde(default)]
+    pub source: structures::DetectionSource,
+
+    #[serde(default)]
+    pub review: structures::ReviewStatus,
 }
 
 impl From<structures::CamTrapDetection> for CamTrapJSONDetection {
@@ -60,8 +104,10 @@ impl From<structures::CamTrapDetection> for CamTrapJSONDetection {
             y: yolo.y,
             width: yolo.width,
             height: yolo.height,
-            category: yolo.class_index + 1,
+            category: Category::id_of(yolo.class_index),
             confidence: yolo.confidence,
+            source: yolo.source,
+            review: yolo.review,
         }
     }
 }
@@ -70,10 +116,297 @@ impl From<structures::CamTrapImageDetections> for CamTrapJSONImageDetections {
     fn from(yolo: structures::CamTrapImageDetections) -> Self {
         CamTrapJSONImageDetections {
             file: yolo.file,
-            error: None,
+            error: yolo.error,
             image_width: yolo.image_width,
             image_height: yolo.image_height,
             detections: yolo.detections.into_iter().map(|d| d.into()).collect(),
+            review: yolo.review,
+            stamp: yolo.stamp,
+            metadata: yolo.metadata,
+        }
+    }
+}
+
+impl From<CamTrapJSONDetection> for structures::CamTrapDetection {
+    fn from(json: CamTrapJSONDetection) -> Self {
+        Self {
+            x: json.x,
+            y: json.y,
+            width: json.width,
+            height: json.height,
+            class_index: Category::model_index_of(json.category),
+            confidence: json.confidence,
+            source: json.source,
+            review: json.review,
+        }
+    }
+}
+
+impl From<CamTrapJSONImageDetections> for structures::CamTrapImageDetections {
+    fn from(json: CamTrapJSONImageDetections) -> Self {
+        Self {
+            file: json.file,
+            error: json.error,
+            image_width: json.image_width,
+            image_height: json.image_height,
+            detections: json.detections.into_iter().map(|d| d.into()).collect(),
+            review: json.review,
+            stamp: json.stamp,
+            metadata: json.metadata,
+        }
+    }
+}
+
+impl CamTrapJSONImageDetections {
+    /// Convert the results of an image, with the boxes of its detections in the given coordinate
+    /// system
+    pub fn with_coordinates(
+        image: structures::CamTrapImageDetections,
+        coordinates: &CoordinateOptions,
+    ) -> Self {
+        let (width, height) = (
+            image.image_width.unwrap_or(0),
+            image.image_height.unwrap_or(0),
+        );
+        let boxes: Vec<[f32; 4]> = image
+            .detections
+            .iter()
+            .map(|detection| coordinates.convert(detection, width, height))
+            .collect();
+
+        let mut json: Self = image.into();
+        for (detection, values) in json.detections.iter_mut().zip(boxes) {
+            [detection.x, detection.y, detection.width, detection.height] = values;
+        }
+        json
+    }
+
+    /// Convert back to the results of an image, with the boxes of its detections converted from
+    /// the given coordinate system to pixels
+    pub fn into_pixels(self, system: CoordinateSystem) -> structures::CamTrapImageDetections {
+        let mut image: structures::CamTrapImageDetections = self.into();
+        let (width, height) = (
+            image.image_width.unwrap_or(0),
+            image.image_height.unwrap_or(0),
+        );
+        for detection in &mut image.detections {
+            let values = [detection.x, detection.y, detection.width, detection.height];
+            [detection.x, detection.y, detection.width, detection.height] =
+                system.to_pixels(values, width, height);
         }
+        image
+    }
+}
+
+/// Write results as JSON, with the category names and how they were produced if known
+///
+/// The boxes of the detections are written in the given coordinate system. The images are
+/// written as they are iterated, one per line, so large sets need not be held in memory twice.
+pub fn write_json(
+    results: impl IntoIterator<Item = structures::CamTrapImageDetections>,
+    info: Option<&structures::RunInfo>,
+    coordinates: &CoordinateOptions,
+    mut writer: impl Write,
+) -> Result<()> {
+    writeln!(writer, "{{")?;
+    if let Some(info) = info {
+        writeln!(writer, "  \"info\": {},", serde_json::to_string(info)?)?;
+    }
+    writeln!(
+        writer,
+        "  \"coordinates\": {},",
+        serde_json::to_string(&coordinates.system)?
+    )?;
+    writeln!(
+        writer,
+        "  \"categories\": {},",
+        serde_json::to_string(&json_categories())?
+    )?;
+
+    write!(writer, "  \"images\": [")?;
+    for (i, image) in results.into_iter().enumerate() {
+        let image = CamTrapJSONImageDetections::with_coordinates(image, coordinates);
+        let separator = if i == 0 { "" } else { "," };
+        write!(
+            writer,
+            "{}\n    {}",
+            separator,
+            serde_json::to_string(&image)?
+        )?;
+    }
+    writeln!(writer, "\n  ]\n}}")?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Results read back from a JSON export
+pub type JsonImport = (
+    Vec<structures::CamTrapImageDetections>,
+    Option<structures::RunInfo>,
+);
+
+/// Read results written by [`write_json`], with their paths joined onto `base_dir`
+///
+/// Also returns how the results were produced, if the export recorded it.
+pub fn read_json(reader: impl Read, base_dir: &Path) -> Result<JsonImport> {
+    let mut container: CamTrapJSONContainer = serde_json::from_reader(reader)?;
+    let info = container.info.take();
+
+    let images = container
+        .into_detections()
+        .into_iter()
+        .map(|mut image| {
+            image.file = base_dir.join(&image.file).to_string_lossy().to_string();
+            image
+        })
+        .collect();
+    Ok((images, info))
+}
+
+/// Read a JSON export file, with the image paths joined onto `base_dir`
+pub fn import_json(path: &Path, base_dir: &Path) -> Result<JsonImport> {
+    read_json(
+        std::io::BufReader::new(std::fs::File::open(path)?),
+        base_dir,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{
+        CamTrapDetection, CamTrapImageDetections, DetectionSource, ReviewStatus,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_json_roundtrip() {
+        let mut results = vec![
+            CamTrapImageDetections {
+                file: String::from("site/a.jpg"),
+                error: None,
+                image_width: Some(10),
+                image_height: Some(10),
+                detections: vec![CamTrapDetection {
+                    x: 1.0,
+                    y: 2.0,
+                    width: 3.0,
+                    height: 4.0,
+                    class_index: 2,
+                    confidence: 0.5,
+                    source: DetectionSource::Human,
+                    review: ReviewStatus::Verified,
+                }],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections::failed(Path::new("site/b.jpg"), "Unreadable"),
+        ];
+
+        let info = structures::RunInfo {
+            app_version: String::from("2.0.0"),
+            model_file: String::from("md_v1000.0.0-redwood.onnx"),
+            model_sha256: String::from("ab12"),
+            input_size: (1280, 1280),
+            confidence_threshold: 0.2,
+            nms_threshold: 0.45,
+            execution_provider: String::from("cpu"),
+            inference: crate::yolov5::InferenceOptions {
+                tiling: None,
+                tta: Some(Default::default()),
+            },
+            started: 1_700_000_000,
+            finished: Some(1_700_000_060),
+            os: String::from("linux-x86_64"),
+            images: 2,
+        };
+        let metadata = ImageMetadata {
+            timestamp: Some(String::from("2023-06-01 21:14:05")),
+            temperature: Some(20.0),
+            ..Default::default()
+        };
+        results[0].metadata = Some(metadata.clone());
+
+        let mut output = vec![];
+        write_json(
+            results,
+            Some(&info),
+            &CoordinateOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+
+        let (read, read_info) = read_json(output.as_slice(), Path::new("/data")).unwrap();
+        assert_eq!(read_info, Some(info));
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(PathBuf::from(&read[0].file), Path::new("/data/site/a.jpg"));
+        assert_eq!(read[0].detections[0].class_index, 2);
+        assert_eq!(read[0].detections[0].source, DetectionSource::Human);
+        assert_eq!(read[0].metadata, Some(metadata));
+        assert_eq!(read[1].metadata, None);
+        assert_eq!(read[1].error.as_deref(), Some("Unreadable"));
+    }
+
+    #[test]
+    fn test_json_normalized_coordinates() {
+        let results = vec![CamTrapImageDetections {
+            file: String::from("a.jpg"),
+            error: None,
+            image_width: Some(200),
+            image_height: Some(100),
+            detections: vec![CamTrapDetection {
+                x: 10.0,
+                y: 20.0,
+                width: 30.0,
+                height: 40.0,
+                class_index: 0,
+                confidence: 0.5,
+                source: DetectionSource::Model,
+                review: ReviewStatus::Unreviewed,
+            }],
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        }];
+
+        let coordinates = CoordinateOptions {
+            system: CoordinateSystem::NormalizedCenter,
+            decimals: Some(3),
+        };
+        let mut output = vec![];
+        write_json(results, None, &coordinates, &mut output).unwrap();
+
+        let container: CamTrapJSONContainer = serde_json::from_slice(&output).unwrap();
+        assert_eq!(container.coordinates, CoordinateSystem::NormalizedCenter);
+        let detection = &container.images[0].detections[0];
+        assert_eq!(
+            [detection.x, detection.y, detection.width, detection.height],
+            [0.125, 0.4, 0.15, 0.4]
+        );
+
+        let (read, _) = read_json(output.as_slice(), Path::new("")).unwrap();
+        let detection = &read[0].detections[0];
+        assert!((detection.x - 10.0).abs() < 1e-4);
+        assert!((detection.y - 20.0).abs() < 1e-4);
+        assert!((detection.width - 30.0).abs() < 1e-4);
+        assert!((detection.height - 40.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_read_json_without_info() {
+        let json = r#"{"images": [], "categories": []}"#;
+        let (images, info) = read_json(json.as_bytes(), Path::new("/data")).unwrap();
+        assert!(images.is_empty());
+        assert_eq!(info, None);
+
+        // Older exports may record only some of the fields
+        let json = r#"{"info": {"model_file": "md.onnx"}, "images": [], "categories": []}"#;
+        let (_, info) = read_json(json.as_bytes(), Path::new("/data")).unwrap();
+        let info = info.unwrap();
+        assert_eq!(info.model_file, "md.onnx");
+        assert_eq!(info.finished, None);
     }
 }
@@ -0,0 +1,144 @@
+//! JSON Lines module
+//!
+//! Writes one image per line, in the same format as the images of a JSON export, for tools that
+//! process results line by line. How the results were produced is recorded in a sidecar file.
+//!
+
+use super::coordinates::{CoordinateOptions, CoordinateSystem};
+use super::json::{CamTrapJSONImageDetections, JsonImport};
+use super::sidecar::read_info_sidecar;
+use crate::structures::CamTrapImageDetections;
+use crate::Result;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Write results as JSON Lines, with the boxes of the detections in the given coordinate system
+///
+/// The results are written as they are iterated so large sets need not be held in memory twice.
+pub fn write_jsonl(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+    coordinates: &CoordinateOptions,
+    mut writer: impl Write,
+) -> Result<()> {
+    for image in results {
+        let image = CamTrapJSONImageDetections::with_coordinates(image, coordinates);
+        serde_json::to_writer(&mut writer, &image)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read results written by [`write_jsonl`] in the given coordinate system, with their paths
+/// joined onto `base_dir`
+///
+/// Blank lines are skipped, so a file still being appended to can be read.
+pub fn read_jsonl(
+    reader: impl BufRead,
+    system: CoordinateSystem,
+    base_dir: &Path,
+) -> Result<Vec<CamTrapImageDetections>> {
+    let mut images = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let json: CamTrapJSONImageDetections = serde_json::from_str(&line)?;
+        let mut image = json.into_pixels(system);
+        image.file = base_dir.join(&image.file).to_string_lossy().to_string();
+        images.push(image);
+    }
+
+    Ok(images)
+}
+
+/// Read a JSON Lines export file, with the image paths joined onto `base_dir`
+///
+/// The coordinate system of the boxes, and how the results were produced, are read from the
+/// sidecar file. Without one, e.g. for results appended while watching a folder, boxes are in
+/// pixels.
+pub fn import_jsonl(path: &Path, base_dir: &Path) -> Result<JsonImport> {
+    let sidecar = read_info_sidecar(path)?.unwrap_or_default();
+    let images = read_jsonl(
+        std::io::BufReader::new(std::fs::File::open(path)?),
+        sidecar.coordinates.system,
+        base_dir,
+    )?;
+    Ok((images, sidecar.info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exports::sidecar::{write_info_sidecar, ExportInfo};
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus, RunInfo};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_jsonl_roundtrip() {
+        let detection = CamTrapDetection {
+            x: 10.0,
+            y: 20.0,
+            width: 30.0,
+            height: 40.0,
+            class_index: 1,
+            confidence: 0.5,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+        let results = vec![
+            CamTrapImageDetections {
+                file: String::from("a.jpg"),
+                error: None,
+                image_width: Some(200),
+                image_height: Some(100),
+                detections: vec![detection],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections::failed(Path::new("b.jpg"), "Unreadable"),
+        ];
+
+        let coordinates = CoordinateOptions {
+            system: CoordinateSystem::Normalized,
+            decimals: None,
+        };
+        let mut output = vec![];
+        write_jsonl(results, &coordinates, &mut output).unwrap();
+
+        let text = String::from_utf8(output.clone()).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().next().unwrap().contains(r#""x":0.05"#));
+
+        let read = read_jsonl(output.as_slice(), coordinates.system, Path::new("/data")).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(PathBuf::from(&read[0].file), Path::new("/data/a.jpg"));
+        assert!((read[0].detections[0].x - 10.0).abs() < 1e-4);
+        assert_eq!(read[0].detections[0].class_index, 1);
+        assert_eq!(read[1].error.as_deref(), Some("Unreadable"));
+
+        // Imported with the coordinate system and run info from the sidecar
+        let dir = std::env::temp_dir().join("camtrap-export-jsonl");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.jsonl");
+        std::fs::write(&path, &output).unwrap();
+        let info = ExportInfo {
+            info: Some(RunInfo {
+                images: 2,
+                ..Default::default()
+            }),
+            coordinates,
+        };
+        write_info_sidecar(&info, &path).unwrap();
+
+        let (imported, run_info) = import_jsonl(&path, Path::new("/data")).unwrap();
+        assert!((imported[0].detections[0].y - 20.0).abs() < 1e-4);
+        assert_eq!(run_info, info.info);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
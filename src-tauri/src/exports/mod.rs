@@ -1,3 +1,108 @@
+pub mod contact_sheet;
+pub mod coordinates;
 pub mod csv;
+pub mod html;
 pub mod image;
 pub mod json;
+pub mod jsonl;
+pub mod parquet;
+pub mod sidecar;
+pub mod sqlite;
+pub mod summary;
+pub mod tables;
+
+#[cfg(test)]
+mod tests {
+    use super::coordinates::CoordinateOptions;
+    use super::csv::write_csv;
+    use super::image::{match_criteria, FilterCriteria};
+    use super::json::{read_json, write_json, CamTrapJSONContainer};
+    use crate::megadetector::Category;
+    use crate::structures::{
+        CamTrapDetection, CamTrapImageDetections, DetectionSource, ReviewStatus,
+    };
+    use std::path::Path;
+
+    fn image(class_index: Option<u32>) -> CamTrapImageDetections {
+        CamTrapImageDetections {
+            file: String::from("a.jpg"),
+            error: None,
+            image_width: Some(100),
+            image_height: Some(100),
+            detections: class_index
+                .map(|class_index| CamTrapDetection {
+                    x: 10.0,
+                    y: 10.0,
+                    width: 20.0,
+                    height: 20.0,
+                    class_index,
+                    confidence: 0.9,
+                    source: DetectionSource::Model,
+                    review: ReviewStatus::Unreviewed,
+                })
+                .into_iter()
+                .collect(),
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        }
+    }
+
+    /// Every exporter and filter names each category the same as the category table
+    #[test]
+    fn test_category_consistency() {
+        let categories = [
+            (Category::EMPTY, "empty"),
+            (Category::ANIMAL, "animals"),
+            (Category::HUMAN, "humans"),
+            (Category::VEHICLE, "vehicles"),
+        ];
+
+        for (category, filter) in categories {
+            let results = vec![image(category.model_index)];
+
+            let mut csv = vec![];
+            write_csv(results.clone(), &CoordinateOptions::default(), &mut csv).unwrap();
+            let csv = String::from_utf8(csv).unwrap();
+            let row: Vec<&str> = csv.lines().nth(1).unwrap().split(',').collect();
+            assert_eq!(row[8], category.name);
+
+            let mut json = vec![];
+            write_json(
+                results.clone(),
+                None,
+                &CoordinateOptions::default(),
+                &mut json,
+            )
+            .unwrap();
+            let container: CamTrapJSONContainer = serde_json::from_slice(&json).unwrap();
+            let json_category = |id: u32| {
+                container
+                    .categories
+                    .iter()
+                    .find(|json_category| json_category.id == id as usize)
+                    .map(|json_category| json_category.name.as_str())
+            };
+            assert_eq!(json_category(category.id), Some(category.name));
+            if let Some(detection) = container.images[0].detections.first() {
+                assert_eq!(detection.category, category.id);
+            }
+
+            let (read, _) = read_json(json.as_slice(), Path::new("")).unwrap();
+            assert_eq!(
+                read[0].detections.first().map(|d| d.class_index),
+                category.model_index
+            );
+
+            let mut criteria = serde_json::json!({
+                "animals": "Intersect",
+                "humans": "Intersect",
+                "vehicles": "Intersect",
+                "empty": "Intersect",
+            });
+            criteria[filter] = serde_json::json!("Include");
+            let criteria: FilterCriteria = serde_json::from_value(criteria).unwrap();
+            assert!(match_criteria(&results[0], &criteria), "{}", category.name);
+        }
+    }
+}
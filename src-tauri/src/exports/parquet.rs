@@ -0,0 +1,268 @@
+//! Parquet module
+//!
+//! Writes results to a folder of Parquet files, one per table of the normalized schema of the
+//! tables module, for loading into pandas, DuckDB or R.
+//!
+
+use super::tables::{
+    category_rows, image_rows, run_row, ColumnKind, Row, Table, Value, CATEGORIES_TABLE,
+    DETECTIONS, IMAGES, RUN,
+};
+use crate::structures::{CamTrapImageDetections, RunInfo};
+use crate::Result;
+use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The number of rows buffered before they are written as a row group
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// The Parquet schema of a table, with text columns annotated as UTF-8 strings
+fn schema(table: &Table) -> Result<Type> {
+    let fields = table
+        .columns
+        .iter()
+        .map(|column| {
+            let (physical, logical) = match column.kind {
+                ColumnKind::Integer => (PhysicalType::INT64, None),
+                ColumnKind::Real => (PhysicalType::DOUBLE, None),
+                ColumnKind::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            };
+            let repetition = if column.nullable {
+                Repetition::OPTIONAL
+            } else {
+                Repetition::REQUIRED
+            };
+
+            Ok(Arc::new(
+                Type::primitive_type_builder(column.name, physical)
+                    .with_repetition(repetition)
+                    .with_logical_type(logical)
+                    .build()?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Type::group_type_builder(table.name)
+        .with_fields(fields)
+        .build()?)
+}
+
+/// Writes the rows of a table to a Parquet file, a row group at a time
+struct TableWriter {
+    table: Table,
+    writer: SerializedFileWriter<File>,
+    rows: Vec<Row>,
+}
+
+impl TableWriter {
+    fn create(dir: &Path, table: Table) -> Result<Self> {
+        let file = File::create(dir.join(format!("{}.parquet", table.name)))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            SerializedFileWriter::new(file, Arc::new(schema(&table)?), Arc::new(properties))?;
+
+        Ok(Self {
+            table,
+            writer,
+            rows: vec![],
+        })
+    }
+
+    fn push(&mut self, row: Row) -> Result<()> {
+        self.rows.push(row);
+        if self.rows.len() >= ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered rows as a row group
+    fn flush(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut row_group = self.writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut writer) = row_group.next_column()? {
+            let column = self.table.columns[index];
+            let values: Vec<&Value> = self.rows.iter().map(|row| &row[index]).collect();
+
+            // Nulls are recorded by definition levels, 0 for null and 1 for present, and skipped
+            // from the values
+            let levels: Vec<i16> = values
+                .iter()
+                .map(|value| (**value != Value::Null) as i16)
+                .collect();
+            let levels = column.nullable.then_some(levels.as_slice());
+
+            match column.kind {
+                ColumnKind::Integer => {
+                    let values: Vec<i64> = values
+                        .iter()
+                        .filter_map(|value| match value {
+                            Value::Integer(value) => Some(*value),
+                            _ => None,
+                        })
+                        .collect();
+                    writer
+                        .typed::<Int64Type>()
+                        .write_batch(&values, levels, None)?;
+                }
+                ColumnKind::Real => {
+                    let values: Vec<f64> = values
+                        .iter()
+                        .filter_map(|value| match value {
+                            Value::Real(value) => Some(*value),
+                            _ => None,
+                        })
+                        .collect();
+                    writer
+                        .typed::<DoubleType>()
+                        .write_batch(&values, levels, None)?;
+                }
+                ColumnKind::Text => {
+                    let values: Vec<ByteArray> = values
+                        .iter()
+                        .filter_map(|value| match value {
+                            Value::Text(value) => Some(ByteArray::from(value.as_str())),
+                            _ => None,
+                        })
+                        .collect();
+                    writer
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, levels, None)?;
+                }
+            }
+
+            writer.close()?;
+            index += 1;
+        }
+
+        row_group.close()?;
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+/// Write results to a folder of Parquet files, `images.parquet`, `detections.parquet`,
+/// `categories.parquet` and `run.parquet`, creating the folder if needed
+///
+/// The rows are written as the results are iterated so large sets need not be held in memory
+/// twice. The run table is left empty when how the results were produced is not known.
+pub fn write_parquet(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+    info: Option<&RunInfo>,
+    dir: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut images = TableWriter::create(dir, IMAGES)?;
+    let mut detections = TableWriter::create(dir, DETECTIONS)?;
+    for rows in image_rows(results) {
+        images.push(rows.image)?;
+        for detection in rows.detections {
+            detections.push(detection)?;
+        }
+    }
+    images.close()?;
+    detections.close()?;
+
+    let mut categories = TableWriter::create(dir, CATEGORIES_TABLE)?;
+    for category in category_rows() {
+        categories.push(category)?;
+    }
+    categories.close()?;
+
+    let mut run = TableWriter::create(dir, RUN)?;
+    if let Some(info) = info {
+        run.push(run_row(info))?;
+    }
+    run.close()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    fn read(path: &Path) -> Vec<Vec<Field>> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .get_column_iter()
+                    .map(|(_, field)| field.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let results = vec![
+            CamTrapImageDetections {
+                file: String::from("a.jpg"),
+                error: None,
+                image_width: Some(10),
+                image_height: Some(10),
+                detections: vec![CamTrapDetection {
+                    x: 1.5,
+                    y: 2.0,
+                    width: 3.0,
+                    height: 4.0,
+                    class_index: 2,
+                    confidence: 0.5,
+                    source: DetectionSource::Human,
+                    review: ReviewStatus::Verified,
+                }],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections::failed(Path::new("b.jpg"), "Unreadable"),
+        ];
+
+        // A folder per process so concurrent test runs do not write to the same files
+        let dir =
+            std::env::temp_dir().join(format!("camtrap-parquet-export-{}", std::process::id()));
+        write_parquet(results, None, &dir).unwrap();
+
+        let images = read(&dir.join("images.parquet"));
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0][1], Field::Str(String::from("a.jpg")));
+        assert_eq!(images[0][2], Field::Null);
+        assert_eq!(images[1][2], Field::Str(String::from("Unreadable")));
+        assert_eq!(images[1][3], Field::Null);
+
+        let detections = read(&dir.join("detections.parquet"));
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0][1], Field::Long(1));
+        assert_eq!(detections[0][2], Field::Long(3));
+        assert_eq!(detections[0][3], Field::Double(1.5));
+        assert_eq!(detections[0][8], Field::Str(String::from("Human")));
+
+        assert_eq!(read(&dir.join("categories.parquet")).len(), 4);
+        assert!(read(&dir.join("run.parquet")).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+//! Sidecar module
+//!
+//! Records how results were produced, and how their boxes are written, next to exports whose
+//! format has no place for it, such as CSV and JSON Lines.
+//!
+
+use super::coordinates::CoordinateOptions;
+use crate::structures::RunInfo;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The contents of a sidecar file, the same as the `info` and `coordinates` of a JSON export
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportInfo {
+    /// How the results were produced, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<RunInfo>,
+
+    /// How the boxes of the detections are written, needed to read them back
+    pub coordinates: CoordinateOptions,
+}
+
+/// The sidecar file recording how an export was produced, e.g. `results.info.json` for
+/// `results.csv`
+pub fn info_sidecar_path(export_path: &Path) -> PathBuf {
+    export_path.with_extension("info.json")
+}
+
+/// Write how an export was produced to its sidecar file
+pub fn write_info_sidecar(info: &ExportInfo, export_path: &Path) -> Result<()> {
+    let file = std::fs::File::create(info_sidecar_path(export_path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, info)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read how an export was produced from its sidecar file, `None` if there is none
+pub fn read_info_sidecar(export_path: &Path) -> Result<Option<ExportInfo>> {
+    let path = info_sidecar_path(export_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = std::fs::File::open(path)?;
+    Ok(Some(serde_json::from_reader(std::io::BufReader::new(
+        file,
+    ))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exports::coordinates::CoordinateSystem;
+
+    #[test]
+    fn test_info_sidecar() {
+        let dir = std::env::temp_dir().join("camtrap-export-sidecar");
+        std::fs::create_dir_all(&dir).unwrap();
+        let export_path = dir.join("results.csv");
+        assert_eq!(
+            info_sidecar_path(&export_path),
+            dir.join("results.info.json")
+        );
+
+        let _ = std::fs::remove_file(info_sidecar_path(&export_path));
+        assert_eq!(read_info_sidecar(&export_path).unwrap(), None);
+
+        let info = ExportInfo {
+            info: Some(RunInfo {
+                model_file: String::from("md.onnx"),
+                images: 3,
+                ..Default::default()
+            }),
+            coordinates: CoordinateOptions {
+                system: CoordinateSystem::NormalizedCenter,
+                decimals: Some(4),
+            },
+        };
+        write_info_sidecar(&info, &export_path).unwrap();
+        assert_eq!(read_info_sidecar(&export_path).unwrap(), Some(info));
+    }
+}
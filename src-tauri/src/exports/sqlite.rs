@@ -0,0 +1,213 @@
+//! SQLite module
+//!
+//! Writes results to an SQLite database in the normalized schema of the tables module, with
+//! indices on the image file and detection category so queries over millions of detections are
+//! fast.
+//!
+
+use super::tables::{
+    category_rows, image_rows, run_row, ColumnKind, Table, Value, CATEGORIES_TABLE, DETECTIONS,
+    IMAGES, RUN, TABLES,
+};
+use crate::structures::{CamTrapImageDetections, RunInfo};
+use crate::Result;
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::{params_from_iter, Connection, ToSql};
+use std::path::Path;
+
+impl ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Borrowed(match self {
+            Value::Integer(value) => ValueRef::Integer(*value),
+            Value::Real(value) => ValueRef::Real(*value),
+            Value::Text(value) => ValueRef::Text(value.as_bytes()),
+            Value::Null => ValueRef::Null,
+        }))
+    }
+}
+
+fn create_table_sql(table: &Table) -> String {
+    let columns: Vec<String> = table
+        .columns
+        .iter()
+        .map(|column| {
+            let kind = match column.kind {
+                ColumnKind::Integer => "INTEGER",
+                ColumnKind::Real => "REAL",
+                ColumnKind::Text => "TEXT",
+            };
+            match (column.name, column.nullable) {
+                ("id", _) => format!("{} {} PRIMARY KEY", column.name, kind),
+                (_, true) => format!("{} {}", column.name, kind),
+                (_, false) => format!("{} {} NOT NULL", column.name, kind),
+            }
+        })
+        .collect();
+
+    format!("CREATE TABLE {} ({})", table.name, columns.join(", "))
+}
+
+fn insert_sql(table: &Table) -> String {
+    let names: Vec<&str> = table.columns.iter().map(|column| column.name).collect();
+    let placeholders = vec!["?"; names.len()];
+
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table.name,
+        names.join(", "),
+        placeholders.join(", ")
+    )
+}
+
+/// Write results to a new SQLite database, replacing any file at `path`
+///
+/// The rows are inserted as the results are iterated so large sets need not be held in memory
+/// twice. The run table is left empty when how the results were produced is not known.
+pub fn write_sqlite(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+    info: Option<&RunInfo>,
+    path: &Path,
+) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+
+    let mut connection = Connection::open(path)?;
+    let transaction = connection.transaction()?;
+
+    for table in &TABLES {
+        transaction.execute(&create_table_sql(table), [])?;
+    }
+
+    {
+        let mut insert_image = transaction.prepare(&insert_sql(&IMAGES))?;
+        let mut insert_detection = transaction.prepare(&insert_sql(&DETECTIONS))?;
+        for rows in image_rows(results) {
+            insert_image.execute(params_from_iter(&rows.image))?;
+            for detection in &rows.detections {
+                insert_detection.execute(params_from_iter(detection))?;
+            }
+        }
+
+        let mut insert_category = transaction.prepare(&insert_sql(&CATEGORIES_TABLE))?;
+        for category in category_rows() {
+            insert_category.execute(params_from_iter(&category))?;
+        }
+
+        if let Some(info) = info {
+            transaction
+                .prepare(&insert_sql(&RUN))?
+                .execute(params_from_iter(&run_row(info)))?;
+        }
+    }
+
+    // Indexing once the rows are inserted is faster than updating the indices for every row
+    for table in &TABLES {
+        for column in table.indexed {
+            transaction.execute(
+                &format!(
+                    "CREATE INDEX {table}_{column} ON {table} ({column})",
+                    table = table.name,
+                    column = column
+                ),
+                [],
+            )?;
+        }
+    }
+
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::megadetector::Category;
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus};
+
+    #[test]
+    fn test_write_sqlite() {
+        let detection = |class_index| CamTrapDetection {
+            x: 1.5,
+            y: 2.0,
+            width: 3.0,
+            height: 4.0,
+            class_index,
+            confidence: 0.5,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+        let results = vec![
+            CamTrapImageDetections {
+                file: String::from("site/a.jpg"),
+                error: None,
+                image_width: Some(10),
+                image_height: Some(10),
+                detections: vec![detection(0), detection(1), detection(1)],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections::failed(Path::new("site/b.jpg"), "Unreadable"),
+        ];
+        let info = RunInfo {
+            model_file: String::from("md.onnx"),
+            images: 2,
+            ..Default::default()
+        };
+
+        // A folder per process so concurrent test runs do not write to the same database
+        let dir =
+            std::env::temp_dir().join(format!("camtrap-sqlite-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.sqlite");
+        // Writing twice replaces the first database rather than failing on the existing tables
+        write_sqlite(results.clone(), None, &path).unwrap();
+        write_sqlite(results, Some(&info), &path).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let humans: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM detections
+                 JOIN images ON images.id = detections.image_id
+                 JOIN categories ON categories.id = detections.category_id
+                 WHERE images.file = 'site/a.jpg' AND categories.name = ?",
+                [Category::HUMAN.name],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(humans, 2);
+
+        let x: f64 = connection
+            .query_row("SELECT x FROM detections WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(x, 1.5);
+
+        let error: String = connection
+            .query_row("SELECT error FROM images WHERE id = 2", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(error, "Unreadable");
+
+        let model_file: String = connection
+            .query_row("SELECT model_file FROM run", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(model_file, "md.onnx");
+
+        let indices: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name IN
+                 ('images_file', 'detections_category_id')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(indices, 2);
+
+        drop(connection);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
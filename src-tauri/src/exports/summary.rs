@@ -0,0 +1,198 @@
+//! Summary module
+//!
+//! Writes one CSV row per image rather than per detection, with the number of detections and the
+//! highest confidence of each category, for occupancy analyses that count images. The same
+//! metadata columns as the CSV export follow, with the capture timestamp and camera model.
+//!
+
+use super::csv::METADATA_COLUMNS;
+use crate::megadetector::{model_categories, Category};
+use crate::metadata::ImageMetadata;
+use crate::structures::CamTrapImageDetections;
+use crate::Result;
+use std::io::Write;
+use std::path::Path;
+
+/// The header of the summary, with a count and max confidence column for each model category
+fn header() -> Vec<String> {
+    let mut header: Vec<String> = ["file", "error", "image_width", "image_height"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+
+    for category in model_categories() {
+        let name = category.name.to_lowercase();
+        header.push(format!("{}_count", name));
+        header.push(format!("{}_max_confidence", name));
+    }
+
+    header.extend(
+        ["top_category", "review"]
+            .iter()
+            .chain(&METADATA_COLUMNS)
+            .map(|column| column.to_string()),
+    );
+    header
+}
+
+/// The summary row of an image
+///
+/// The top category is the one with the most confident detection, `Empty` for images without
+/// detections and blank for images that failed to process.
+fn summary_row(image: &CamTrapImageDetections, metadata: ImageMetadata) -> Vec<String> {
+    let optional = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    let mut row = vec![
+        image.file.clone(),
+        image.error.clone().unwrap_or_default(),
+        optional(image.image_width),
+        optional(image.image_height),
+    ];
+
+    for category in model_categories() {
+        let confidences: Vec<f32> = image
+            .detections
+            .iter()
+            .filter(|detection| Category::from_model_index(detection.class_index) == Some(category))
+            .map(|detection| detection.confidence)
+            .collect();
+        let max_confidence = confidences.iter().copied().reduce(f32::max);
+
+        row.push(confidences.len().to_string());
+        row.push(max_confidence.map(|c| c.to_string()).unwrap_or_default());
+    }
+
+    let top_category = if image.error.is_some() {
+        String::new()
+    } else {
+        image
+            .detections
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .map_or(Category::EMPTY.name, |detection| {
+                Category::name_of(detection.class_index)
+            })
+            .to_string()
+    };
+
+    row.push(top_category);
+    row.push(format!("{:?}", image.review));
+    row.extend([
+        metadata.timestamp.unwrap_or_default(),
+        metadata.make.unwrap_or_default(),
+        metadata.model.unwrap_or_default(),
+        metadata.serial_number.unwrap_or_default(),
+        metadata
+            .temperature
+            .map(|t| t.to_string())
+            .unwrap_or_default(),
+        metadata
+            .moon_phase
+            .map(|phase| format!("{:?}", phase))
+            .unwrap_or_default(),
+        metadata
+            .trigger
+            .map(|trigger| format!("{:?}", trigger))
+            .unwrap_or_default(),
+        optional(metadata.sequence_index),
+        optional(metadata.sequence_length),
+    ]);
+    row
+}
+
+/// Write a CSV summary of results, one row per image
+///
+/// Results without metadata, e.g. from older versions, have it read from the EXIF data of the image
+/// at their path joined onto `base_dir`. Images that cannot be read, or have no EXIF data, are
+/// written with blank metadata columns. The results are written as they are iterated so large
+/// sets need not be held in memory twice.
+pub fn write_summary_csv(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+    base_dir: &Path,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(header())?;
+
+    for image in results {
+        let metadata = match &image.metadata {
+            Some(metadata) => metadata.clone(),
+            None => ImageMetadata::read(base_dir.join(&image.file))
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+        };
+        writer.write_record(summary_row(&image, metadata))?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus};
+
+    #[test]
+    fn test_write_summary_csv() {
+        let detection = |class_index, confidence| CamTrapDetection {
+            x: 1.0,
+            y: 2.0,
+            width: 3.0,
+            height: 4.0,
+            class_index,
+            confidence,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        };
+        let image = |file: &str, detections| CamTrapImageDetections {
+            file: file.to_string(),
+            error: None,
+            image_width: Some(10),
+            image_height: Some(20),
+            detections,
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        };
+        let mut results = vec![
+            image(
+                "a.jpg",
+                vec![detection(0, 0.5), detection(0, 0.75), detection(1, 0.9)],
+            ),
+            image("b.jpg", vec![]),
+            CamTrapImageDetections::failed(Path::new("c.jpg"), "Unreadable"),
+        ];
+
+        results[1].metadata = Some(ImageMetadata {
+            model: Some(String::from("HC600")),
+            trigger: Some(crate::metadata::Trigger::TimeLapse),
+            sequence_index: Some(1),
+            ..Default::default()
+        });
+
+        let mut output = vec![];
+        write_summary_csv(results, Path::new("/nonexistent"), &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "file,error,image_width,image_height,animal_count,animal_max_confidence,\
+             human_count,human_max_confidence,vehicle_count,vehicle_max_confidence,\
+             top_category,review,timestamp,camera_make,camera_model,camera_serial_number,\
+             temperature,moon_phase,trigger,sequence_index,sequence_length"
+        );
+        assert_eq!(
+            lines[1],
+            "a.jpg,,10,20,2,0.75,1,0.9,0,,Human,Unreviewed,,,,,,,,,"
+        );
+        assert_eq!(
+            lines[2],
+            "b.jpg,,10,20,0,,0,,0,,Empty,Unreviewed,,,HC600,,,,TimeLapse,1,"
+        );
+        assert_eq!(lines[3], "c.jpg,Unreadable,,,0,,0,,0,,,Unreviewed,,,,,,,,,");
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -0,0 +1,293 @@
+//! Tables module
+//!
+//! Describes results as normalized tables, one row per image, detection, category and run, for
+//! the database exports. Boxes are in pixels, with the image size alongside to normalize them.
+//!
+
+use crate::megadetector::{Category, CATEGORIES};
+use crate::structures::{CamTrapImageDetections, RunInfo};
+
+/// The type of the values of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Integer,
+    Real,
+    Text,
+}
+
+/// A column of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnKind,
+    pub nullable: bool,
+}
+
+const fn column(name: &'static str, kind: ColumnKind, nullable: bool) -> Column {
+    Column {
+        name,
+        kind,
+        nullable,
+    }
+}
+
+/// A table of the normalized schema, with the columns indexed for fast lookups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [Column],
+    pub indexed: &'static [&'static str],
+}
+
+/// A value of a row, in the same order as its table's columns
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Null,
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Real(value as f64)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+/// A row of a table
+pub type Row = Vec<Value>;
+
+pub const IMAGES: Table = Table {
+    name: "images",
+    columns: &[
+        column("id", ColumnKind::Integer, false),
+        column("file", ColumnKind::Text, false),
+        column("error", ColumnKind::Text, true),
+        column("width", ColumnKind::Integer, true),
+        column("height", ColumnKind::Integer, true),
+        column("review", ColumnKind::Text, false),
+    ],
+    indexed: &["file"],
+};
+
+pub const DETECTIONS: Table = Table {
+    name: "detections",
+    columns: &[
+        column("id", ColumnKind::Integer, false),
+        column("image_id", ColumnKind::Integer, false),
+        column("category_id", ColumnKind::Integer, false),
+        column("x", ColumnKind::Real, false),
+        column("y", ColumnKind::Real, false),
+        column("width", ColumnKind::Real, false),
+        column("height", ColumnKind::Real, false),
+        column("confidence", ColumnKind::Real, false),
+        column("source", ColumnKind::Text, false),
+        column("review", ColumnKind::Text, false),
+    ],
+    indexed: &["image_id", "category_id"],
+};
+
+pub const CATEGORIES_TABLE: Table = Table {
+    name: "categories",
+    columns: &[
+        column("id", ColumnKind::Integer, false),
+        column("name", ColumnKind::Text, false),
+        column("model_index", ColumnKind::Integer, true),
+    ],
+    indexed: &[],
+};
+
+pub const RUN: Table = Table {
+    name: "run",
+    columns: &[
+        column("app_version", ColumnKind::Text, false),
+        column("model_file", ColumnKind::Text, false),
+        column("model_sha256", ColumnKind::Text, false),
+        column("input_width", ColumnKind::Integer, false),
+        column("input_height", ColumnKind::Integer, false),
+        column("confidence_threshold", ColumnKind::Real, false),
+        column("nms_threshold", ColumnKind::Real, false),
+        column("execution_provider", ColumnKind::Text, false),
+        column("inference", ColumnKind::Text, false),
+        column("started", ColumnKind::Integer, false),
+        column("finished", ColumnKind::Integer, true),
+        column("os", ColumnKind::Text, false),
+        column("images", ColumnKind::Integer, false),
+    ],
+    indexed: &[],
+};
+
+/// Every table, in the order they are written
+pub const TABLES: [Table; 4] = [IMAGES, DETECTIONS, CATEGORIES_TABLE, RUN];
+
+/// The rows of an image and of its detections
+pub struct ImageRows {
+    pub image: Row,
+    pub detections: Vec<Row>,
+}
+
+/// The rows of each image in turn, numbering images and detections from 1
+///
+/// The rows are made as the results are iterated so large sets need not be held in memory twice.
+pub fn image_rows(
+    results: impl IntoIterator<Item = CamTrapImageDetections>,
+) -> impl Iterator<Item = ImageRows> {
+    let mut detection_id: i64 = 0;
+
+    results
+        .into_iter()
+        .zip(1..)
+        .map(move |(image, image_id): (CamTrapImageDetections, i64)| {
+            let detections = image
+                .detections
+                .iter()
+                .map(|detection| {
+                    detection_id += 1;
+                    vec![
+                        detection_id.into(),
+                        image_id.into(),
+                        Category::id_of(detection.class_index).into(),
+                        detection.x.into(),
+                        detection.y.into(),
+                        detection.width.into(),
+                        detection.height.into(),
+                        detection.confidence.into(),
+                        format!("{:?}", detection.source).into(),
+                        format!("{:?}", detection.review).into(),
+                    ]
+                })
+                .collect();
+
+            ImageRows {
+                image: vec![
+                    image_id.into(),
+                    image.file.into(),
+                    image.error.into(),
+                    image.image_width.into(),
+                    image.image_height.into(),
+                    format!("{:?}", image.review).into(),
+                ],
+                detections,
+            }
+        })
+}
+
+/// The rows of the category table
+pub fn category_rows() -> Vec<Row> {
+    CATEGORIES
+        .iter()
+        .map(|category| {
+            vec![
+                category.id.into(),
+                category.name.to_string().into(),
+                category.model_index.into(),
+            ]
+        })
+        .collect()
+}
+
+/// The row of the run table
+pub fn run_row(info: &RunInfo) -> Row {
+    vec![
+        info.app_version.clone().into(),
+        info.model_file.clone().into(),
+        info.model_sha256.clone().into(),
+        (info.input_size.0 as i64).into(),
+        (info.input_size.1 as i64).into(),
+        info.confidence_threshold.into(),
+        info.nms_threshold.into(),
+        info.execution_provider.clone().into(),
+        serde_json::to_string(&info.inference)
+            .unwrap_or_default()
+            .into(),
+        (info.started as i64).into(),
+        info.finished.map(|finished| finished as i64).into(),
+        info.os.clone().into(),
+        (info.images as i64).into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus};
+    use std::path::Path;
+
+    #[test]
+    fn test_image_rows() {
+        let detection = CamTrapDetection {
+            x: 1.0,
+            y: 2.0,
+            width: 3.0,
+            height: 4.0,
+            class_index: 1,
+            confidence: 0.5,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Verified,
+        };
+        let results = vec![
+            CamTrapImageDetections {
+                file: String::from("a.jpg"),
+                error: None,
+                image_width: Some(10),
+                image_height: Some(20),
+                detections: vec![detection.clone(), detection],
+                review: ReviewStatus::Unreviewed,
+                stamp: None,
+                metadata: None,
+            },
+            CamTrapImageDetections::failed(Path::new("b.jpg"), "Unreadable"),
+        ];
+
+        let rows: Vec<ImageRows> = image_rows(results).collect();
+        assert_eq!(rows.len(), 2);
+        for row in &rows {
+            assert_eq!(row.image.len(), IMAGES.columns.len());
+            for detection in &row.detections {
+                assert_eq!(detection.len(), DETECTIONS.columns.len());
+            }
+        }
+
+        assert_eq!(rows[0].image[1], Value::Text(String::from("a.jpg")));
+        assert_eq!(rows[0].detections[1][0], Value::Integer(2));
+        assert_eq!(rows[0].detections[1][1], Value::Integer(1));
+        assert_eq!(
+            rows[0].detections[0][2],
+            Value::Integer(Category::HUMAN.id as i64)
+        );
+        assert_eq!(
+            rows[0].detections[0][9],
+            Value::Text(String::from("Verified"))
+        );
+        assert_eq!(rows[1].image[0], Value::Integer(2));
+        assert_eq!(rows[1].image[2], Value::Text(String::from("Unreadable")));
+        assert_eq!(rows[1].image[3], Value::Null);
+
+        assert_eq!(category_rows().len(), CATEGORIES.len());
+        assert_eq!(run_row(&RunInfo::default()).len(), RUN.columns.len());
+    }
+}
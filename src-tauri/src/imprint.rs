@@ -0,0 +1,601 @@
+//! Imprint module
+//!
+//! Reads the date, time and temperature that trail cameras print in an information bar along the
+//! top or bottom of their photos, for cameras without reliable EXIF data.
+//!
+//! The bar is found as a band of rows of a single background colour at the edge of the frame.
+//! Its characters are separated into connected components and each is matched against glyph
+//! templates rendered from the bundled font, which is enough for the plain digits cameras print
+//! without a full OCR engine.
+//!
+
+use crate::metadata::ImageMetadata;
+use crate::util::magic_image::LABEL_FONT;
+use ab_glyph::PxScale;
+use image::{DynamicImage, GrayImage, Luma};
+use imageproc::region_labelling::{connected_components, Connectivity};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// Where a camera prints its information bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BannerPosition {
+    Top,
+    Bottom,
+}
+
+/// The order a camera prints the day, month and year of dates in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+/// Where a camera prints its information bar and how it prints dates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImprintLayout {
+    /// The bar at the bottom or top, with dates day first
+    #[default]
+    Generic,
+
+    /// The bar at the bottom, with dates month first as cameras sold in the US print them
+    MonthFirst,
+}
+
+impl ImprintLayout {
+    /// Where the information bar is looked for, in order
+    pub fn positions(self) -> &'static [BannerPosition] {
+        match self {
+            ImprintLayout::Generic => &[BannerPosition::Bottom, BannerPosition::Top],
+            ImprintLayout::MonthFirst => &[BannerPosition::Bottom],
+        }
+    }
+
+    /// How dates are printed, dates starting with a four digit year are read year first whatever
+    /// the layout
+    pub fn date_order(self) -> DateOrder {
+        match self {
+            ImprintLayout::Generic => DateOrder::DayMonthYear,
+            ImprintLayout::MonthFirst => DateOrder::MonthDayYear,
+        }
+    }
+}
+
+/// The tallest information bar looked for, as a fraction of the image height
+const MAX_BANNER_FRACTION: f32 = 0.15;
+
+/// The difference in brightness from the background within which a pixel is background
+const BACKGROUND_TOLERANCE: u8 = 40;
+
+/// The difference in brightness from the background of the pixels of the edge row of a bar, which
+/// is plain unlike the noisy edge of a scene
+const EDGE_TOLERANCE: u8 = 12;
+
+/// The fraction of the pixels of a row that must be background for the row to be part of the bar
+const MIN_BACKGROUND_FRACTION: f32 = 0.55;
+
+/// The fewest rows an information bar can have and still hold legible text
+const MIN_BANNER_ROWS: u32 = 6;
+
+/// The size of the grid glyphs are compared on
+const GRID_WIDTH: usize = 10;
+const GRID_HEIGHT: usize = 14;
+
+/// The largest distance from a template a glyph is recognised at
+const MAX_DISTANCE: f32 = 0.4;
+
+/// The characters recognised, as rendered for the templates
+const TEMPLATE_CHARS: &str = "0123456789:/-.°CFAMP";
+
+/// The rows of an information bar and the brightness of its background
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Banner {
+    top: u32,
+    bottom: u32,
+    background: u8,
+}
+
+/// The fraction of the pixels of a row within the tolerance of the background
+fn background_fraction(gray: &GrayImage, y: u32, background: u8, tolerance: u8) -> f32 {
+    let matching = (0..gray.width())
+        .filter(|x| gray.get_pixel(*x, y)[0].abs_diff(background) <= tolerance)
+        .count();
+    matching as f32 / gray.width() as f32
+}
+
+/// Find the information bar at the given edge of an image
+///
+/// The bar starts with a row of plain background at the very edge and ends at the first row
+/// that is mostly something else. A band that runs past the tallest bar looked for is taken to be
+/// part of the scene, e.g. a dark sky.
+fn find_banner(gray: &GrayImage, position: BannerPosition) -> Option<Banner> {
+    let (width, height) = gray.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let row = |i: u32| match position {
+        BannerPosition::Top => i,
+        BannerPosition::Bottom => height - 1 - i,
+    };
+
+    let mut edge: Vec<u8> = (0..width).map(|x| gray.get_pixel(x, row(0))[0]).collect();
+    edge.sort_unstable();
+    let background = edge[edge.len() / 2];
+    if background_fraction(gray, row(0), background, EDGE_TOLERANCE) < 0.9 {
+        return None;
+    }
+
+    let max_rows = ((height as f32 * MAX_BANNER_FRACTION) as u32).min(height - 1);
+    let rows = (1..=max_rows).find(|i| {
+        background_fraction(gray, row(*i), background, BACKGROUND_TOLERANCE)
+            < MIN_BACKGROUND_FRACTION
+    })?;
+    if rows < MIN_BANNER_ROWS {
+        return None;
+    }
+
+    let (top, bottom) = match position {
+        BannerPosition::Top => (0, rows),
+        BannerPosition::Bottom => (height - rows, height),
+    };
+    Some(Banner {
+        top,
+        bottom,
+        background,
+    })
+}
+
+/// A character of an information bar, as the box of its ink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GlyphBox {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+impl GlyphBox {
+    fn width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    fn height(&self) -> u32 {
+        self.bottom - self.top
+    }
+}
+
+/// The ink of an information bar, pixels far enough from the background
+fn ink_mask(gray: &GrayImage, banner: &Banner) -> GrayImage {
+    GrayImage::from_fn(gray.width(), banner.bottom - banner.top, |x, y| {
+        let value = gray.get_pixel(x, banner.top + y)[0];
+        Luma([(value.abs_diff(banner.background) > BACKGROUND_TOLERANCE) as u8 * 255])
+    })
+}
+
+/// Separate the ink into characters, left to right
+///
+/// Components above one another are a single character, e.g. the dots of a colon.
+fn glyph_boxes(mask: &GrayImage) -> Vec<GlyphBox> {
+    let labels = connected_components(mask, Connectivity::Eight, Luma([0u8]));
+
+    let mut boxes: Vec<Option<GlyphBox>> = vec![];
+    for (x, y, label) in labels.enumerate_pixels() {
+        let label = label[0] as usize;
+        if label == 0 {
+            continue;
+        }
+        if boxes.len() < label {
+            boxes.resize(label, None);
+        }
+        let glyph = boxes[label - 1].get_or_insert(GlyphBox {
+            left: x,
+            right: x + 1,
+            top: y,
+            bottom: y + 1,
+        });
+        glyph.left = glyph.left.min(x);
+        glyph.right = glyph.right.max(x + 1);
+        glyph.top = glyph.top.min(y);
+        glyph.bottom = glyph.bottom.max(y + 1);
+    }
+
+    let mut boxes: Vec<GlyphBox> = boxes.into_iter().flatten().collect();
+    boxes.sort_by_key(|glyph| glyph.left);
+
+    let mut merged: Vec<GlyphBox> = vec![];
+    for glyph in boxes {
+        if let Some(last) = merged.last_mut() {
+            let overlap = last
+                .right
+                .min(glyph.right)
+                .saturating_sub(last.left.max(glyph.left));
+            let stacked = glyph.top >= last.bottom || last.top >= glyph.bottom;
+            if stacked && overlap * 2 > last.width().min(glyph.width()) {
+                *last = GlyphBox {
+                    left: last.left.min(glyph.left),
+                    right: last.right.max(glyph.right),
+                    top: last.top.min(glyph.top),
+                    bottom: last.bottom.max(glyph.bottom),
+                };
+                continue;
+            }
+        }
+        merged.push(glyph);
+    }
+    merged
+}
+
+/// The top and height of a line of text, from the median of its tallest characters
+fn line_metrics(boxes: &[GlyphBox]) -> Option<(f32, f32)> {
+    let tallest = boxes.iter().map(GlyphBox::height).max()?;
+    let mut tall: Vec<&GlyphBox> = boxes
+        .iter()
+        .filter(|glyph| glyph.height() * 10 >= tallest * 6)
+        .collect();
+
+    tall.sort_by_key(|glyph| glyph.height());
+    let height = tall[tall.len() / 2].height();
+    tall.sort_by_key(|glyph| glyph.top);
+    let top = tall[tall.len() / 2].top;
+
+    Some((top as f32, height as f32))
+}
+
+/// The shape of a character and where it sits on its line, to compare it with the templates
+#[derive(Debug, Clone)]
+struct Features {
+    grid: [f32; GRID_WIDTH * GRID_HEIGHT],
+    height: f32,
+    center: f32,
+    aspect: f32,
+}
+
+impl Features {
+    fn new(mask: &GrayImage, glyph: &GlyphBox, line: (f32, f32)) -> Self {
+        const SAMPLES: u32 = 4;
+
+        let mut grid = [0.0; GRID_WIDTH * GRID_HEIGHT];
+        for (i, cell) in grid.iter_mut().enumerate() {
+            let (cx, cy) = ((i % GRID_WIDTH) as f32, (i / GRID_WIDTH) as f32);
+            let mut ink = 0;
+            for s in 0..SAMPLES * SAMPLES {
+                let sx = (cx + ((s % SAMPLES) as f32 + 0.5) / SAMPLES as f32) / GRID_WIDTH as f32;
+                let sy = (cy + ((s / SAMPLES) as f32 + 0.5) / SAMPLES as f32) / GRID_HEIGHT as f32;
+                let x = glyph.left + (sx * glyph.width() as f32) as u32;
+                let y = glyph.top + (sy * glyph.height() as f32) as u32;
+                ink += (mask.get_pixel(x, y)[0] > 0) as u32;
+            }
+            *cell = ink as f32 / (SAMPLES * SAMPLES) as f32;
+        }
+
+        let (top, height) = line;
+        Self {
+            grid,
+            height: glyph.height() as f32 / height,
+            center: ((glyph.top + glyph.bottom) as f32 / 2.0 - top) / height,
+            aspect: glyph.width() as f32 / glyph.height() as f32,
+        }
+    }
+
+    /// The shape of small characters such as `°` and `-` is blurred by the few pixels they cover,
+    /// so their shape counts for less than their size and position
+    fn distance(&self, other: &Features) -> f32 {
+        let pixels = self
+            .grid
+            .iter()
+            .zip(&other.grid)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / self.grid.len() as f32;
+
+        pixels * self.height.min(1.0)
+            + (self.height - other.height).abs()
+            + (self.center - other.center).abs() * 0.5
+            + (self.aspect.ln() - other.aspect.ln()).abs() * 0.25
+    }
+}
+
+/// The characters of the line of text in an information bar, with spaces between words and `?`
+/// for characters that match no template
+fn read_line(mask: &GrayImage) -> String {
+    let boxes = glyph_boxes(mask);
+    let Some(line) = line_metrics(&boxes) else {
+        return String::new();
+    };
+
+    let mut text = String::new();
+    let mut previous: Option<&GlyphBox> = None;
+    for glyph in &boxes {
+        if let Some(previous) = previous {
+            if (glyph.left.saturating_sub(previous.right) as f32) > line.1 * 0.4 {
+                text.push(' ');
+            }
+        }
+        previous = Some(glyph);
+
+        let features = Features::new(mask, glyph, line);
+        let best = TEMPLATES
+            .iter()
+            .map(|(c, template)| (*c, features.distance(template)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        text.push(match best {
+            Some((c, distance)) if distance <= MAX_DISTANCE => c,
+            _ => '?',
+        });
+    }
+    text
+}
+
+/// The features of each recognised character, read from the bundled font the same way as the
+/// characters of an information bar
+static TEMPLATES: Lazy<Vec<(char, Features)>> = Lazy::new(|| {
+    let scale = PxScale::from(48.0);
+    let spaced: String = TEMPLATE_CHARS.chars().flat_map(|c| [c, ' ']).collect();
+
+    let mut canvas = GrayImage::new(64 * TEMPLATE_CHARS.chars().count() as u32, 64);
+    imageproc::drawing::draw_text_mut(
+        &mut canvas,
+        Luma([255u8]),
+        4,
+        4,
+        scale,
+        &*LABEL_FONT,
+        &spaced,
+    );
+    let mask = GrayImage::from_fn(canvas.width(), canvas.height(), |x, y| {
+        Luma([(canvas.get_pixel(x, y)[0] > 127) as u8 * 255])
+    });
+
+    let boxes = glyph_boxes(&mask);
+    let line = line_metrics(&boxes).expect("the template characters are rendered");
+    TEMPLATE_CHARS
+        .chars()
+        .zip(&boxes)
+        .map(|(c, glyph)| (c, Features::new(&mask, glyph, line)))
+        .collect()
+});
+
+/// Read the date and time, and temperature, printed in the information bar of an image
+///
+/// Returns `None` if no bar is found or nothing could be read from it.
+pub fn read_imprint(image: &DynamicImage, layout: ImprintLayout) -> Option<ImageMetadata> {
+    let gray = image.to_luma8();
+
+    layout.positions().iter().find_map(|position| {
+        let banner = find_banner(&gray, *position)?;
+        let text = read_line(&ink_mask(&gray, &banner));
+        let metadata = parse_imprint(&text, layout.date_order());
+        (metadata.timestamp.is_some() || metadata.temperature.is_some()).then_some(metadata)
+    })
+}
+
+/// Fill the timestamp and temperature of the metadata of an image from its information bar if
+/// its EXIF data has none
+pub(crate) fn fill_from_imprint(
+    metadata: &mut Option<ImageMetadata>,
+    image: &DynamicImage,
+    layout: ImprintLayout,
+) {
+    if let Some(metadata) = metadata {
+        if metadata.timestamp.is_some() && metadata.temperature.is_some() {
+            return;
+        }
+    }
+
+    if let Some(printed) = read_imprint(image, layout) {
+        let metadata = metadata.get_or_insert_with(ImageMetadata::default);
+        metadata.timestamp = metadata.timestamp.take().or(printed.timestamp);
+        metadata.temperature = metadata.temperature.or(printed.temperature);
+    }
+}
+
+/// Parse the date and time, and temperature, from the text of an information bar
+///
+/// Dates are numbers separated by `/`, `-` or `.`, times are `HH:MM` or `HH:MM:SS` with an
+/// optional AM or PM, and temperatures are numbers followed by `C` or `F`, optionally with a
+/// degree sign. Temperatures are converted to degrees Celsius.
+///
+/// Cameras that print every character in a cell of the same width leave gaps after narrow ones,
+/// so spaces after a separator or degree sign are ignored.
+pub fn parse_imprint(text: &str, date_order: DateOrder) -> ImageMetadata {
+    let mut joined = String::new();
+    for c in text.chars() {
+        let separated = joined.ends_with(['/', '-', '.', ':', '°']);
+        if !(c.is_whitespace() && separated) {
+            joined.push(c);
+        }
+    }
+    let tokens: Vec<&str> = joined.split_whitespace().collect();
+
+    let mut date = None;
+    let mut time = None;
+    let mut temperature = None;
+    for (i, token) in tokens.iter().enumerate() {
+        if date.is_none() {
+            date = parse_date(token, date_order);
+        }
+        if time.is_none() {
+            time = parse_time(token, tokens.get(i + 1).copied());
+        }
+        if temperature.is_none() {
+            temperature = parse_temperature(token);
+        }
+    }
+
+    ImageMetadata {
+        timestamp: date
+            .zip(time)
+            .map(|((year, month, day), (hour, minute, second))| {
+                format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    year, month, day, hour, minute, second
+                )
+            }),
+        temperature,
+        ..Default::default()
+    }
+}
+
+fn parse_date(token: &str, order: DateOrder) -> Option<(u32, u32, u32)> {
+    let parts: Vec<&str> = token.split(['/', '-', '.']).collect();
+    if parts.len() != 3
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let order = if parts[0].len() == 4 {
+        DateOrder::YearMonthDay
+    } else {
+        order
+    };
+    let (year, month, day) = match order {
+        DateOrder::DayMonthYear => (parts[2], parts[1], parts[0]),
+        DateOrder::MonthDayYear => (parts[2], parts[0], parts[1]),
+        DateOrder::YearMonthDay => (parts[0], parts[1], parts[2]),
+    };
+
+    let mut year: u32 = year.parse().ok()?;
+    if year < 100 {
+        year += 2000;
+    }
+    let month: u32 = month.parse().ok()?;
+    let day: u32 = day.parse().ok()?;
+
+    ((1990..=2100).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day))
+        .then_some((year, month, day))
+}
+
+fn parse_time(token: &str, next: Option<&str>) -> Option<(u32, u32, u32)> {
+    let (clock, meridiem) = match token.strip_suffix("AM").or(token.strip_suffix("PM")) {
+        Some(clock) => (clock, Some(&token[clock.len()..])),
+        None => (token, next.filter(|next| matches!(*next, "AM" | "PM"))),
+    };
+
+    let parts: Vec<u32> = clock
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let (hour, minute, second) = match parts[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return None,
+    };
+
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some("AM") => hour % 12,
+        Some(_) => hour % 12 + 12,
+        None => hour,
+    };
+
+    (hour < 24 && minute < 60 && second < 60).then_some((hour, minute, second))
+}
+
+fn parse_temperature(token: &str) -> Option<f32> {
+    let (value, fahrenheit) = match token.strip_suffix('C') {
+        Some(value) => (value, false),
+        None => (token.strip_suffix('F')?, true),
+    };
+    let value = value.strip_suffix('°').unwrap_or(value);
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+        return None;
+    }
+
+    let value: f32 = value.parse().ok()?;
+    Some(if fahrenheit {
+        ((value - 32.0) * 5.0 / 9.0 * 10.0).round() / 10.0
+    } else {
+        value
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Synthetic photos with an information bar in each style, drawn by the `imprint_fixtures`
+    /// example rather than taken by a camera, with the timestamp and temperature they show
+    const FIXTURES: [(&str, ImprintLayout, &str, f32); 4] = [
+        (
+            "month_first_dashes.jpg",
+            ImprintLayout::MonthFirst,
+            "2023-06-01 21:14:05",
+            23.0,
+        ),
+        (
+            "month_first_12_hour.jpg",
+            ImprintLayout::MonthFirst,
+            "2023-06-01 21:14:00",
+            22.8,
+        ),
+        (
+            "month_first_fixed_width.jpg",
+            ImprintLayout::MonthFirst,
+            "2023-11-30 06:02:45",
+            -3.9,
+        ),
+        (
+            "day_first_top.jpg",
+            ImprintLayout::Generic,
+            "2024-02-29 13:45:10",
+            5.0,
+        ),
+    ];
+
+    #[test]
+    fn test_read_imprint_fixtures() {
+        for (file, layout, timestamp, temperature) in FIXTURES {
+            let image = image::open(format!("./tests/fixtures/imprint/{}", file)).unwrap();
+            let metadata = read_imprint(&image, layout)
+                .unwrap_or_else(|| panic!("no information bar read from {}", file));
+
+            assert_eq!(metadata.timestamp.as_deref(), Some(timestamp), "{}", file);
+            assert_eq!(metadata.temperature, Some(temperature), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_no_banner() {
+        // A gradient has no band of plain background at either edge
+        let image =
+            DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, y| Luma([(x * 2 + y) as u8])));
+        assert_eq!(read_imprint(&image, ImprintLayout::Generic), None);
+
+        // Nor does a plain image, which is all background rather than a bar
+        let image = DynamicImage::ImageLuma8(GrayImage::new(64, 64));
+        assert_eq!(read_imprint(&image, ImprintLayout::Generic), None);
+    }
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(TEMPLATES.len(), TEMPLATE_CHARS.chars().count());
+    }
+
+    #[test]
+    fn test_parse_imprint() {
+        let metadata = parse_imprint("M 73°F 06/01/2023 9:14 PM", DateOrder::MonthDayYear);
+        assert_eq!(metadata.timestamp.as_deref(), Some("2023-06-01 21:14:00"));
+        assert_eq!(metadata.temperature, Some(22.8));
+
+        let metadata = parse_imprint("01. 06. 23 12: 05: 09AM -3° C", DateOrder::DayMonthYear);
+        assert_eq!(metadata.timestamp.as_deref(), Some("2023-06-01 00:05:09"));
+        assert_eq!(metadata.temperature, Some(-3.0));
+
+        // Year first whatever the order, and a date alone is not a timestamp
+        let metadata = parse_imprint("2023-06-01 ?1:14", DateOrder::MonthDayYear);
+        assert_eq!(metadata.timestamp, None);
+        assert_eq!(
+            parse_date("2023-06-01", DateOrder::MonthDayYear),
+            Some((2023, 6, 1))
+        );
+
+        assert_eq!(parse_date("13/13/2023", DateOrder::MonthDayYear), None);
+        assert_eq!(parse_time("13:00", Some("PM")), None);
+        assert_eq!(parse_temperature("°C"), None);
+    }
+}
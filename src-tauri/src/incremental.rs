@@ -0,0 +1,101 @@
+//! Incremental module
+//!
+//! Reuses the results of a previous run for files that have not changed since, so only new or
+//! modified images need to be run through the model. A file is considered unchanged when its path,
+//! size and modification time all match those recorded when it was processed.
+//!
+
+use crate::structures::{CamTrapImageDetections, FileStamp};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Match the files found in a folder against previous results
+///
+/// Returns one entry per file, in the same order: the previous result if the file is unchanged,
+/// or `None` if it is new, modified or previously failed and so needs processing. Results for
+/// files no longer in the folder are dropped.
+///
+/// Previous results without a stamp, e.g. imported from an export made before stamps were
+/// recorded, are matched on path alone.
+pub fn reuse_results(
+    files: &[PathBuf],
+    previous: Vec<CamTrapImageDetections>,
+) -> Vec<Option<CamTrapImageDetections>> {
+    let mut previous: HashMap<PathBuf, CamTrapImageDetections> = previous
+        .into_iter()
+        .filter(|result| result.error.is_none())
+        .map(|result| (PathBuf::from(&result.file), result))
+        .collect();
+
+    files
+        .iter()
+        .map(|file| {
+            let result = previous.remove(file)?;
+            match result.stamp {
+                None => Some(result),
+                Some(stamp) => match FileStamp::read(file) {
+                    Ok(current) if current == stamp => Some(result),
+                    _ => None,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::ReviewStatus;
+    use std::path::Path;
+
+    fn create_result(file: &Path, stamp: Option<FileStamp>) -> CamTrapImageDetections {
+        CamTrapImageDetections {
+            file: file.to_str().unwrap().to_string(),
+            error: None,
+            image_width: Some(100),
+            image_height: Some(100),
+            detections: vec![],
+            review: ReviewStatus::Verified,
+            stamp,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_reuse_results() {
+        let dir = std::env::temp_dir().join("camtrap-incremental");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let files: Vec<PathBuf> = ["unchanged.jpg", "modified.jpg", "unstamped.jpg", "new.jpg"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        for file in &files {
+            std::fs::write(file, b"image").unwrap();
+        }
+
+        let stamp = FileStamp::read(&files[0]).unwrap();
+        let modified = FileStamp {
+            size: stamp.size + 1,
+            ..stamp
+        };
+        let previous = vec![
+            create_result(&dir.join("removed.jpg"), Some(stamp)),
+            create_result(&files[2], None),
+            create_result(&files[1], Some(modified)),
+            create_result(&files[0], Some(stamp)),
+        ];
+
+        let reused = reuse_results(&files, previous);
+        let reused: Vec<Option<&str>> = reused
+            .iter()
+            .map(|result| result.as_ref().map(|result| result.file.as_str()))
+            .collect();
+        assert_eq!(
+            reused,
+            vec![files[0].to_str(), None, files[2].to_str(), None]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,51 @@
+//! Detect animals, humans and vehicles in camera trap images with MegaDetector
+//!
+//! Load the model once with [`Detector::builder`], then run it on single images or whole folders.
+//! The results can be written with the exporters in [`exports`], and every fallible function
+//! returns the crate's [`Error`].
+//!
+//! ```no_run
+//! use camtrap_detector::exports::{coordinates::CoordinateOptions, csv::write_csv};
+//! use camtrap_detector::Detector;
+//!
+//! # fn main() -> camtrap_detector::Result<()> {
+//! let mut detector = Detector::builder()
+//!     .model_path("md_v1000.0.0-redwood-dynamic.onnx")
+//!     .confidence_threshold(0.2)
+//!     .build()?;
+//!
+//! let results = detector.detect_dir("/path/to/images", true)?;
+//! write_csv(results, &CoordinateOptions::default(), std::io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The Cargo package is named `app` rather than after the library, as Tauri names the desktop
+//! app's executable and installers after it. Depend on the library with the `package` key:
+//!
+//! ```toml
+//! [dependencies]
+//! camtrap_detector = { git = "https://github.com/bencevans/camtrap-detector", package = "app" }
+//! ```
+//!
+
+pub mod benchmark;
+pub mod detector;
+pub mod error;
+pub mod evaluation;
 pub mod exports;
+pub mod imprint;
+pub mod incremental;
+pub mod masks;
 pub mod megadetector;
+pub mod metadata;
+pub mod review;
+pub mod server;
 pub mod structures;
 pub mod util;
+pub mod watch;
 pub mod yolov5;
+
+pub use detector::{Detector, DetectorBuilder};
+pub use error::{Error, Result};
+pub use util::magic_image::MagicImage;
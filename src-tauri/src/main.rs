@@ -3,18 +3,39 @@
     windows_subsystem = "windows"
 )]
 
-use app::{
+use camtrap_detector::{
+    evaluation::{evaluate, read_annotations, AnnotatedImage, EvaluationReport},
     exports::{
         self,
-        csv::CamTrapCSVDetection,
-        image::{export_image, DrawCriteria, FilterCriteria},
+        contact_sheet::{export_contact_sheets, ContactSheetCriteria},
+        coordinates::CoordinateOptions,
+        csv::write_csv,
+        image::{export_image, DrawCriteria, FilterCriteria, ImageExportReport},
+        json::{import_json, write_json},
+        jsonl::{import_jsonl, write_jsonl},
+        parquet::write_parquet,
+        sidecar::{write_info_sidecar, ExportInfo},
+        sqlite::write_sqlite,
+        summary::write_summary_csv,
     },
-    structures::{self, CamTrapDetection, CamTrapImageDetections},
-    yolov5::YoloModel,
+    imprint::ImprintLayout,
+    incremental::reuse_results,
+    masks::{self, MaskOptions},
+    megadetector::MODEL_FILE,
+    review::{LabelSelection, ReviewBox},
+    structures::{self, CamTrapImageDetections, ReviewStatus, RunInfo},
+    watch::{watch, WatchOptions},
+    yolov5::{helpers::enumerate_images, ExecutionOptions, InferenceOptions, SystemInfo},
+    Detector,
 };
 use chug::Chug;
-use image::GenericImageView;
-use std::{path::PathBuf, sync::Mutex};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 use tauri::{path::BaseDirectory, Emitter, Manager, Window};
 use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
 use tauri_plugin_notification::NotificationExt;
@@ -40,63 +61,136 @@ pub struct AppState(Mutex<App>);
 #[derive(Default)]
 struct App {
     base_dir: PathBuf,
-    results: Vec<structures::CamTrapImageDetections>,
+    /// Shared so exports can write them without holding the lock, changes copy them only while an
+    /// export is running
+    results: Arc<Vec<structures::CamTrapImageDetections>>,
+    export_cancelled: Arc<AtomicBool>,
+    watch_stop: Option<Arc<AtomicBool>>,
+    /// How the results were produced, for the exports
+    run_info: Option<RunInfo>,
+}
+
+/// Load the bundled MegaDetector model
+fn load_model(
+    handle: &tauri::AppHandle,
+    confidence_threshold: f32,
+    execution: ExecutionOptions,
+    inference: InferenceOptions,
+) -> Result<Detector, String> {
+    let model_path = handle
+        .path()
+        .resolve(MODEL_FILE, BaseDirectory::Resource)
+        .map_err(|e| format!("Failed to find the model: {}", e))?;
+
+    Detector::builder()
+        .model_path(model_path)
+        .confidence_threshold(confidence_threshold)
+        .execution_options(execution)
+        .inference_options(inference)
+        .build()
+        .map_err(|e| format!("Failed to load the model: {}", e))
+}
+
+/// Load the model to report the available execution providers and the one that would be used
+#[tauri::command]
+async fn system_info(
+    execution: Option<ExecutionOptions>,
+    handle: tauri::AppHandle,
+) -> Result<SystemInfo, String> {
+    let detector = load_model(
+        &handle,
+        0.2,
+        execution.unwrap_or_default(),
+        InferenceOptions::default(),
+    )?;
+    Ok(detector.system_info())
 }
 
 fn export_csv(
-    results: Vec<structures::CamTrapImageDetections>,
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    info: Option<RunInfo>,
+    coordinates: &CoordinateOptions,
     output_path: PathBuf,
 ) -> Result<(), String> {
-    let mut writer = csv::Writer::from_path(&output_path)
-        .map_err(|e| format!("Failed to create CSV writer: {}", e))?;
-
-    for result in results {
-        let res = if let Some(error) = result.error {
-            writer.serialize(CamTrapCSVDetection::new_error(result.file, error))
-        } else if result.detections.is_empty() {
-            writer.serialize(CamTrapCSVDetection::new_empty(result.file))
-        } else {
-            let mut row_result = Ok(());
-            for detection in result.detections {
-                if let Err(e) = writer.serialize(CamTrapCSVDetection::new_detection(
-                    result.file.clone(),
-                    result.image_width.unwrap_or(0),
-                    result.image_height.unwrap_or(0),
-                    &detection,
-                )) {
-                    row_result = Err(e);
-                    break;
-                }
-            }
-            row_result
-        };
+    let file = std::fs::File::create(&output_path)
+        .map_err(|e| format!("Failed to create CSV file: {}", e))?;
 
-        if let Err(e) = res {
-            return Err(format!("Failed to write CSV row: {}", e));
-        }
-    }
+    write_csv(results, coordinates, file).map_err(|e| format!("Failed to write CSV: {}", e))?;
 
-    writer
-        .flush()
-        .map_err(|e| format!("Failed to flush CSV writer: {}", e))?;
+    let info = ExportInfo {
+        info,
+        coordinates: *coordinates,
+    };
+    write_info_sidecar(&info, &output_path)
+        .map_err(|e| format!("Failed to write CSV info file: {}", e))
+}
 
-    Ok(())
+fn export_summary_csv(
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    base_dir: &std::path::Path,
+    output_path: PathBuf,
+) -> Result<(), String> {
+    let file = std::fs::File::create(&output_path)
+        .map_err(|e| format!("Failed to create summary CSV file: {}", e))?;
+
+    write_summary_csv(results, base_dir, file)
+        .map_err(|e| format!("Failed to write summary CSV: {}", e))
 }
 
 fn export_json(
-    results: Vec<structures::CamTrapImageDetections>,
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    info: Option<RunInfo>,
+    coordinates: &CoordinateOptions,
     output_path: PathBuf,
 ) -> Result<(), String> {
-    let mut writer = std::fs::File::create(&output_path)
+    let file = std::fs::File::create(&output_path)
         .map_err(|e| format!("Failed to create JSON file: {}", e))?;
-    let json_images: Vec<exports::json::CamTrapJSONImageDetections> =
-        results.into_iter().map(|d| d.into()).collect();
-    let json_container = exports::json::CamTrapJSONContainer::new(json_images);
 
-    serde_json::to_writer_pretty(&mut writer, &json_container)
-        .map_err(|e| format!("Failed to write JSON: {}", e))?;
+    write_json(
+        results,
+        info.as_ref(),
+        coordinates,
+        std::io::BufWriter::new(file),
+    )
+    .map_err(|e| format!("Failed to write JSON: {}", e))
+}
 
-    Ok(())
+fn export_jsonl(
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    info: Option<RunInfo>,
+    coordinates: &CoordinateOptions,
+    output_path: PathBuf,
+) -> Result<(), String> {
+    let file = std::fs::File::create(&output_path)
+        .map_err(|e| format!("Failed to create JSON Lines file: {}", e))?;
+
+    write_jsonl(results, coordinates, std::io::BufWriter::new(file))
+        .map_err(|e| format!("Failed to write JSON Lines: {}", e))?;
+
+    let info = ExportInfo {
+        info,
+        coordinates: *coordinates,
+    };
+    write_info_sidecar(&info, &output_path)
+        .map_err(|e| format!("Failed to write JSON Lines info file: {}", e))
+}
+
+fn export_sqlite(
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    info: Option<RunInfo>,
+    output_path: PathBuf,
+) -> Result<(), String> {
+    write_sqlite(results, info.as_ref(), &output_path)
+        .map_err(|e| format!("Failed to write SQLite database: {}", e))
+}
+
+fn export_parquet(
+    results: impl Iterator<Item = structures::CamTrapImageDetections>,
+    info: Option<RunInfo>,
+    output_path: PathBuf,
+) -> Result<(), String> {
+    write_parquet(results, info.as_ref(), &output_path)
+        .map_err(|e| format!("Failed to write Parquet files: {}", e))
 }
 
 #[tauri::command]
@@ -104,63 +198,222 @@ async fn export_image_set(
     state: tauri::State<'_, AppState>,
     output_path: PathBuf,
     filter_criteria: FilterCriteria,
-    draw_criteria: DrawCriteria
-) -> Result<String, String> {
-    let results = state.0.lock().unwrap().results.clone();
-    let base_dir = state.0.lock().unwrap().base_dir.clone();
+    draw_criteria: DrawCriteria,
+    window: Window,
+) -> Result<ImageExportReport, String> {
+    let (results, base_dir) = {
+        let app = state.0.lock().unwrap();
+        (app.results.clone(), app.base_dir.clone())
+    };
 
     // Ensure it's not the same folder as the raw images
     if output_path == base_dir {
         return Err("The export folder cannot be the same as the raw images folder.".to_string());
     }
 
-    export_image(
-        results,
-        base_dir,
+    let cancel = state.0.lock().unwrap().export_cancelled.clone();
+    cancel.store(false, Ordering::Relaxed);
+
+    // Only the images matching the filter are exported, so the ETA is started on first progress
+    let eta: Mutex<Option<Chug>> = Mutex::new(None);
+
+    let report = export_image(
+        &results[..],
+        base_dir.clone(),
         output_path,
         filter_criteria,
         draw_criteria,
-    ).map_err(|e| format!("Failed to export images: {}", e))?;
+        &cancel,
+        |current, total, file| {
+            let eta = {
+                let mut eta = eta.lock().unwrap();
+                let eta = eta.get_or_insert_with(|| Chug::new(100, total));
+                eta.tick();
+                eta.eta().map(|eta| eta.as_secs() as usize)
+            };
+
+            let path = pathdiff::diff_paths(file, &base_dir)
+                .and_then(|path| path.to_str().map(|path| path.to_string()))
+                .unwrap_or_else(|| file.to_string());
+
+            if let Err(err) = window.emit(
+                "export-progress",
+                Progress {
+                    current,
+                    total,
+                    percent: (current as f64 / total as f64) * 100.0,
+                    eta,
+                    path,
+                    message: String::from("Exporting "),
+                },
+            ) {
+                eprintln!("Failed to emit export progress: {}", err);
+            }
+        },
+    );
 
-    Ok("The image export has completed.".to_string())
+    Ok(report)
 }
 
 #[tauri::command]
-async fn export(
-    format: String,
-    output_path: PathBuf,
+async fn export_contact_sheet_set(
     state: tauri::State<'_, AppState>,
+    output_path: PathBuf,
+    filter_criteria: FilterCriteria,
+    sheet_criteria: ContactSheetCriteria,
     window: Window,
+) -> Result<usize, String> {
+    let (results, base_dir) = {
+        let app = state.0.lock().unwrap();
+        (app.results.clone(), app.base_dir.clone())
+    };
+
+    // Ensure it's not the same folder as the raw images
+    if output_path == base_dir {
+        return Err("The export folder cannot be the same as the raw images folder.".to_string());
+    }
+
+    let cancel = state.0.lock().unwrap().export_cancelled.clone();
+    cancel.store(false, Ordering::Relaxed);
+
+    let sheets = export_contact_sheets(
+        &results[..],
+        base_dir,
+        output_path.clone(),
+        filter_criteria,
+        sheet_criteria,
+        &cancel,
+        |current, total, sheet| {
+            let path = pathdiff::diff_paths(sheet, &output_path)
+                .and_then(|path| path.to_str().map(|path| path.to_string()))
+                .unwrap_or_else(|| sheet.to_string());
+
+            if let Err(err) = window.emit(
+                "export-progress",
+                Progress {
+                    current,
+                    total,
+                    percent: (current as f64 / total as f64) * 100.0,
+                    eta: None,
+                    path,
+                    message: String::from("Exporting "),
+                },
+            ) {
+                eprintln!("Failed to emit export progress: {}", err);
+            }
+        },
+    )
+    .map_err(|e| format!("Failed to export contact sheets: {}", e))?;
+
+    Ok(sheets.len())
+}
+
+#[tauri::command]
+async fn export_html_gallery(
+    state: tauri::State<'_, AppState>,
+    output_path: PathBuf,
 ) -> Result<(), String> {
+    let results = state.0.lock().unwrap().results.clone();
     let base_dir = state.0.lock().unwrap().base_dir.clone();
 
-    // Gather the results and convert the paths to relative paths
-    let results = state
-        .0
-        .lock()
-        .unwrap()
+    exports::html::export_html(&results, base_dir, output_path)
+        .map_err(|e| format!("Failed to export HTML gallery: {}", e))
+}
+
+/// Evaluate the model's detections in this session against a ground truth annotations file
+#[tauri::command]
+async fn evaluate_results(
+    state: tauri::State<'_, AppState>,
+    ground_truth: PathBuf,
+    confidence_threshold: f32,
+) -> Result<EvaluationReport, String> {
+    let file = std::fs::File::open(&ground_truth)
+        .map_err(|e| format!("Failed to open the ground truth: {}", e))?;
+    let ground_truth = read_annotations(std::io::BufReader::new(file), LabelSelection::Reviewed)
+        .map_err(|e| format!("Failed to read the ground truth: {}", e))?;
+
+    // Ground truth paths are relative to the dataset, so are matched to the processed folder
+    let app = state.0.lock().unwrap();
+    let results: Vec<AnnotatedImage> = app
         .results
         .iter()
-        .map(|r| {
-            let mut copied = r.clone();
-            copied.file = pathdiff::diff_paths(&r.file, &base_dir)
-                .unwrap()
-                .to_str()
-                .unwrap()
+        .filter_map(|image| {
+            let mut image = image.clone();
+            image.file = pathdiff::diff_paths(&image.file, &app.base_dir)?
+                .to_string_lossy()
                 .to_string();
-            copied
+            AnnotatedImage::from_detections(&image, LabelSelection::Model)
         })
         .collect();
 
-    let r = match format.as_str() {
-        "csv" => export_csv(results, output_path),
-        "json" => export_json(results, output_path),
-        _ => Err("Unknown export format".to_string()),
+    Ok(evaluate(&ground_truth, &results, confidence_threshold))
+}
+
+/// Cancel the image or contact sheet export in progress, files already written are left in place.
+#[tauri::command]
+fn cancel_export(state: tauri::State<'_, AppState>) {
+    state
+        .0
+        .lock()
+        .unwrap()
+        .export_cancelled
+        .store(true, Ordering::Relaxed);
+}
+
+#[tauri::command]
+async fn export(
+    format: String,
+    output_path: PathBuf,
+    labels: Option<LabelSelection>,
+    coordinates: Option<CoordinateOptions>,
+    state: tauri::State<'_, AppState>,
+    window: Window,
+) -> Result<(), String> {
+    let labels = labels.unwrap_or_default();
+    let coordinates = coordinates.unwrap_or_default();
+
+    // The results are shared rather than copied, and streamed to disk one image at a time without
+    // holding the lock so the rest of the app is not blocked by a large export
+    let (all_results, base_dir, mut run_info) = {
+        let app = state.0.lock().unwrap();
+        (
+            app.results.clone(),
+            app.base_dir.clone(),
+            app.run_info.clone(),
+        )
+    };
+    if let Some(info) = &mut run_info {
+        info.images = all_results.len();
+    }
+
+    let r = {
+        // Select the labels and convert the paths to relative paths
+        let results = all_results.iter().map(|r| {
+            let mut copied = labels.select(r);
+            if let Some(relative) = pathdiff::diff_paths(&r.file, &base_dir) {
+                copied.file = relative.to_string_lossy().to_string();
+            }
+            copied
+        });
+
+        match format.as_str() {
+            "csv" => export_csv(results, run_info, &coordinates, output_path),
+            "summary-csv" => export_summary_csv(results, &base_dir, output_path),
+            "json" => export_json(results, run_info, &coordinates, output_path),
+            "jsonl" => export_jsonl(results, run_info, &coordinates, output_path),
+            "sqlite" => export_sqlite(results, run_info, output_path),
+            "parquet" => export_parquet(results, run_info, output_path),
+            _ => Err("Unknown export format".to_string()),
+        }
     };
 
     let format_name = match format.as_str() {
         "csv" => "CSV",
+        "summary-csv" => "summary CSV",
         "json" => "JSON",
+        "jsonl" => "JSON Lines",
+        "sqlite" => "SQLite",
+        "parquet" => "Parquet",
         _ => "Unknown",
     };
 
@@ -189,12 +442,46 @@ async fn process(
     path: String,
     confidence_threshold: f32,
     recursive: bool,
+    incremental: Option<bool>,
+    previous_results: Option<PathBuf>,
+    execution: Option<ExecutionOptions>,
+    inference: Option<InferenceOptions>,
+    imprint: Option<ImprintLayout>,
+    masks: Option<MaskOptions>,
     window: Window,
     state: tauri::State<'_, AppState>,
     handle: tauri::AppHandle,
-) -> Result<(), ()> {
-    let files = app::yolov5::helpers::enumerate_images(PathBuf::from(&path), recursive);
-    let files_n = files.len();
+) -> Result<(), String> {
+    let files = enumerate_images(PathBuf::from(&path), recursive);
+
+    // In incremental mode the previous results are taken from a JSON export if given, otherwise
+    // from this session when it processed the same folder
+    let (previous, previous_info) = if !incremental.unwrap_or(false) {
+        (vec![], None)
+    } else if let Some(previous_results) = previous_results {
+        let base_dir = std::path::Path::new(&path);
+        let imported = if previous_results
+            .extension()
+            .is_some_and(|ext| ext == "jsonl")
+        {
+            import_jsonl(&previous_results, base_dir)
+        } else {
+            import_json(&previous_results, base_dir)
+        };
+        imported.map_err(|e| format!("Failed to read previous results: {}", e))?
+    } else {
+        let app = state.0.lock().unwrap();
+        if app.base_dir == PathBuf::from(&path) {
+            (app.results.to_vec(), app.run_info.clone())
+        } else {
+            (vec![], None)
+        }
+    };
+
+    let mut results = reuse_results(&files, previous);
+    let pending: Vec<usize> = (0..files.len()).filter(|i| results[*i].is_none()).collect();
+    let reused_n = files.len() - pending.len();
+    let files_n = pending.len();
 
     println!("Running with Confidence Threshold {}", confidence_threshold);
 
@@ -212,24 +499,35 @@ async fn process(
         )
         .unwrap();
 
-    let mut model = YoloModel::new_from_file(
-        handle
-            .path()
-            .resolve(
-                "../md_v1000.0.0-redwood-dynamic.onnx",
-                BaseDirectory::Resource,
-            )
-            .unwrap()
-            .to_str()
-            .unwrap(),
-        (640, 640),
-    )
-    .unwrap();
+    let mut detector = load_model(
+        &handle,
+        confidence_threshold,
+        execution.unwrap_or_default(),
+        inference.unwrap_or_default(),
+    )?;
+    detector.set_imprint(imprint);
+    detector.set_masks(masks.unwrap_or_default());
+
+    // Reused results are masked too, so they match the images processed now
+    for image in results.iter_mut().flatten() {
+        detector.masks().apply(image);
+    }
+
+    let mut run_info = detector.run_info();
+    if let Some(previous_info) = previous_info.filter(|_| reused_n > 0) {
+        if previous_info.model_sha256 != run_info.model_sha256 {
+            eprintln!(
+                "Reusing results made with a different model, {}, than {}",
+                previous_info.model_file, run_info.model_file
+            );
+        }
+    }
 
     let mut eta = Chug::new(100, files_n);
-    let mut results: Vec<CamTrapImageDetections> = vec![];
 
-    for (i, file) in files.iter().enumerate() {
+    for (i, index) in pending.into_iter().enumerate() {
+        let file = &files[index];
+
         window
             .emit(
                 "progress",
@@ -245,56 +543,17 @@ async fn process(
             .unwrap();
         eta.tick();
 
-        let image = match image::open(file.to_str().unwrap()) {
-            Ok(image) => image,
-            Err(err) => {
-                results.push(CamTrapImageDetections {
-                    file: file.to_str().unwrap().to_string(),
-                    error: Some(err.to_string()),
-                    image_width: None,
-                    image_height: None,
-                    detections: vec![],
-                });
-                continue;
-            }
-        };
-
-        let result = model.detect(&image, Some(confidence_threshold), Some(0.45));
-
-        let (width, height) = image.dimensions();
-
-        let result_handled = match result {
-            Ok(result) => CamTrapImageDetections {
-                file: file.to_str().unwrap().to_string(),
-                error: None,
-                image_width: Some(width),
-                image_height: Some(height),
-                detections: result
-                    .into_iter()
-                    .map(|d| CamTrapDetection {
-                        class_index: d.class as u32,
-                        confidence: d.score,
-                        x: d.bbox.x,
-                        y: d.bbox.y,
-                        width: d.bbox.w,
-                        height: d.bbox.h,
-                    })
-                    .collect(),
-            },
-            Err(err) => CamTrapImageDetections {
-                file: file.to_str().unwrap().to_string(),
-                error: Some(err.to_string()),
-                image_width: None,
-                image_height: None,
-                detections: vec![],
-            },
-        };
-
-        results.push(result_handled);
+        results[index] = Some(
+            detector
+                .detect_path(file)
+                .unwrap_or_else(|err| CamTrapImageDetections::failed(file, err)),
+        );
     }
 
     state.0.lock().unwrap().base_dir = PathBuf::from(&path);
-    state.0.lock().unwrap().results = results;
+    state.0.lock().unwrap().results = Arc::new(results.into_iter().flatten().collect());
+    run_info.finish(files.len());
+    state.0.lock().unwrap().run_info = Some(run_info);
 
     window
         .emit(
@@ -314,7 +573,14 @@ async fn process(
         .notification()
         .builder()
         .title("Processing Complete")
-        .body(format!("Processed {} images.", files_n))
+        .body(if reused_n > 0 {
+            format!(
+                "Processed {} new images, {} unchanged images were skipped.",
+                files_n, reused_n
+            )
+        } else {
+            format!("Processed {} images.", files_n)
+        })
         .show()
     {
         eprintln!("Failed to show notification: {}", err);
@@ -323,6 +589,189 @@ async fn process(
     Ok(())
 }
 
+/// Watch a folder, running detection on new images as they arrive and adding them to the results
+///
+/// Each result is emitted as a `watch-result` event. Watching continues in the background until
+/// `stop_watch` is called.
+#[tauri::command]
+async fn start_watch(
+    options: WatchOptions,
+    execution: Option<ExecutionOptions>,
+    inference: Option<InferenceOptions>,
+    imprint: Option<ImprintLayout>,
+    masks: Option<MaskOptions>,
+    window: Window,
+    state: tauri::State<'_, AppState>,
+    handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut app = state.0.lock().unwrap();
+        if app.watch_stop.is_some() {
+            return Err("A folder is already being watched.".to_string());
+        }
+        app.watch_stop = Some(stop.clone());
+
+        // Continue the session when watching the folder it processed, otherwise start afresh
+        if app.base_dir != options.root {
+            app.base_dir = options.root.clone();
+            app.results = Arc::default();
+        }
+    }
+
+    let mut detector = match load_model(
+        &handle,
+        options.confidence_threshold,
+        execution.unwrap_or_default(),
+        inference.unwrap_or_default(),
+    ) {
+        Ok(detector) => detector,
+        Err(err) => {
+            state.0.lock().unwrap().watch_stop = None;
+            return Err(err);
+        }
+    };
+    detector.set_imprint(imprint);
+    detector.set_masks(masks.unwrap_or_default());
+    state.0.lock().unwrap().run_info = Some(detector.run_info());
+
+    std::thread::spawn(move || {
+        let state = handle.state::<AppState>();
+
+        watch(
+            &options,
+            &mut detector,
+            &stop,
+            |image| {
+                if let Err(err) = window.emit("watch-result", &image) {
+                    eprintln!("Failed to emit watch result: {}", err);
+                }
+                let mut app = state.0.lock().unwrap();
+                let results = Arc::make_mut(&mut app.results);
+                match results.iter_mut().find(|r| r.file == image.file) {
+                    Some(existing) => *existing = image,
+                    None => results.push(image),
+                }
+            },
+            |error| {
+                if let Err(err) = window.emit("watch-error", &error) {
+                    eprintln!("Failed to emit watch error: {}", err);
+                }
+            },
+        );
+    });
+
+    Ok(())
+}
+
+/// Stop watching a folder, the image being processed is finished first
+#[tauri::command]
+fn stop_watch(state: tauri::State<'_, AppState>) {
+    if let Some(stop) = state.0.lock().unwrap().watch_stop.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Apply a review to the image with the given file path
+fn review<T>(
+    state: &tauri::State<'_, AppState>,
+    file: &str,
+    apply: impl FnOnce(&mut CamTrapImageDetections) -> camtrap_detector::Result<T>,
+) -> Result<T, String> {
+    let mut app = state.0.lock().unwrap();
+    let image = Arc::make_mut(&mut app.results)
+        .iter_mut()
+        .find(|image| image.file == file)
+        .ok_or_else(|| format!("No results for {}", file))?;
+
+    apply(image).map_err(|e| format!("Failed to review {}: {}", file, e))
+}
+
+/// Get the detections of an image, including any review
+#[tauri::command]
+fn image_detections(
+    state: tauri::State<'_, AppState>,
+    file: String,
+) -> Result<CamTrapImageDetections, String> {
+    state
+        .0
+        .lock()
+        .unwrap()
+        .results
+        .iter()
+        .find(|image| image.file == file)
+        .cloned()
+        .ok_or_else(|| format!("No results for {}", file))
+}
+
+/// Mark a whole image as verified or rejected, e.g. to confirm it is empty
+#[tauri::command]
+fn review_image(
+    state: tauri::State<'_, AppState>,
+    file: String,
+    status: ReviewStatus,
+) -> Result<(), String> {
+    review(&state, &file, |image| {
+        image.review = status;
+        Ok(())
+    })
+}
+
+/// Mark a detection as verified or rejected
+#[tauri::command]
+fn review_detection(
+    state: tauri::State<'_, AppState>,
+    file: String,
+    index: usize,
+    status: ReviewStatus,
+) -> Result<(), String> {
+    review(&state, &file, |image| image.review_detection(index, status))
+}
+
+/// Add a detection drawn by the reviewer, returning its index
+#[tauri::command]
+fn add_detection(
+    state: tauri::State<'_, AppState>,
+    file: String,
+    bbox: ReviewBox,
+    class_index: u32,
+) -> Result<usize, String> {
+    review(&state, &file, |image| {
+        Ok(image.add_detection(bbox, class_index))
+    })
+}
+
+/// Correct the box and/or class of a detection, returning the index of the corrected detection
+#[tauri::command]
+fn correct_detection(
+    state: tauri::State<'_, AppState>,
+    file: String,
+    index: usize,
+    bbox: Option<ReviewBox>,
+    class_index: Option<u32>,
+) -> Result<usize, String> {
+    review(&state, &file, |image| {
+        image.correct_detection(index, bbox, class_index)
+    })
+}
+
+/// Delete a detection, model detections are kept as rejected
+#[tauri::command]
+fn delete_detection(
+    state: tauri::State<'_, AppState>,
+    file: String,
+    index: usize,
+) -> Result<(), String> {
+    review(&state, &file, |image| image.delete_detection(index))
+}
+
+/// Apply exclusion masks to the results of this session, returning how many detections were
+/// dropped
+#[tauri::command]
+fn apply_masks(state: tauri::State<'_, AppState>, masks: MaskOptions) -> usize {
+    masks::apply_masks(Arc::make_mut(&mut state.0.lock().unwrap().results), &masks)
+}
+
 /// Show the main window, this is used to reduce the flicker when the app is started
 /// and the window is hidden by default.
 #[tauri::command]
@@ -357,6 +806,20 @@ fn main() {
             process,
             export,
             export_image_set,
+            export_contact_sheet_set,
+            export_html_gallery,
+            cancel_export,
+            image_detections,
+            review_image,
+            review_detection,
+            add_detection,
+            correct_detection,
+            delete_detection,
+            apply_masks,
+            start_watch,
+            stop_watch,
+            system_info,
+            evaluate_results,
             showup
         ])
         .run(context)
@@ -0,0 +1,306 @@
+//! Masks module
+//!
+//! Drops detections in areas of the frame that hold no animals, such as the information bar trail
+//! cameras print along the top or bottom of their photos, whose logos the model can take for
+//! vehicles or humans. Masks are drawn in normalized coordinates so one mask fits every image
+//! size a camera takes, and can be limited to a single camera.
+//!
+
+use crate::metadata::ImageMetadata;
+use crate::structures::{CamTrapDetection, CamTrapImageDetections, DetectionSource, ReviewStatus};
+use serde::{Deserialize, Serialize};
+
+/// An area of the frame, in fractions (0-1) of the image width and height
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MaskShape {
+    /// The top-left corner, width and height
+    Rectangle {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+
+    /// The corners of a polygon, in order around it
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+impl MaskShape {
+    /// The corners of the shape, in order around it
+    fn points(&self) -> Vec<[f32; 2]> {
+        match self {
+            MaskShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => vec![
+                [*x, *y],
+                [x + width, *y],
+                [x + width, y + height],
+                [*x, y + height],
+            ],
+            MaskShape::Polygon { points } => points.clone(),
+        }
+    }
+
+    /// The area of a box, as `[left, top, right, bottom]`, that is inside the shape
+    fn overlap_area(&self, bbox: [f32; 4]) -> f32 {
+        polygon_area(&clip_polygon(self.points(), bbox))
+    }
+}
+
+/// Clip a polygon to a box, as `[left, top, right, bottom]`, with the Sutherland-Hodgman
+/// algorithm
+///
+/// Clipping to each edge of the box in turn keeps the area of the polygon inside it, even for
+/// concave polygons.
+fn clip_polygon(mut points: Vec<[f32; 2]>, bbox: [f32; 4]) -> Vec<[f32; 2]> {
+    let [left, top, right, bottom] = bbox;
+
+    // The signed distance of a point inside each edge, positive when inside
+    let inside = |edge: usize, p: [f32; 2]| match edge {
+        0 => p[0] - left,
+        1 => right - p[0],
+        2 => p[1] - top,
+        _ => bottom - p[1],
+    };
+
+    for edge in 0..4 {
+        let mut clipped = vec![];
+        for (i, current) in points.iter().enumerate() {
+            let previous = points[(i + points.len() - 1) % points.len()];
+            let (d_current, d_previous) = (inside(edge, *current), inside(edge, previous));
+
+            if (d_current >= 0.0) != (d_previous >= 0.0) {
+                let t = d_previous / (d_previous - d_current);
+                clipped.push([
+                    previous[0] + t * (current[0] - previous[0]),
+                    previous[1] + t * (current[1] - previous[1]),
+                ]);
+            }
+            if d_current >= 0.0 {
+                clipped.push(*current);
+            }
+        }
+        points = clipped;
+    }
+    points
+}
+
+/// The area of a polygon with the shoelace formula
+fn polygon_area(points: &[[f32; 2]]) -> f32 {
+    let twice_area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum();
+    twice_area.abs() / 2.0
+}
+
+/// Areas of the frame in which detections are dropped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExclusionMask {
+    /// The camera the mask applies to, matched against the serial number or model of an image,
+    /// or every image if none
+    #[serde(default)]
+    pub camera: Option<String>,
+
+    pub shapes: Vec<MaskShape>,
+}
+
+impl ExclusionMask {
+    /// Check if the mask applies to an image captured as described by its metadata
+    pub fn applies_to(&self, metadata: Option<&ImageMetadata>) -> bool {
+        let Some(camera) = &self.camera else {
+            return true;
+        };
+        metadata.is_some_and(|metadata| {
+            [&metadata.serial_number, &metadata.model]
+                .into_iter()
+                .flatten()
+                .any(|value| value.trim() == camera.trim())
+        })
+    }
+}
+
+/// The exclusion masks applied to detections, and how much of a detection must be masked for it
+/// to be dropped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaskOptions {
+    pub masks: Vec<ExclusionMask>,
+
+    /// The fraction of the box of a detection inside a single shape above which it is dropped
+    #[serde(default = "default_max_overlap")]
+    pub max_overlap: f32,
+}
+
+fn default_max_overlap() -> f32 {
+    0.5
+}
+
+impl Default for MaskOptions {
+    fn default() -> Self {
+        Self {
+            masks: vec![],
+            max_overlap: default_max_overlap(),
+        }
+    }
+}
+
+impl MaskOptions {
+    /// The largest fraction of the box of a detection, in pixels of an image of the given size,
+    /// inside any one shape of the masks that apply to the image
+    pub fn masked_fraction(
+        &self,
+        detection: &CamTrapDetection,
+        metadata: Option<&ImageMetadata>,
+        image_width: u32,
+        image_height: u32,
+    ) -> f32 {
+        let (width, height) = (image_width as f32, image_height as f32);
+        let area = detection.width / width * detection.height / height;
+        if area <= 0.0 {
+            return 0.0;
+        }
+
+        let bbox = [
+            detection.x / width,
+            detection.y / height,
+            (detection.x + detection.width) / width,
+            (detection.y + detection.height) / height,
+        ];
+        self.masks
+            .iter()
+            .filter(|mask| mask.applies_to(metadata))
+            .flat_map(|mask| &mask.shapes)
+            .map(|shape| shape.overlap_area(bbox) / area)
+            .fold(0.0, f32::max)
+    }
+
+    /// Drop the detections of an image masked above the maximum overlap, returning how many
+    ///
+    /// Only unreviewed model detections are dropped, those drawn or reviewed by a person are kept.
+    /// Images without a known size, e.g. that failed to process, are left as they are.
+    pub fn apply(&self, image: &mut CamTrapImageDetections) -> usize {
+        let (Some(width), Some(height)) = (image.image_width, image.image_height) else {
+            return 0;
+        };
+        if self.masks.is_empty() || width == 0 || height == 0 {
+            return 0;
+        }
+
+        let before = image.detections.len();
+        let metadata = image.metadata.as_ref();
+        image.detections.retain(|detection| {
+            detection.source != DetectionSource::Model
+                || detection.review != ReviewStatus::Unreviewed
+                || self.masked_fraction(detection, metadata, width, height) <= self.max_overlap
+        });
+        before - image.detections.len()
+    }
+}
+
+/// Apply exclusion masks to stored results, e.g. after drawing a new mask, returning how many
+/// detections were dropped
+pub fn apply_masks(results: &mut [CamTrapImageDetections], options: &MaskOptions) -> usize {
+    results.iter_mut().map(|image| options.apply(image)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap_area() {
+        let rectangle = MaskShape::Rectangle {
+            x: 0.0,
+            y: 0.9,
+            width: 1.0,
+            height: 0.1,
+        };
+        assert!((rectangle.overlap_area([0.5, 0.8, 0.7, 1.0]) - 0.02).abs() < 1e-6);
+        assert_eq!(rectangle.overlap_area([0.5, 0.1, 0.7, 0.5]), 0.0);
+
+        // A concave L shape, covering the left column and bottom row of a 2 by 2 grid
+        let polygon = MaskShape::Polygon {
+            points: vec![
+                [0.0, 0.0],
+                [0.5, 0.0],
+                [0.5, 0.5],
+                [1.0, 0.5],
+                [1.0, 1.0],
+                [0.0, 1.0],
+            ],
+        };
+        assert!((polygon.overlap_area([0.0, 0.0, 1.0, 1.0]) - 0.75).abs() < 1e-6);
+        assert!((polygon.overlap_area([0.25, 0.25, 0.75, 0.75]) - 0.1875).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_apply() {
+        let detection = |y, source, review| CamTrapDetection {
+            x: 100.0,
+            y,
+            width: 200.0,
+            height: 100.0,
+            class_index: 2,
+            confidence: 0.8,
+            source,
+            review,
+        };
+        let mut image = CamTrapImageDetections {
+            file: String::from("a.jpg"),
+            error: None,
+            image_width: Some(1000),
+            image_height: Some(1000),
+            detections: vec![
+                detection(920.0, DetectionSource::Model, ReviewStatus::Unreviewed),
+                detection(850.0, DetectionSource::Model, ReviewStatus::Unreviewed),
+                detection(920.0, DetectionSource::Model, ReviewStatus::Verified),
+                detection(920.0, DetectionSource::Human, ReviewStatus::Unreviewed),
+            ],
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: Some(ImageMetadata {
+                model: Some(String::from("HC600")),
+                ..Default::default()
+            }),
+        };
+
+        let bar = |camera: Option<&str>| ExclusionMask {
+            camera: camera.map(String::from),
+            shapes: vec![MaskShape::Rectangle {
+                x: 0.0,
+                y: 0.9,
+                width: 1.0,
+                height: 0.1,
+            }],
+        };
+
+        // Another camera's mask does not apply
+        let mut options = MaskOptions {
+            masks: vec![bar(Some("XR6"))],
+            ..Default::default()
+        };
+        assert_eq!(options.apply(&mut image.clone()), 0);
+
+        // 80% and 50% of the first two boxes are masked, and reviewed detections are kept
+        options.masks = vec![bar(Some("HC600"))];
+        assert_eq!(options.apply(&mut image.clone()), 1);
+
+        options.max_overlap = 0.4;
+        assert_eq!(apply_masks(std::slice::from_mut(&mut image), &options), 2);
+        assert_eq!(image.detections.len(), 2);
+        assert!(image
+            .detections
+            .iter()
+            .all(|detection| detection.source == DetectionSource::Human
+                || detection.review == ReviewStatus::Verified));
+
+        // A global mask applies to images without metadata
+        image.metadata = None;
+        assert!(bar(None).applies_to(None));
+        assert!(!bar(Some("HC600")).applies_to(image.metadata.as_ref()));
+    }
+}
@@ -1 +1,138 @@
-pub const CATEGORIES: [&str; 4] = ["Empty", "Animal", "Human", "Vehicle"];
+/// A category of the category table, the single mapping between the classes the model outputs
+/// and the ids, names and colours used by exports and filters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Category {
+    /// The id written to JSON exports
+    pub id: u32,
+
+    /// The name written to CSV exports, labels and folder names
+    pub name: &'static str,
+
+    /// The default RGB colour boxes are drawn with
+    pub color: [u8; 3],
+
+    /// The class index the model outputs, `None` for the empty pseudo-category of images without
+    /// detections
+    pub model_index: Option<u32>,
+}
+
+impl Category {
+    pub const EMPTY: Self = Self {
+        id: 0,
+        name: "Empty",
+        color: [0, 0, 0],
+        model_index: None,
+    };
+
+    pub const ANIMAL: Self = Self {
+        id: 1,
+        name: "Animal",
+        color: [255, 255, 255],
+        model_index: Some(0),
+    };
+
+    pub const HUMAN: Self = Self {
+        id: 2,
+        name: "Human",
+        color: [255, 0, 0],
+        model_index: Some(1),
+    };
+
+    pub const VEHICLE: Self = Self {
+        id: 3,
+        name: "Vehicle",
+        color: [0, 0, 255],
+        model_index: Some(2),
+    };
+
+    /// The category of a class the model outputs
+    pub fn from_model_index(index: u32) -> Option<Self> {
+        CATEGORIES
+            .into_iter()
+            .find(|category| category.model_index == Some(index))
+    }
+
+    /// The category with an id from a JSON export
+    pub fn from_id(id: u32) -> Option<Self> {
+        CATEGORIES.into_iter().find(|category| category.id == id)
+    }
+
+    /// The category with a name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        CATEGORIES
+            .into_iter()
+            .find(|category| category.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The id of the category of a model class
+    ///
+    /// Classes outside the table, e.g. from a custom model, keep the offset of the table's.
+    pub fn id_of(model_index: u32) -> u32 {
+        Self::from_model_index(model_index).map_or(model_index + 1, |category| category.id)
+    }
+
+    /// The model class of a category id, the inverse of [`Category::id_of`]
+    pub fn model_index_of(id: u32) -> u32 {
+        Self::from_id(id)
+            .and_then(|category| category.model_index)
+            .unwrap_or(id.saturating_sub(1))
+    }
+
+    /// The name of the category of a model class, `Unknown` for classes outside the table
+    pub fn name_of(model_index: u32) -> &'static str {
+        Self::from_model_index(model_index).map_or("Unknown", |category| category.name)
+    }
+}
+
+/// Every category, in order of id
+pub const CATEGORIES: [Category; 4] = [
+    Category::EMPTY,
+    Category::ANIMAL,
+    Category::HUMAN,
+    Category::VEHICLE,
+];
+
+/// The categories the model detects, in order of model index
+pub fn model_categories() -> impl Iterator<Item = Category> {
+    CATEGORIES
+        .into_iter()
+        .filter(|category| category.model_index.is_some())
+}
+
+/// The file name of the bundled MegaDetector model, relative to the resource directory
+pub const MODEL_FILE: &str = "../md_v1000.0.0-redwood-dynamic.onnx";
+
+/// The input size of the bundled MegaDetector model
+pub const MODEL_INPUT_SIZE: (usize, usize) = (640, 640);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_table() {
+        for (i, category) in CATEGORIES.iter().enumerate() {
+            assert_eq!(category.id, i as u32);
+            assert_eq!(Category::from_id(category.id), Some(*category));
+            assert_eq!(Category::from_name(category.name), Some(*category));
+        }
+
+        let model: Vec<Category> = model_categories().collect();
+        assert_eq!(
+            model,
+            vec![Category::ANIMAL, Category::HUMAN, Category::VEHICLE]
+        );
+        for (i, category) in model.iter().enumerate() {
+            assert_eq!(category.model_index, Some(i as u32));
+            assert_eq!(Category::from_model_index(i as u32), Some(*category));
+        }
+
+        assert_eq!(Category::from_model_index(3), None);
+        assert_eq!(Category::id_of(2), Category::VEHICLE.id);
+        assert_eq!(Category::id_of(5), 6);
+        assert_eq!(Category::model_index_of(Category::id_of(5)), 5);
+        assert_eq!(Category::name_of(1), "Human");
+        assert_eq!(Category::name_of(7), "Unknown");
+        assert_eq!(Category::from_name(" vehicle "), Some(Category::VEHICLE));
+    }
+}
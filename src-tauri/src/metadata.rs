@@ -0,0 +1,322 @@
+//! Metadata module
+//!
+//! Extracts when and how an image was captured from its EXIF data, the timestamp, camera and
+//! ambient temperature, for ecological analyses. The maker notes of Reconyx HyperFire cameras are
+//! also read for the moon phase, trigger and burst sequence the camera records alongside them.
+//!
+
+use crate::util::exif::read_capture_tags;
+use crate::util::magic_image::read_exif;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The phase of the moon when a photo was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoonPhase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+/// What triggered the camera to take a photo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    Motion,
+    TimeLapse,
+    External,
+}
+
+/// How an image was captured, as recorded by the camera
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageMetadata {
+    /// When the photo was taken in the camera's local time, as `YYYY-MM-DD HH:MM:SS`
+    pub timestamp: Option<String>,
+
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial_number: Option<String>,
+
+    /// Ambient temperature in degrees Celsius
+    pub temperature: Option<f32>,
+
+    pub moon_phase: Option<MoonPhase>,
+    pub trigger: Option<Trigger>,
+
+    /// The position of the photo in the burst taken for a trigger, from 1
+    pub sequence_index: Option<u32>,
+
+    /// The number of photos in the burst taken for a trigger
+    pub sequence_length: Option<u32>,
+}
+
+impl ImageMetadata {
+    /// Extract the metadata from raw EXIF data
+    pub fn from_exif(exif: &[u8]) -> Result<Self> {
+        let tags = read_capture_tags(exif)?;
+
+        let mut metadata = Self {
+            timestamp: tags.date_time_original.as_deref().and_then(exif_timestamp),
+            make: tags.make,
+            model: tags.model,
+            serial_number: tags.serial_number,
+            temperature: tags.ambient_temperature,
+            ..Default::default()
+        };
+
+        if let Some(note) = tags.maker_note.as_deref().and_then(HyperFireNote::parse) {
+            metadata.timestamp = metadata.timestamp.or(note.timestamp);
+            metadata.serial_number = metadata.serial_number.or(note.serial_number);
+            metadata.temperature = metadata.temperature.or(Some(note.temperature));
+            metadata.moon_phase = note.moon_phase;
+            metadata.trigger = note.trigger;
+            metadata.sequence_index = Some(note.sequence.0);
+            metadata.sequence_length = Some(note.sequence.1);
+        }
+
+        Ok(metadata)
+    }
+
+    /// Read the metadata of a JPEG or PNG image file, `None` when it has no EXIF data
+    pub fn read(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let image_bytes = std::fs::read(path.as_ref())?;
+        let format = image::guess_format(&image_bytes)?;
+        Self::from_image_bytes(image_bytes, format)
+    }
+
+    /// Extract the metadata of an encoded image, `None` when it has no EXIF data
+    pub(crate) fn from_image_bytes(
+        image_bytes: Vec<u8>,
+        format: image::ImageFormat,
+    ) -> Result<Option<Self>> {
+        read_exif(image_bytes, format)?
+            .map(|exif| Self::from_exif(&exif))
+            .transpose()
+    }
+}
+
+/// Convert an EXIF `YYYY:MM:DD HH:MM:SS` date time to `YYYY-MM-DD HH:MM:SS`
+///
+/// Cameras whose clock was never set write blanks or zeros, which are treated as unknown.
+fn exif_timestamp(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 19 || !bytes[..19].is_ascii() || bytes[4] != b':' || bytes[7] != b':' {
+        return None;
+    }
+    if !bytes[..4].iter().all(u8::is_ascii_digit) || value.starts_with("0000") {
+        return None;
+    }
+
+    Some(format!(
+        "{}-{}-{}",
+        &value[..4],
+        &value[5..7],
+        &value[8..19]
+    ))
+}
+
+/// The fields of a Reconyx HyperFire maker note, a little-endian array of 16-bit words
+///
+/// The layout follows ExifTool's Reconyx HyperFire table: the trigger mode at word 6, sequence at
+/// 7, date and time from the seconds at 11, moon phase at 17, temperature in Fahrenheit then
+/// Celsius at 18 and 19, and the serial number from 20.
+struct HyperFireNote {
+    trigger: Option<Trigger>,
+    sequence: (u32, u32),
+    timestamp: Option<String>,
+    moon_phase: Option<MoonPhase>,
+    temperature: f32,
+    serial_number: Option<String>,
+}
+
+impl HyperFireNote {
+    /// The number of words up to and including the serial number
+    const WORDS: usize = 35;
+
+    /// Parse a maker note, `None` if it is not from a HyperFire camera
+    fn parse(note: &[u8]) -> Option<Self> {
+        if note.len() < Self::WORDS * 2 || note[0..2] != [0x01, 0xF1] || note[3] != 0 {
+            return None;
+        }
+        if !matches!(note[2], 0x02 | 0x03) {
+            return None;
+        }
+
+        let word = |index: usize| u16::from_le_bytes([note[index * 2], note[index * 2 + 1]]);
+
+        let trigger = match note[12] {
+            b'M' => Some(Trigger::Motion),
+            b'T' => Some(Trigger::TimeLapse),
+            b'E' => Some(Trigger::External),
+            _ => None,
+        };
+
+        let [second, minute, hour, day, month, year] = [11, 12, 13, 14, 15, 16].map(word);
+        let timestamp = (year > 0).then(|| {
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                year, month, day, hour, minute, second
+            )
+        });
+
+        let moon_phase = match word(17) {
+            0 => Some(MoonPhase::New),
+            1 => Some(MoonPhase::WaxingCrescent),
+            2 => Some(MoonPhase::FirstQuarter),
+            3 => Some(MoonPhase::WaxingGibbous),
+            4 => Some(MoonPhase::Full),
+            5 => Some(MoonPhase::WaningGibbous),
+            6 => Some(MoonPhase::LastQuarter),
+            7 => Some(MoonPhase::WaningCrescent),
+            _ => None,
+        };
+
+        // UTF-16 and NUL terminated
+        let serial: Vec<u16> = (20..Self::WORDS)
+            .map(word)
+            .take_while(|unit| *unit != 0)
+            .collect();
+        let serial_number = String::from_utf16(&serial)
+            .ok()
+            .map(|serial| serial.trim().to_string())
+            .filter(|serial| !serial.is_empty());
+
+        Some(Self {
+            trigger,
+            sequence: (word(7) as u32, word(8) as u32),
+            timestamp,
+            moon_phase,
+            temperature: word(19) as i16 as f32,
+            serial_number,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build little-endian EXIF data from a Reconyx camera, with a make, a HyperFire maker note and
+    /// optionally a DateTimeOriginal
+    fn build_exif(date_time_original: Option<&str>) -> Vec<u8> {
+        let mut data = vec![0u8; 176];
+        let write_u16 = |data: &mut Vec<u8>, offset: usize, value: u16| {
+            data[offset..offset + 2].copy_from_slice(&value.to_le_bytes())
+        };
+        let write_u32 = |data: &mut Vec<u8>, offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        let write_entry = |data: &mut Vec<u8>, offset: usize, entry: [u32; 4]| {
+            data[offset..offset + 2].copy_from_slice(&(entry[0] as u16).to_le_bytes());
+            data[offset + 2..offset + 4].copy_from_slice(&(entry[1] as u16).to_le_bytes());
+            data[offset + 4..offset + 8].copy_from_slice(&entry[2].to_le_bytes());
+            data[offset + 8..offset + 12].copy_from_slice(&entry[3].to_le_bytes());
+        };
+
+        data[0..2].copy_from_slice(b"II");
+        write_u16(&mut data, 2, 42);
+        write_u32(&mut data, 4, 8);
+
+        // IFD0 at 8: Make stored at 40 and the Exif pointer
+        write_u16(&mut data, 8, 2);
+        write_entry(&mut data, 10, [0x010F, 2, 8, 40]);
+        write_entry(&mut data, 22, [0x8769, 4, 1, 50]);
+        data[40..48].copy_from_slice(b"RECONYX\0");
+
+        // Exif IFD at 50: MakerNote stored at 80, and DateTimeOriginal stored at 152 if given
+        let entries = if date_time_original.is_some() { 2 } else { 1 };
+        write_u16(&mut data, 50, entries);
+        write_entry(&mut data, 52, [0x927C, 7, 70, 80]);
+        if let Some(value) = date_time_original {
+            write_entry(&mut data, 64, [0x9003, 2, 20, 152]);
+            data[152..171].copy_from_slice(value.as_bytes());
+        }
+
+        // Version and firmware, trigger, sequence, event number, second to year, moon phase, and
+        // temperature in Fahrenheit and Celsius
+        let mut note = vec![0xF101, 3, 1, 2, 2011, 501];
+        note.push(u16::from_le_bytes(*b"M\0"));
+        note.extend([2, 3, 0, 42]);
+        note.extend([5, 14, 21, 1, 6, 2023]);
+        note.extend([4, 68, 20]);
+        for (i, word) in note.iter().enumerate() {
+            write_u16(&mut data, 80 + i * 2, *word);
+        }
+        for (i, unit) in "H500ABC".encode_utf16().enumerate() {
+            write_u16(&mut data, 80 + (20 + i) * 2, unit);
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_hyperfire_metadata() {
+        let metadata = ImageMetadata::from_exif(&build_exif(None)).unwrap();
+        assert_eq!(
+            metadata,
+            ImageMetadata {
+                timestamp: Some(String::from("2023-06-01 21:14:05")),
+                make: Some(String::from("RECONYX")),
+                model: None,
+                serial_number: Some(String::from("H500ABC")),
+                temperature: Some(20.0),
+                moon_phase: Some(MoonPhase::Full),
+                trigger: Some(Trigger::Motion),
+                sequence_index: Some(2),
+                sequence_length: Some(3),
+            }
+        );
+
+        // The standard tag is preferred over the maker note
+        let exif = build_exif(Some("2023:06:02 06:00:00"));
+        let metadata = ImageMetadata::from_exif(&exif).unwrap();
+        assert_eq!(metadata.timestamp.as_deref(), Some("2023-06-02 06:00:00"));
+    }
+
+    #[test]
+    fn test_hyperfire_fixture() {
+        // A JPEG with a maker note written field by field from ExifTool's HyperFire table,
+        // including the firmware, event number, image settings and user label that are not read
+        let path = "./tests/fixtures/metadata/hyperfire.jpg";
+        let metadata = ImageMetadata::read(path).unwrap().unwrap();
+        assert_eq!(
+            metadata,
+            ImageMetadata {
+                timestamp: Some(String::from("2023-06-01 21:14:05")),
+                make: Some(String::from("RECONYX")),
+                model: Some(String::from("HC600 HYPERFIRE")),
+                serial_number: Some(String::from("H500ABC")),
+                temperature: Some(20.0),
+                moon_phase: Some(MoonPhase::Full),
+                trigger: Some(Trigger::Motion),
+                sequence_index: Some(2),
+                sequence_length: Some(3),
+            }
+        );
+
+        // The maker note's own date and time agrees with DateTimeOriginal
+        let exif = read_exif(std::fs::read(path).unwrap(), image::ImageFormat::Jpeg)
+            .unwrap()
+            .unwrap();
+        let note = read_capture_tags(&exif).unwrap().maker_note.unwrap();
+        let note = HyperFireNote::parse(&note).unwrap();
+        assert_eq!(note.timestamp, metadata.timestamp);
+    }
+
+    #[test]
+    fn test_exif_timestamp() {
+        assert_eq!(
+            exif_timestamp("2023:06:01 21:14:05").as_deref(),
+            Some("2023-06-01 21:14:05")
+        );
+        assert_eq!(exif_timestamp("0000:00:00 00:00:00"), None);
+        assert_eq!(exif_timestamp("    :  :     :  :  "), None);
+        assert_eq!(exif_timestamp(""), None);
+    }
+}
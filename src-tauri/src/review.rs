@@ -0,0 +1,222 @@
+//! Review module
+//!
+//! Human-in-the-loop verification and correction of detections. Model detections are never
+//! changed or removed by a review, corrections are recorded as new human detections alongside
+//! the rejected model detection so both can be exported.
+//!
+
+use crate::error::{Error, Result};
+use crate::structures::{CamTrapDetection, CamTrapImageDetections, DetectionSource, ReviewStatus};
+use serde::{Deserialize, Serialize};
+
+/// A bounding box in pixels, as drawn by a reviewer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReviewBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Which detections to include in an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LabelSelection {
+    /// Every model and human detection, including rejected ones
+    #[default]
+    Both,
+
+    /// Only the model's detections, as if nothing had been reviewed
+    Model,
+
+    /// Only verified model detections and human detections
+    Reviewed,
+}
+
+impl LabelSelection {
+    /// Check if a detection should be included
+    pub fn includes(&self, detection: &CamTrapDetection) -> bool {
+        match self {
+            LabelSelection::Both => true,
+            LabelSelection::Model => detection.source == DetectionSource::Model,
+            LabelSelection::Reviewed => match detection.source {
+                DetectionSource::Model => detection.review == ReviewStatus::Verified,
+                DetectionSource::Human => detection.review != ReviewStatus::Rejected,
+            },
+        }
+    }
+
+    /// A copy of the image with only the selected detections
+    pub fn select(&self, image: &CamTrapImageDetections) -> CamTrapImageDetections {
+        let mut selected = image.clone();
+        selected
+            .detections
+            .retain(|detection| self.includes(detection));
+        selected
+    }
+}
+
+impl CamTrapImageDetections {
+    fn detection_mut(&mut self, index: usize) -> Result<&mut CamTrapDetection> {
+        let file = &self.file;
+        self.detections
+            .get_mut(index)
+            .ok_or_else(|| Error::InvalidInput(format!("No detection {} in {}", index, file)))
+    }
+
+    /// Mark a detection as verified or rejected
+    pub fn review_detection(&mut self, index: usize, status: ReviewStatus) -> Result<()> {
+        self.detection_mut(index)?.review = status;
+        Ok(())
+    }
+
+    /// Add a detection drawn by a reviewer
+    ///
+    /// Returns the index of the new detection.
+    pub fn add_detection(&mut self, bbox: ReviewBox, class_index: u32) -> usize {
+        self.detections.push(CamTrapDetection {
+            x: bbox.x,
+            y: bbox.y,
+            width: bbox.width,
+            height: bbox.height,
+            class_index,
+            confidence: 1.0,
+            source: DetectionSource::Human,
+            review: ReviewStatus::Verified,
+        });
+        self.detections.len() - 1
+    }
+
+    /// Correct the box and/or class of a detection
+    ///
+    /// Human detections are edited in place. Model detections are rejected and a corrected human
+    /// copy added, so the model's output is kept. Returns the index of the corrected detection.
+    pub fn correct_detection(
+        &mut self,
+        index: usize,
+        bbox: Option<ReviewBox>,
+        class_index: Option<u32>,
+    ) -> Result<usize> {
+        let detection = self.detection_mut(index)?;
+
+        let corrected_index = if detection.source == DetectionSource::Human {
+            index
+        } else {
+            detection.review = ReviewStatus::Rejected;
+            let mut copy = detection.clone();
+            copy.source = DetectionSource::Human;
+            copy.confidence = 1.0;
+            self.detections.push(copy);
+            self.detections.len() - 1
+        };
+
+        let corrected = &mut self.detections[corrected_index];
+        if let Some(bbox) = bbox {
+            corrected.x = bbox.x;
+            corrected.y = bbox.y;
+            corrected.width = bbox.width;
+            corrected.height = bbox.height;
+        }
+        if let Some(class_index) = class_index {
+            corrected.class_index = class_index;
+        }
+        corrected.review = ReviewStatus::Verified;
+
+        Ok(corrected_index)
+    }
+
+    /// Delete a detection
+    ///
+    /// Human detections are removed, model detections are rejected so the model's output is
+    /// kept. Note that removing a detection shifts the index of those after it.
+    pub fn delete_detection(&mut self, index: usize) -> Result<()> {
+        if self.detection_mut(index)?.source == DetectionSource::Human {
+            self.detections.remove(index);
+        } else {
+            self.detections[index].review = ReviewStatus::Rejected;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_image() -> CamTrapImageDetections {
+        CamTrapImageDetections {
+            file: String::from("test.jpg"),
+            error: None,
+            image_width: Some(100),
+            image_height: Some(100),
+            detections: vec![CamTrapDetection {
+                x: 10.0,
+                y: 10.0,
+                width: 20.0,
+                height: 20.0,
+                class_index: 0,
+                confidence: 0.8,
+                source: DetectionSource::Model,
+                review: ReviewStatus::Unreviewed,
+            }],
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        }
+    }
+
+    const BOX: ReviewBox = ReviewBox {
+        x: 1.0,
+        y: 2.0,
+        width: 3.0,
+        height: 4.0,
+    };
+
+    #[test]
+    fn test_correct_model_detection() {
+        let mut image = create_image();
+        let index = image.correct_detection(0, None, Some(1)).unwrap();
+
+        assert_eq!(index, 1);
+        assert_eq!(image.detections[0].review, ReviewStatus::Rejected);
+        assert_eq!(image.detections[0].class_index, 0);
+        assert_eq!(image.detections[1].source, DetectionSource::Human);
+        assert_eq!(image.detections[1].review, ReviewStatus::Verified);
+        assert_eq!(image.detections[1].class_index, 1);
+        assert_eq!(image.detections[1].x, 10.0);
+
+        // Correcting the human detection again edits it in place
+        let index = image.correct_detection(1, Some(BOX), None).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(image.detections.len(), 2);
+        assert_eq!(image.detections[1].x, 1.0);
+        assert_eq!(image.detections[1].class_index, 1);
+    }
+
+    #[test]
+    fn test_delete_detection() {
+        let mut image = create_image();
+        image.add_detection(BOX, 2);
+
+        image.delete_detection(1).unwrap();
+        assert_eq!(image.detections.len(), 1);
+
+        image.delete_detection(0).unwrap();
+        assert_eq!(image.detections.len(), 1);
+        assert_eq!(image.detections[0].review, ReviewStatus::Rejected);
+
+        assert!(image.delete_detection(5).is_err());
+    }
+
+    #[test]
+    fn test_label_selection() {
+        let mut image = create_image();
+        image.detections.push(image.detections[0].clone());
+        image.review_detection(1, ReviewStatus::Verified).unwrap();
+        image.add_detection(BOX, 1);
+
+        let count = |selection: LabelSelection| selection.select(&image).detections.len();
+        assert_eq!(count(LabelSelection::Both), 3);
+        assert_eq!(count(LabelSelection::Model), 2);
+        assert_eq!(count(LabelSelection::Reviewed), 2);
+    }
+}
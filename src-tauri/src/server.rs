@@ -0,0 +1,509 @@
+//! Server module
+//!
+//! A local HTTP API so other tools, e.g. notebooks or web uploaders, can share a loaded model:
+//!
+//! - `GET /health` reports the server is up and how busy it is.
+//! - `POST /detect` runs detection on the image in the request body.
+//! - `POST /batch` runs detection on a file or folder on the server, given as
+//!   `{"path": "...", "recursive": false}`.
+//!
+//! `/detect` takes the confidence threshold as a `confidence` query parameter, `/batch` as a
+//! `confidence` field. Both respond with the same JSON as the JSON export. Requests beyond the
+//! concurrency limit are rejected with `503 Service Unavailable` rather than queued.
+//!
+//! `/batch` reads any path the server can, so the server should only listen on a loopback
+//! address unless the network is trusted.
+//!
+
+use crate::detector::{detect_path_with, Detector};
+use crate::error::{Error, Result};
+use crate::exports::json::{CamTrapJSONContainer, CamTrapJSONImageDetections};
+use crate::structures::{CamTrapDetection, CamTrapImageDetections, ReviewStatus};
+use crate::yolov5::helpers::enumerate_images;
+use image::{DynamicImage, GenericImageView};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+
+/// Runs detection for the server, shared between the threads handling requests
+///
+/// Implemented for a [`Detector`] behind a mutex, requests wait for the model while another is
+/// using it.
+pub trait SharedDetector: Send + Sync {
+    fn detect(
+        &self,
+        image: &DynamicImage,
+        confidence_threshold: f32,
+    ) -> Result<Vec<CamTrapDetection>>;
+}
+
+impl SharedDetector for Mutex<Detector> {
+    fn detect(
+        &self,
+        image: &DynamicImage,
+        confidence_threshold: f32,
+    ) -> Result<Vec<CamTrapDetection>> {
+        let mut detector = self
+            .lock()
+            .map_err(|_| Error::Model(String::from("The model is unavailable")))?;
+        detector.set_confidence_threshold(confidence_threshold);
+        detector.detect(image)
+    }
+}
+
+/// Options for running the server
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// The address to listen on, e.g. `127.0.0.1:8080`
+    pub address: String,
+
+    /// The maximum number of detection requests handled at once
+    pub max_concurrent: usize,
+
+    /// The confidence threshold used when a request does not give one
+    pub confidence_threshold: f32,
+
+    /// The maximum size of an uploaded image in bytes
+    pub max_body_bytes: usize,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            address: String::from("127.0.0.1:8080"),
+            max_concurrent: 4,
+            confidence_threshold: 0.2,
+            max_body_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The body of a `/batch` request
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    path: PathBuf,
+    #[serde(default)]
+    recursive: bool,
+    confidence: Option<f32>,
+}
+
+/// An error response
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+type HttpResult = Result<serde_json::Value, HttpError>;
+
+/// A detection server listening for requests
+pub struct DetectionServer<D> {
+    server: tiny_http::Server,
+    detector: Arc<D>,
+    options: ServerOptions,
+    active: Arc<AtomicUsize>,
+}
+
+impl<D: SharedDetector + 'static> DetectionServer<D> {
+    /// Start listening on the address in the options
+    pub fn bind(options: ServerOptions, detector: D) -> Result<Self> {
+        let server = tiny_http::Server::http(&options.address).map_err(std::io::Error::other)?;
+
+        Ok(Self {
+            server,
+            detector: Arc::new(detector),
+            options,
+            active: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// The address the server is listening on, useful when bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handle requests until `stop` is set
+    pub fn run(&self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            let request = match self.server.recv_timeout(Duration::from_millis(200)) {
+                Ok(Some(request)) => request,
+                Ok(None) => continue,
+                Err(err) => {
+                    eprintln!("Failed to receive request: {}", err);
+                    continue;
+                }
+            };
+
+            // Health checks are answered straight away, even when busy
+            if split_url(request.url()).0 == "/health" {
+                let result = self.health(&request);
+                respond(request, result);
+                continue;
+            }
+
+            if self.active.fetch_add(1, Ordering::SeqCst) >= self.options.max_concurrent {
+                self.active.fetch_sub(1, Ordering::SeqCst);
+                respond(
+                    request,
+                    Err(HttpError::new(503, "Too many requests, try again later")),
+                );
+                continue;
+            }
+
+            let detector = self.detector.clone();
+            let options = self.options.clone();
+            let active = self.active.clone();
+            std::thread::spawn(move || {
+                let (request, result) = handle(request, detector.as_ref(), &options);
+
+                // Free the slot before responding, so the client can send its next request
+                active.fetch_sub(1, Ordering::SeqCst);
+                respond(request, result);
+            });
+        }
+    }
+
+    fn health(&self, request: &Request) -> HttpResult {
+        if *request.method() != Method::Get {
+            return Err(HttpError::new(405, "Method not allowed"));
+        }
+
+        Ok(json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+            "active": self.active.load(Ordering::SeqCst),
+            "max_concurrent": self.options.max_concurrent,
+        }))
+    }
+}
+
+/// Split a request URL into its path and query
+fn split_url(url: &str) -> (&str, &str) {
+    url.split_once('?').unwrap_or((url, ""))
+}
+
+/// Route a detection request, returning the request to respond to with the result
+fn handle(
+    mut request: Request,
+    detector: &dyn SharedDetector,
+    options: &ServerOptions,
+) -> (Request, HttpResult) {
+    let (path, query) = split_url(request.url());
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let result = match (request.method(), path.as_str()) {
+        (Method::Post, "/detect") => detect(&mut request, &query, detector, options),
+        (Method::Post, "/batch") => batch(&mut request, detector, options),
+        (_, "/detect") | (_, "/batch") => Err(HttpError::new(405, "Method not allowed")),
+        _ => Err(HttpError::new(404, "Not found")),
+    };
+
+    (request, result)
+}
+
+/// Read a request's body, up to the size limit
+fn read_body(request: &mut Request, options: &ServerOptions) -> Result<Vec<u8>, HttpError> {
+    let too_large = || HttpError::new(413, "Request body too large");
+
+    if request.body_length().unwrap_or(0) > options.max_body_bytes {
+        return Err(too_large());
+    }
+
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(options.max_body_bytes as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| HttpError::new(400, format!("Failed to read request: {}", err)))?;
+
+    if body.len() > options.max_body_bytes {
+        return Err(too_large());
+    }
+
+    Ok(body)
+}
+
+/// The value of a query parameter
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn container(images: Vec<CamTrapImageDetections>) -> HttpResult {
+    let images: Vec<CamTrapJSONImageDetections> = images.into_iter().map(|i| i.into()).collect();
+    serde_json::to_value(CamTrapJSONContainer::new(images))
+        .map_err(|err| HttpError::new(500, err.to_string()))
+}
+
+fn detect(
+    request: &mut Request,
+    query: &str,
+    detector: &dyn SharedDetector,
+    options: &ServerOptions,
+) -> HttpResult {
+    let confidence_threshold = match query_param(query, "confidence") {
+        Some(value) => value
+            .parse()
+            .map_err(|_| HttpError::new(400, "Invalid confidence"))?,
+        None => options.confidence_threshold,
+    };
+
+    let body = read_body(request, options)?;
+    let image = image::load_from_memory(&body)
+        .map_err(|err| HttpError::new(400, format!("Invalid image: {}", err)))?;
+
+    let detections = detector
+        .detect(&image, confidence_threshold)
+        .map_err(|err| HttpError::new(500, format!("Detection failed: {}", err)))?;
+
+    let (width, height) = image.dimensions();
+    container(vec![CamTrapImageDetections {
+        file: query_param(query, "file").unwrap_or_default().to_string(),
+        error: None,
+        image_width: Some(width),
+        image_height: Some(height),
+        detections,
+        review: ReviewStatus::Unreviewed,
+        stamp: None,
+        metadata: None,
+    }])
+}
+
+fn batch(
+    request: &mut Request,
+    detector: &dyn SharedDetector,
+    options: &ServerOptions,
+) -> HttpResult {
+    let body = read_body(request, options)?;
+    let batch: BatchRequest = serde_json::from_slice(&body)
+        .map_err(|err| HttpError::new(400, format!("Invalid request: {}", err)))?;
+    let confidence_threshold = batch.confidence.unwrap_or(options.confidence_threshold);
+
+    let files = if batch.path.is_dir() {
+        enumerate_images(batch.path, batch.recursive)
+    } else if batch.path.is_file() {
+        vec![batch.path]
+    } else {
+        return Err(HttpError::new(
+            404,
+            format!("{} does not exist", batch.path.display()),
+        ));
+    };
+
+    container(
+        files
+            .iter()
+            .map(|file| {
+                detect_path_with(file, None, |image| {
+                    detector.detect(image, confidence_threshold)
+                })
+                .unwrap_or_else(|err| CamTrapImageDetections::failed(file, err))
+            })
+            .collect(),
+    )
+}
+
+fn respond(request: Request, result: HttpResult) {
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(err) => (err.status, json!({ "error": err.message })),
+    };
+
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("Content-Type is a valid header"),
+        );
+
+    if let Err(err) = request.respond(response) {
+        eprintln!("Failed to send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::DetectionSource;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+
+    /// Detects a single animal covering the whole image, optionally waiting to be released
+    struct FakeDetector {
+        release: Option<Mutex<mpsc::Receiver<()>>>,
+    }
+
+    impl SharedDetector for FakeDetector {
+        fn detect(
+            &self,
+            image: &DynamicImage,
+            confidence_threshold: f32,
+        ) -> Result<Vec<CamTrapDetection>> {
+            if let Some(release) = &self.release {
+                release.lock().unwrap().recv().unwrap();
+            }
+
+            Ok(vec![CamTrapDetection {
+                x: 0.0,
+                y: 0.0,
+                width: image.width() as f32,
+                height: image.height() as f32,
+                class_index: 0,
+                confidence: 0.9,
+                source: DetectionSource::Model,
+                review: ReviewStatus::Unreviewed,
+            }]
+            .into_iter()
+            .filter(|d| d.confidence >= confidence_threshold)
+            .collect())
+        }
+    }
+
+    /// Send a request and return the status code and JSON body
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(vec![]);
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    /// Run a server on a free port in the background, stopped when the returned flag is set
+    fn start(
+        detector: FakeDetector,
+        max_concurrent: usize,
+    ) -> (
+        Arc<DetectionServer<FakeDetector>>,
+        SocketAddr,
+        Arc<AtomicBool>,
+    ) {
+        let server = Arc::new(
+            DetectionServer::bind(
+                ServerOptions {
+                    address: String::from("127.0.0.1:0"),
+                    max_concurrent,
+                    ..Default::default()
+                },
+                detector,
+            )
+            .unwrap(),
+        );
+        let addr = server.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (running, running_stop) = (server.clone(), stop.clone());
+        std::thread::spawn(move || running.run(&running_stop));
+
+        (server, addr, stop)
+    }
+
+    #[test]
+    fn test_server() {
+        let (_server, addr, stop) = start(FakeDetector { release: None }, 2);
+
+        let dir = std::env::temp_dir().join("camtrap-server-batch");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.png"), png(4, 4)).unwrap();
+        std::fs::write(dir.join("b.png"), b"not an image").unwrap();
+
+        let (status, body) = send(addr, "GET", "/health", b"");
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(send(addr, "GET", "/health?probe=1", b"").0, 200);
+
+        let (status, body) = send(addr, "POST", "/detect?file=x.png", &png(8, 6));
+        assert_eq!(status, 200);
+        assert_eq!(body["images"][0]["file"], "x.png");
+        assert_eq!(body["images"][0]["image_width"], 8);
+        assert_eq!(body["images"][0]["detections"][0]["category"], 1);
+
+        let (status, body) = send(addr, "POST", "/detect?confidence=0.95", &png(8, 6));
+        assert_eq!(status, 200);
+        assert_eq!(body["images"][0]["detections"].as_array().unwrap().len(), 0);
+
+        let (status, _) = send(addr, "POST", "/detect", b"not an image");
+        assert_eq!(status, 400);
+
+        let request = json!({ "path": dir }).to_string();
+        let (status, body) = send(addr, "POST", "/batch", request.as_bytes());
+        assert_eq!(status, 200);
+        let mut images = body["images"].as_array().unwrap().clone();
+        images.sort_by_key(|image| image["file"].as_str().unwrap().to_string());
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0]["detections"].as_array().unwrap().len(), 1);
+        assert!(images[1]["error"].is_string());
+
+        let request = json!({ "path": dir.join("missing") }).to_string();
+        assert_eq!(send(addr, "POST", "/batch", request.as_bytes()).0, 404);
+
+        assert_eq!(send(addr, "GET", "/detect", b"").0, 405);
+        assert_eq!(send(addr, "GET", "/unknown", b"").0, 404);
+
+        stop.store(true, Ordering::Relaxed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_server_concurrency_limit() {
+        let (release, receiver) = mpsc::channel();
+        let detector = FakeDetector {
+            release: Some(Mutex::new(receiver)),
+        };
+        let (server, addr, stop) = start(detector, 1);
+
+        // Hold the only slot with a request waiting to be released
+        let blocked = std::thread::spawn(move || send(addr, "POST", "/detect", &png(4, 4)));
+        while server.active.load(Ordering::SeqCst) == 0 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(send(addr, "POST", "/detect", &png(4, 4)).0, 503);
+        assert_eq!(send(addr, "GET", "/health", b"").1["active"], 1);
+
+        release.send(()).unwrap();
+        assert_eq!(blocked.join().unwrap().0, 200);
+
+        stop.store(true, Ordering::Relaxed);
+    }
+}
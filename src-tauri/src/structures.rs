@@ -1,7 +1,8 @@
+use crate::metadata::ImageMetadata;
 use serde::{Deserialize, Serialize};
 
 /// A structure to hold the detections found in an image
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CamTrapImageDetections {
     /// The file path of the image
     pub file: String,
@@ -17,6 +18,86 @@ pub struct CamTrapImageDetections {
 
     /// The detections found in the image
     pub detections: Vec<CamTrapDetection>,
+
+    /// Whether a person has reviewed the image as a whole, e.g. confirmed it is empty
+    #[serde(default)]
+    pub review: ReviewStatus,
+
+    /// The size and modification time of the file when it was processed
+    #[serde(default)]
+    pub stamp: Option<FileStamp>,
+
+    /// How the image was captured, read from its EXIF data
+    #[serde(default)]
+    pub metadata: Option<ImageMetadata>,
+}
+
+impl CamTrapImageDetections {
+    /// The result for an image that could not be processed
+    pub fn failed(file: &std::path::Path, error: impl ToString) -> Self {
+        Self {
+            file: file.to_string_lossy().to_string(),
+            error: Some(error.to_string()),
+            image_width: None,
+            image_height: None,
+            detections: vec![],
+            review: ReviewStatus::Unreviewed,
+            stamp: FileStamp::read(file).ok(),
+            metadata: None,
+        }
+    }
+}
+
+/// The size and modification time of a file, used to tell if it changed since it was processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    /// The size of the file in bytes
+    pub size: u64,
+
+    /// The modification time in seconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl FileStamp {
+    /// Read the stamp of a file from its metadata
+    pub fn read(path: &std::path::Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        Ok(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Where a detection came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectionSource {
+    /// Predicted by the model
+    #[default]
+    Model,
+
+    /// Added or corrected by a person
+    Human,
+}
+
+/// The outcome of a person reviewing an image or detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReviewStatus {
+    /// Not yet reviewed
+    #[default]
+    Unreviewed,
+
+    /// Confirmed as correct
+    Verified,
+
+    /// Marked as incorrect, kept so the model's output is still available
+    Rejected,
 }
 
 /// An individual detection found in an image
@@ -39,6 +120,69 @@ pub struct CamTrapDetection {
 
     /// The confidence of the detection
     pub confidence: f32,
+
+    /// Whether the detection was predicted by the model or added by a person
+    #[serde(default)]
+    pub source: DetectionSource,
+
+    /// Whether a person has verified or rejected the detection
+    #[serde(default)]
+    pub review: ReviewStatus,
+}
+
+/// How a set of results was produced, recorded in exports
+///
+/// Every field has a default so exports from older versions, without some fields, still load.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunInfo {
+    /// The version of this app
+    pub app_version: String,
+
+    /// The file name of the model, without its folder
+    pub model_file: String,
+
+    /// The SHA-256 of the model file as lowercase hex, identifying it even if renamed
+    pub model_sha256: String,
+
+    /// The width and height the model runs at in pixels
+    pub input_size: (usize, usize),
+
+    pub confidence_threshold: f32,
+    pub nms_threshold: f32,
+
+    /// The execution provider that ran the model
+    pub execution_provider: String,
+
+    /// Slower inference modes used, e.g. tiling or test-time augmentation
+    pub inference: super::yolov5::InferenceOptions,
+
+    /// When processing started and finished, in seconds since the Unix epoch. Not finished while
+    /// a folder is still being watched.
+    pub started: u64,
+    pub finished: Option<u64>,
+
+    /// The operating system and CPU architecture, e.g. `linux-x86_64`
+    pub os: String,
+
+    /// The number of images in the results
+    pub images: usize,
+}
+
+impl RunInfo {
+    /// Record that processing finished now, with the given number of images
+    pub fn finish(&mut self, images: usize) {
+        self.finished = Some(unix_time_now());
+        self.images = images;
+    }
+}
+
+/// The current time in seconds since the Unix epoch
+pub(crate) fn unix_time_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
 }
 
 impl From<super::yolov5::YoloDetection> for CamTrapDetection {
@@ -50,6 +194,8 @@ impl From<super::yolov5::YoloDetection> for CamTrapDetection {
             height: yolo.height,
             class_index: yolo.class_index,
             confidence: yolo.confidence,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
         }
     }
 }
@@ -62,6 +208,9 @@ impl From<super::yolov5::YoloImageDetections> for CamTrapImageDetections {
             image_width: Some(yolo.image_width),
             image_height: Some(yolo.image_height),
             detections: yolo.detections.into_iter().map(|d| d.into()).collect(),
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
         }
     }
 }
@@ -0,0 +1,506 @@
+//! EXIF module
+//!
+//! This module contains the logic to read capture details from, and remove privacy sensitive tags
+//! from, raw EXIF (TIFF) data.
+//!
+//! Tags are removed in place so the offsets of all other tags remain valid and the data can be
+//! written back into the original image unchanged otherwise.
+//!
+
+use crate::error::{Error, Result};
+
+/// GPS IFD pointer tag in IFD0
+const TAG_GPS_IFD: u16 = 0x8825;
+
+/// Exif IFD pointer tag in IFD0
+const TAG_EXIF_IFD: u16 = 0x8769;
+
+/// Camera make and model tags in IFD0
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+
+/// Serial number tags, BodySerialNumber in the Exif IFD and the older CameraSerialNumber in IFD0
+const TAG_BODY_SERIAL_NUMBER: u16 = 0xA431;
+const TAG_CAMERA_SERIAL_NUMBER: u16 = 0xC62F;
+
+/// Tags holding serial numbers of the camera or lens
+const SERIAL_TAGS: [u16; 3] = [
+    TAG_BODY_SERIAL_NUMBER,
+    0xA435, // LensSerialNumber
+    TAG_CAMERA_SERIAL_NUMBER,
+];
+
+/// DateTimeOriginal tag in the Exif IFD, when the photo was taken
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+/// AmbientTemperature tag in the Exif IFD, a signed rational in degrees Celsius
+const TAG_AMBIENT_TEMPERATURE: u16 = 0x9400;
+
+/// MakerNote tag in the Exif IFD
+const TAG_MAKER_NOTE: u16 = 0x927C;
+
+/// Thumbnail offset and length tags in IFD1
+const TAG_THUMBNAIL_OFFSET: u16 = 0x0201;
+const TAG_THUMBNAIL_LENGTH: u16 = 0x0202;
+
+fn malformed(reason: &str) -> Error {
+    Error::Metadata(format!("malformed EXIF data, {}", reason))
+}
+
+/// A single entry of an image file directory
+struct Entry {
+    /// The offset of the 12 byte entry itself
+    position: usize,
+    tag: u16,
+    /// The offset of the entry's value, either inline in the entry or elsewhere in the data
+    value_position: usize,
+    value_size: usize,
+}
+
+/// Raw TIFF data in either byte order
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Result<Self> {
+        let little_endian = match data.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(malformed("unknown byte order")),
+        };
+
+        let tiff = Self {
+            data,
+            little_endian,
+        };
+
+        if tiff.read_u16(2)? != 42 {
+            return Err(malformed("invalid TIFF header"));
+        }
+
+        Ok(tiff)
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16> {
+        let bytes: [u8; 2] = self
+            .data
+            .get(offset..offset + 2)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| malformed("unexpected end of data"))?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let bytes: [u8; 4] = self
+            .data
+            .get(offset..offset + 4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| malformed("unexpected end of data"))?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        self.data[offset..offset + 2].copy_from_slice(&bytes);
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        self.data[offset..offset + 4].copy_from_slice(&bytes);
+    }
+
+    /// Zero a range of bytes, ignoring any part of the range beyond the end of the data
+    fn zero(&mut self, offset: usize, size: usize) {
+        let end = offset.saturating_add(size).min(self.data.len());
+        if offset < end {
+            self.data[offset..end].fill(0);
+        }
+    }
+
+    /// The offset of IFD0
+    fn first_ifd(&self) -> Result<usize> {
+        Ok(self.read_u32(4)? as usize)
+    }
+
+    /// The offset of the IFD following the IFD at `ifd`, if any
+    fn next_ifd(&self, ifd: usize) -> Result<Option<usize>> {
+        let count = self.read_u16(ifd)? as usize;
+        let next = self.read_u32(ifd + 2 + count * 12)? as usize;
+        Ok(if next == 0 { None } else { Some(next) })
+    }
+
+    fn entries(&self, ifd: usize) -> Result<Vec<Entry>> {
+        let count = self.read_u16(ifd)? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let position = ifd + 2 + i * 12;
+            let tag = self.read_u16(position)?;
+            let value_type = self.read_u16(position + 2)?;
+            let value_count = self.read_u32(position + 4)? as usize;
+
+            let type_size = match value_type {
+                1 | 2 | 6 | 7 => 1,
+                3 | 8 => 2,
+                4 | 9 | 11 => 4,
+                5 | 10 | 12 => 8,
+                _ => 1,
+            };
+            let value_size = type_size * value_count;
+            let value_position = if value_size <= 4 {
+                position + 8
+            } else {
+                self.read_u32(position + 8)? as usize
+            };
+
+            entries.push(Entry {
+                position,
+                tag,
+                value_position,
+                value_size,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// The value of an ASCII entry, without its NUL terminator and padding
+    fn read_ascii(&self, entry: &Entry) -> Option<String> {
+        let bytes = self
+            .data
+            .get(entry.value_position..entry.value_position + entry.value_size)?;
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        let value = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+        (!value.is_empty()).then_some(value)
+    }
+
+    /// Blank the value of an entry, keeping the entry itself
+    fn zero_value(&mut self, entry: &Entry) {
+        self.zero(entry.value_position, entry.value_size);
+    }
+
+    /// Remove an entry from an IFD, shifting the following entries and next IFD offset up
+    fn remove_entry(&mut self, ifd: usize, entry: &Entry) -> Result<()> {
+        let count = self.read_u16(ifd)? as usize;
+        let end = ifd + 2 + count * 12 + 4;
+        if end > self.data.len() {
+            return Err(malformed("unexpected end of data"));
+        }
+
+        self.data
+            .copy_within(entry.position + 12..end, entry.position);
+        self.zero(end - 12, 12);
+        self.write_u16(ifd, (count - 1) as u16);
+
+        Ok(())
+    }
+}
+
+/// The tags of raw EXIF data describing when and with which camera a photo was taken
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct CaptureTags {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// BodySerialNumber, or CameraSerialNumber for older cameras
+    pub serial_number: Option<String>,
+    /// DateTimeOriginal as written by the camera, e.g. `2023:06:01 21:14:05`
+    pub date_time_original: Option<String>,
+    /// AmbientTemperature in degrees Celsius
+    pub ambient_temperature: Option<f32>,
+    /// The raw maker note, in a format specific to the camera manufacturer
+    pub maker_note: Option<Vec<u8>>,
+}
+
+/// Read the capture tags from raw EXIF data
+pub(crate) fn read_capture_tags(exif: &[u8]) -> Result<CaptureTags> {
+    let mut data = exif.to_vec();
+    let tiff = Tiff::new(&mut data)?;
+    let ifd0 = tiff.first_ifd()?;
+
+    let mut tags = CaptureTags::default();
+    let mut ifds = vec![ifd0];
+    for entry in tiff.entries(ifd0)? {
+        if entry.tag == TAG_EXIF_IFD {
+            ifds.push(tiff.read_u32(entry.value_position)? as usize);
+        }
+    }
+
+    for ifd in ifds {
+        for entry in tiff.entries(ifd)? {
+            match entry.tag {
+                TAG_MAKE => tags.make = tiff.read_ascii(&entry),
+                TAG_MODEL => tags.model = tiff.read_ascii(&entry),
+                TAG_BODY_SERIAL_NUMBER => tags.serial_number = tiff.read_ascii(&entry),
+                TAG_CAMERA_SERIAL_NUMBER => {
+                    tags.serial_number = tags.serial_number.take().or(tiff.read_ascii(&entry))
+                }
+                TAG_DATE_TIME_ORIGINAL => tags.date_time_original = tiff.read_ascii(&entry),
+                TAG_AMBIENT_TEMPERATURE => {
+                    let numerator = tiff.read_u32(entry.value_position)? as i32;
+                    let denominator = tiff.read_u32(entry.value_position + 4)? as i32;
+                    tags.ambient_temperature =
+                        (denominator != 0).then(|| numerator as f32 / denominator as f32);
+                }
+                TAG_MAKER_NOTE => {
+                    tags.maker_note = tiff
+                        .data
+                        .get(entry.value_position..entry.value_position + entry.value_size)
+                        .map(|bytes| bytes.to_vec())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Remove GPS location and camera/lens serial numbers from raw EXIF data
+///
+/// The maker note is removed too, as cameras often record their serial number in it.
+pub(crate) fn strip_private_tags(exif: &mut [u8]) -> Result<()> {
+    let mut tiff = Tiff::new(exif)?;
+    let ifd0 = tiff.first_ifd()?;
+
+    let mut serial_ifds = vec![ifd0];
+
+    for entry in tiff.entries(ifd0)? {
+        match entry.tag {
+            TAG_GPS_IFD => {
+                let gps_ifd = tiff.read_u32(entry.value_position)? as usize;
+                for gps_entry in tiff.entries(gps_ifd)? {
+                    tiff.zero_value(&gps_entry);
+                }
+                let gps_count = tiff.read_u16(gps_ifd)? as usize;
+                tiff.zero(gps_ifd, 2 + gps_count * 12 + 4);
+            }
+            TAG_EXIF_IFD => serial_ifds.push(tiff.read_u32(entry.value_position)? as usize),
+            _ => {}
+        }
+    }
+
+    // Drop the pointer to the now blank GPS IFD, entries are re-read as removal shifts them
+    if let Some(gps_pointer) = tiff
+        .entries(ifd0)?
+        .into_iter()
+        .find(|entry| entry.tag == TAG_GPS_IFD)
+    {
+        tiff.remove_entry(ifd0, &gps_pointer)?;
+    }
+
+    for ifd in serial_ifds {
+        for entry in tiff.entries(ifd)? {
+            if SERIAL_TAGS.contains(&entry.tag) || entry.tag == TAG_MAKER_NOTE {
+                tiff.zero_value(&entry);
+            }
+        }
+
+        if let Some(maker_note) = tiff
+            .entries(ifd)?
+            .into_iter()
+            .find(|entry| entry.tag == TAG_MAKER_NOTE)
+        {
+            tiff.remove_entry(ifd, &maker_note)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the embedded thumbnail (IFD1) from raw EXIF data
+///
+/// The thumbnail is a small copy of the original image, so must be removed whenever the image
+/// itself has been redacted.
+pub(crate) fn strip_thumbnail(exif: &mut [u8]) -> Result<()> {
+    let mut tiff = Tiff::new(exif)?;
+    let ifd0 = tiff.first_ifd()?;
+
+    let Some(ifd1) = tiff.next_ifd(ifd0)? else {
+        return Ok(());
+    };
+
+    let entries = tiff.entries(ifd1)?;
+    let offset = entries
+        .iter()
+        .find(|entry| entry.tag == TAG_THUMBNAIL_OFFSET)
+        .map(|entry| tiff.read_u32(entry.value_position))
+        .transpose()?;
+    let length = entries
+        .iter()
+        .find(|entry| entry.tag == TAG_THUMBNAIL_LENGTH)
+        .map(|entry| tiff.read_u32(entry.value_position))
+        .transpose()?;
+
+    if let (Some(offset), Some(length)) = (offset, length) {
+        tiff.zero(offset as usize, length as usize);
+    }
+
+    for entry in &entries {
+        tiff.zero_value(entry);
+    }
+    tiff.zero(ifd1, 2 + entries.len() * 12 + 4);
+
+    // Unlink IFD1 from IFD0
+    let ifd0_count = tiff.read_u16(ifd0)? as usize;
+    tiff.write_u32(ifd0 + 2 + ifd0_count * 12, 0);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a little-endian TIFF with a GPS IFD, an Exif IFD holding a serial number and a
+    /// thumbnail IFD.
+    fn build_exif() -> Vec<u8> {
+        let mut data = vec![0u8; 256];
+        let mut tiff = Tiff {
+            data: &mut data,
+            little_endian: true,
+        };
+        tiff.data[0..2].copy_from_slice(b"II");
+        tiff.write_u16(2, 42);
+        tiff.write_u32(4, 8);
+
+        let write_entry = |tiff: &mut Tiff, position: usize, tag, value_type, count, value| {
+            tiff.write_u16(position, tag);
+            tiff.write_u16(position + 2, value_type);
+            tiff.write_u32(position + 4, count);
+            tiff.write_u32(position + 8, value);
+        };
+
+        // IFD0 at 8: Make, Exif pointer, GPS pointer, then IFD1 at 100
+        tiff.write_u16(8, 3);
+        write_entry(&mut tiff, 10, 0x010F, 2, 4, u32::from_le_bytes(*b"ABC\0"));
+        write_entry(&mut tiff, 22, TAG_EXIF_IFD, 4, 1, 50);
+        write_entry(&mut tiff, 34, TAG_GPS_IFD, 4, 1, 70);
+        tiff.write_u32(46, 100);
+
+        // Exif IFD at 50: BodySerialNumber stored at 200
+        tiff.write_u16(50, 1);
+        write_entry(&mut tiff, 52, 0xA431, 2, 8, 200);
+        tiff.write_u32(64, 0);
+        tiff.data[200..208].copy_from_slice(b"SN12345\0");
+
+        // GPS IFD at 70: GPSLatitudeRef
+        tiff.write_u16(70, 1);
+        write_entry(&mut tiff, 72, 0x0001, 2, 2, u32::from_le_bytes(*b"N\0\0\0"));
+        tiff.write_u32(84, 0);
+
+        // IFD1 at 100: thumbnail at 220, 16 bytes long
+        tiff.write_u16(100, 2);
+        write_entry(&mut tiff, 102, TAG_THUMBNAIL_OFFSET, 4, 1, 220);
+        write_entry(&mut tiff, 114, TAG_THUMBNAIL_LENGTH, 4, 1, 16);
+        tiff.write_u32(126, 0);
+        tiff.data[220..236].fill(0xFF);
+
+        data
+    }
+
+    #[test]
+    fn test_strip_private_tags() {
+        let mut data = build_exif();
+        strip_private_tags(&mut data).unwrap();
+
+        let tiff = Tiff::new(&mut data).unwrap();
+        let tags: Vec<u16> = tiff.entries(8).unwrap().iter().map(|e| e.tag).collect();
+        assert_eq!(tags, vec![0x010F, TAG_EXIF_IFD]);
+        assert_eq!(tiff.next_ifd(8).unwrap(), Some(100));
+        assert_eq!(&tiff.data[18..22], b"ABC\0");
+        assert!(tiff.data[70..88].iter().all(|b| *b == 0));
+        assert!(tiff.data[200..208].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_strip_maker_note() {
+        let mut data = build_exif();
+        let mut tiff = Tiff {
+            data: &mut data,
+            little_endian: true,
+        };
+        // Replace the GPS pointer with Model, and add a MakerNote stored at 236
+        tiff.write_u16(34, TAG_MODEL);
+        tiff.write_u16(50, 2);
+        tiff.write_u16(64, TAG_MAKER_NOTE);
+        tiff.write_u16(66, 7);
+        tiff.write_u32(68, 16);
+        tiff.write_u32(72, 236);
+        tiff.write_u32(76, 0);
+        tiff.data[236..252].copy_from_slice(b"SERIAL H500ABC\0\0");
+        assert!(read_capture_tags(&data).unwrap().maker_note.is_some());
+
+        strip_private_tags(&mut data).unwrap();
+        let tags = read_capture_tags(&data).unwrap();
+        assert_eq!(tags.maker_note, None);
+        assert_eq!(tags.serial_number, None);
+        assert!(data[236..252].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_strip_thumbnail() {
+        let mut data = build_exif();
+        strip_thumbnail(&mut data).unwrap();
+
+        let tiff = Tiff::new(&mut data).unwrap();
+        assert_eq!(tiff.next_ifd(8).unwrap(), None);
+        assert!(tiff.data[220..236].iter().all(|b| *b == 0));
+        assert_eq!(&tiff.data[200..208], b"SN12345\0");
+    }
+
+    #[test]
+    fn test_read_capture_tags() {
+        let mut data = build_exif();
+        let mut tiff = Tiff {
+            data: &mut data,
+            little_endian: true,
+        };
+        // Replace the GPS pointer with Model, and add DateTimeOriginal stored at 236
+        tiff.write_u16(34, TAG_MODEL);
+        tiff.write_u16(36, 2);
+        tiff.write_u32(38, 4);
+        tiff.write_u32(42, u32::from_le_bytes(*b"XY \0"));
+        tiff.write_u16(50, 2);
+        tiff.write_u16(64, TAG_DATE_TIME_ORIGINAL);
+        tiff.write_u16(66, 2);
+        tiff.write_u32(68, 20);
+        tiff.write_u32(72, 236);
+        tiff.write_u32(76, 0);
+        tiff.data[236..256].copy_from_slice(b"2023:06:01 21:14:05\0");
+
+        let tags = read_capture_tags(&data).unwrap();
+        assert_eq!(tags.make.as_deref(), Some("ABC"));
+        assert_eq!(tags.model.as_deref(), Some("XY"));
+        assert_eq!(tags.serial_number.as_deref(), Some("SN12345"));
+        assert_eq!(
+            tags.date_time_original.as_deref(),
+            Some("2023:06:01 21:14:05")
+        );
+        assert_eq!(tags.ambient_temperature, None);
+        assert_eq!(tags.maker_note, None);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(strip_private_tags(&mut [0u8; 4]).is_err());
+        assert!(strip_thumbnail(&mut b"II*\0\xFF\xFF\xFF\xFF".to_vec()).is_err());
+    }
+}
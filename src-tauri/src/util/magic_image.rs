@@ -5,35 +5,61 @@
 //! The module is based on the [image](https://crates.io/crates/image) and [img_parts](https://crates.io/crates/img_parts) crates.
 //!
 
+use crate::Result;
+use ab_glyph::{FontRef, PxScale};
+use image::codecs::jpeg::JpegEncoder;
+use image::{GenericImage, GenericImageView, Pixel};
 use img_parts::ImageEXIF;
+use once_cell::sync::Lazy;
 use std::fs;
 use std::io::Cursor;
 use std::path::Path;
 
+/// The font used to draw labels, embedded so exports look the same on every platform
+pub(crate) static LABEL_FONT: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"))
+        .expect("the embedded label font is valid")
+});
+
+/// Read the raw EXIF data of an encoded image, only JPEG and PNG images are supported
+pub(crate) fn read_exif(
+    image_bytes: Vec<u8>,
+    format: image::ImageFormat,
+) -> Result<Option<img_parts::Bytes>> {
+    Ok(match format {
+        image::ImageFormat::Jpeg => img_parts::jpeg::Jpeg::from_bytes(image_bytes.into())?.exif(),
+        image::ImageFormat::Png => img_parts::png::Png::from_bytes(image_bytes.into())?.exif(),
+        _ => None,
+    })
+}
+
 /// Magic Image
 ///
 /// This struct represents an image that can be drawn on and saved while preserving the original EXIF data.
-pub(crate) struct MagicImage {
+pub struct MagicImage {
     image: image::DynamicImage,
     exif: Option<img_parts::Bytes>,
     original_format: image::ImageFormat,
 }
 
 impl MagicImage {
+    /// Create an image without any EXIF data, saved in the given format
+    pub fn new(image: image::DynamicImage, format: image::ImageFormat) -> Self {
+        Self {
+            image,
+            exif: None,
+            original_format: format,
+        }
+    }
+
     /// Open an image from a path
-    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
         let image_bytes = fs::read(path.as_ref())?;
 
         let original_format = image::guess_format(&image_bytes)?;
         let image = image::load_from_memory_with_format(&image_bytes, original_format)?;
 
-        let exif = match original_format {
-            image::ImageFormat::Jpeg => {
-                img_parts::jpeg::Jpeg::from_bytes(image_bytes.into())?.exif()
-            }
-            image::ImageFormat::Png => img_parts::png::Png::from_bytes(image_bytes.into())?.exif(),
-            _ => None,
-        };
+        let exif = read_exif(image_bytes, original_format)?;
 
         Ok(Self {
             image,
@@ -42,23 +68,175 @@ impl MagicImage {
         })
     }
 
+    /// The decoded image
+    pub fn image(&self) -> &image::DynamicImage {
+        &self.image
+    }
+
+    /// Take the decoded image, discarding any EXIF data
+    pub fn into_image(self) -> image::DynamicImage {
+        self.image
+    }
+
+    /// Draw another image on top of this one with its top-left corner at `x`, `y`
+    pub fn overlay(&mut self, top: &image::DynamicImage, x: i64, y: i64) {
+        image::imageops::overlay(&mut self.image, top, x, y);
+    }
+
+    /// The width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    /// The height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
     /// Draw a bounding box on the image
+    ///
+    /// The outline is `thickness` pixels wide and drawn inwards from the edges of the box.
     pub fn draw_bounding_box(
         &mut self,
         x: i32,
         y: i32,
         width: u32,
         height: u32,
+        thickness: u32,
         color: image::Rgba<u8>,
     ) {
-        let rect = imageproc::rect::Rect::at(x, y).of_size(width, height);
-        imageproc::drawing::draw_hollow_rect_mut(&mut self.image, rect, color);
+        // Keep the outline within the box, degenerate boxes are drawn at least 1x1
+        let thickness = thickness.clamp(1, (width.min(height) / 2).max(1));
+
+        for inset in 0..thickness {
+            let rect = imageproc::rect::Rect::at(x + inset as i32, y + inset as i32).of_size(
+                width.saturating_sub(inset * 2).max(1),
+                height.saturating_sub(inset * 2).max(1),
+            );
+            imageproc::drawing::draw_hollow_rect_mut(&mut self.image, rect, color);
+        }
+    }
+
+    /// Fill a rectangle on the image, blending the color by its alpha channel
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: image::Rgba<u8>) {
+        let Some((x, y, width, height)) = self.clamp_region(x, y, width, height) else {
+            return;
+        };
+
+        for py in y..y + height {
+            for px in x..x + width {
+                let mut pixel = self.image.get_pixel(px, py);
+                pixel.blend(&color);
+                self.image.put_pixel(px, py, pixel);
+            }
+        }
+    }
+
+    /// Draw a text label with a solid background, anchored by its bottom-left corner
+    ///
+    /// The label is moved down to stay within the image when it would be drawn above the top edge.
+    pub fn draw_label(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        font_size: f32,
+        background: image::Rgba<u8>,
+        foreground: image::Rgba<u8>,
+    ) {
+        let scale = PxScale::from(font_size);
+        let (text_width, text_height) = imageproc::drawing::text_size(scale, &*LABEL_FONT, text);
+        let padding = (font_size / 4.0).ceil() as u32;
+        let label_width = text_width + padding * 2;
+        let label_height = text_height + padding * 2;
+
+        let top = (y - label_height as i32).max(0);
+        let left = x.clamp(0, (self.image.width() as i32 - label_width as i32).max(0));
+
+        self.fill_rect(left, top, label_width, label_height, background);
+        imageproc::drawing::draw_text_mut(
+            &mut self.image,
+            foreground,
+            left + padding as i32,
+            top + padding as i32,
+            scale,
+            &*LABEL_FONT,
+            text,
+        );
+    }
+
+    /// Clamp a region to the bounds of the image, returning `None` if nothing of it remains
+    fn clamp_region(
+        &self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> Option<(u32, u32, u32, u32)> {
+        let x_start = x.max(0) as u32;
+        let y_start = y.max(0) as u32;
+        let x_end = (x.saturating_add(width as i32).max(0) as u32).min(self.image.width());
+        let y_end = (y.saturating_add(height as i32).max(0) as u32).min(self.image.height());
+
+        if x_start >= x_end || y_start >= y_end {
+            return None;
+        }
+
+        Some((x_start, y_start, x_end - x_start, y_end - y_start))
+    }
+
+    /// Gaussian blur a region of the image
+    pub fn blur_region(&mut self, x: i32, y: i32, width: u32, height: u32, sigma: f32) {
+        if let Some((x, y, width, height)) = self.clamp_region(x, y, width, height) {
+            let blurred = self.image.crop_imm(x, y, width, height).blur(sigma);
+            // The blurred region is the same size as the region cropped so always fits
+            let _ = self.image.copy_from(&blurred, x, y);
+        }
+    }
+
+    /// Pixelate a region of the image into square blocks of `block_size` pixels
+    pub fn pixelate_region(&mut self, x: i32, y: i32, width: u32, height: u32, block_size: u32) {
+        if let Some((x, y, width, height)) = self.clamp_region(x, y, width, height) {
+            let block_size = block_size.max(1);
+            let pixelated = self
+                .image
+                .crop_imm(x, y, width, height)
+                .resize_exact(
+                    width.div_ceil(block_size),
+                    height.div_ceil(block_size),
+                    image::imageops::FilterType::Triangle,
+                )
+                .resize_exact(width, height, image::imageops::FilterType::Nearest);
+            // The pixelated region is the same size as the region cropped so always fits
+            let _ = self.image.copy_from(&pixelated, x, y);
+        }
+    }
+
+    /// Remove GPS location, camera/lens serial numbers and the maker note from the EXIF data
+    pub fn strip_private_exif(&mut self) -> Result<()> {
+        self.modify_exif(super::exif::strip_private_tags)
+    }
+
+    /// Remove the thumbnail from the EXIF data, it would otherwise show the unmodified image
+    pub fn strip_exif_thumbnail(&mut self) -> Result<()> {
+        self.modify_exif(super::exif::strip_thumbnail)
+    }
+
+    fn modify_exif(&mut self, modify: impl FnOnce(&mut [u8]) -> Result<()>) -> Result<()> {
+        if let Some(exif) = &self.exif {
+            let mut exif = exif.to_vec();
+            modify(&mut exif)?;
+            self.exif = Some(exif.into());
+        }
+
+        Ok(())
     }
 
     /// Save the image to a path (preserving EXIF data if it is a JPEG or PNG)
     ///
-    /// Preserves the original image format no matter what the path extension is.
-    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    /// Preserves the original image format no matter what the path extension is. JPEGs are
+    /// encoded at `jpeg_quality` (1-100), or the encoder's default quality when `None`.
+    pub fn save(&self, path: impl AsRef<Path>, jpeg_quality: Option<u8>) -> Result<()> {
         // First, we need to check if the image is a JPEG or PNG. If it is, we need to use img_parts to
         // preserve the EXIF data. Otherwise, we can use the image crate to save the image.
         let mut output_file = fs::File::create(path)?;
@@ -66,8 +244,17 @@ impl MagicImage {
         let mut buffer = Vec::new();
         let mut cursored_buffer = Cursor::new(&mut buffer);
 
-        self.image
-            .write_to(&mut cursored_buffer, self.original_format)?;
+        match (self.original_format, jpeg_quality) {
+            (image::ImageFormat::Jpeg, Some(quality)) => {
+                let encoder =
+                    JpegEncoder::new_with_quality(&mut cursored_buffer, quality.clamp(1, 100));
+                self.image.write_with_encoder(encoder)?;
+            }
+            _ => {
+                self.image
+                    .write_to(&mut cursored_buffer, self.original_format)?;
+            }
+        }
 
         match self.original_format {
             image::ImageFormat::Jpeg => {
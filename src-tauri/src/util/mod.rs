@@ -1 +1,2 @@
-pub(crate) mod magic_image;
+pub(crate) mod exif;
+pub mod magic_image;
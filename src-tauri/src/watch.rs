@@ -0,0 +1,419 @@
+//! Watch module
+//!
+//! Continuous ingestion of images arriving in a folder, e.g. synced from cellular cameras. The
+//! folder is polled rather than subscribed to, which behaves the same on local, network and synced
+//! drives. A new file is only processed once its size and modification time have stopped changing,
+//! so files still being written are not read part way through.
+//!
+
+use crate::detector::Detector;
+use crate::error::{Error, Result};
+use crate::exports::coordinates::CoordinateOptions;
+use crate::exports::jsonl::write_jsonl;
+use crate::megadetector::Category;
+use crate::structures::{CamTrapImageDetections, FileStamp};
+use crate::yolov5::helpers::is_image_path;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// What to do with an image once it has been processed
+///
+/// Relative directories are inside the watched folder, not the working directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileAction {
+    /// Leave the image where it is
+    #[default]
+    Keep,
+
+    /// Move the image into a directory, keeping its path relative to the watched folder
+    Move(PathBuf),
+
+    /// Copy the image into a directory, keeping its path relative to the watched folder
+    Copy(PathBuf),
+}
+
+/// The action to take for images of each category, by their top detection
+///
+/// Images that could not be processed are always kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryActions {
+    pub empty: FileAction,
+    pub animal: FileAction,
+    pub human: FileAction,
+    pub vehicle: FileAction,
+}
+
+impl CategoryActions {
+    /// The action for an image
+    pub fn get(&self, image: &CamTrapImageDetections) -> &FileAction {
+        if image.error.is_some() {
+            return &FileAction::Keep;
+        }
+
+        let top = image
+            .detections
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence));
+
+        match top.map(|detection| Category::from_model_index(detection.class_index)) {
+            None => &self.empty,
+            Some(Some(Category::ANIMAL)) => &self.animal,
+            Some(Some(Category::HUMAN)) => &self.human,
+            Some(Some(Category::VEHICLE)) => &self.vehicle,
+            Some(_) => &FileAction::Keep,
+        }
+    }
+
+    /// The directories images are moved or copied into
+    fn destinations(&self) -> Vec<&Path> {
+        [&self.empty, &self.animal, &self.human, &self.vehicle]
+            .into_iter()
+            .filter_map(|action| match action {
+                FileAction::Keep => None,
+                FileAction::Move(dir) | FileAction::Copy(dir) => Some(dir.as_path()),
+            })
+            .collect()
+    }
+}
+
+/// Options for watching a folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchOptions {
+    /// The folder to watch
+    pub root: PathBuf,
+
+    /// Whether to also watch subfolders
+    pub recursive: bool,
+
+    /// The minimum confidence of detections to keep
+    pub confidence_threshold: f32,
+
+    /// How long a file must be unchanged before it is processed, in seconds
+    #[serde(default = "default_settle_secs")]
+    pub settle_secs: f32,
+
+    /// How often to look for new files, in seconds
+    #[serde(default = "default_poll_secs")]
+    pub poll_secs: f32,
+
+    /// Whether to also process the images already in the folder when watching starts
+    #[serde(default)]
+    pub include_existing: bool,
+
+    /// A JSON Lines file to append each result to
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+
+    /// What to do with images after they have been processed
+    #[serde(default)]
+    pub actions: CategoryActions,
+}
+
+fn default_settle_secs() -> f32 {
+    5.0
+}
+
+fn default_poll_secs() -> f32 {
+    2.0
+}
+
+/// Finds image files that have appeared in a folder and finished being written
+pub struct FolderWatcher {
+    root: PathBuf,
+    recursive: bool,
+    settle: Duration,
+
+    /// Directories inside the root to ignore, e.g. where processed images are moved to
+    excluded: Vec<PathBuf>,
+
+    /// Files seen but not yet settled, with their last stamp and when it last changed
+    pending: HashMap<PathBuf, (FileStamp, Instant)>,
+
+    /// Files already returned, or present when watching started
+    seen: HashSet<PathBuf>,
+}
+
+impl FolderWatcher {
+    pub fn new(root: PathBuf, recursive: bool, settle: Duration) -> Self {
+        Self {
+            root,
+            recursive,
+            settle,
+            excluded: vec![],
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Ignore a directory, and everything in it, relative to the root unless absolute
+    pub fn exclude(&mut self, dir: PathBuf) {
+        self.excluded.push(self.root.join(dir));
+    }
+
+    /// Mark the images currently in the folder as seen, so only new arrivals are returned
+    pub fn skip_existing(&mut self) {
+        let existing: Vec<PathBuf> = self.scan().collect();
+        self.seen.extend(existing);
+    }
+
+    fn scan(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        WalkDir::new(&self.root)
+            .max_depth(if self.recursive { usize::MAX } else { 1 })
+            .into_iter()
+            .filter_entry(|entry| {
+                !self
+                    .excluded
+                    .iter()
+                    .any(|dir| entry.path().starts_with(dir))
+            })
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file() && is_image_path(entry.path()))
+            .map(|entry| entry.into_path())
+    }
+
+    /// Look for new files, returning those that have been unchanged for the settle time
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let mut ready = vec![];
+
+        let found: Vec<PathBuf> = self
+            .scan()
+            .filter(|file| !self.seen.contains(file))
+            .collect();
+
+        // Forget files removed before they settled
+        self.pending.retain(|file, _| found.contains(file));
+
+        for file in found {
+            let Ok(stamp) = FileStamp::read(&file) else {
+                continue;
+            };
+
+            match self.pending.get_mut(&file) {
+                Some((last, changed)) if *last == stamp => {
+                    if now.duration_since(*changed) >= self.settle {
+                        self.pending.remove(&file);
+                        self.seen.insert(file.clone());
+                        ready.push(file);
+                    }
+                }
+                Some(entry) => *entry = (stamp, now),
+                None => {
+                    self.pending.insert(file, (stamp, now));
+                }
+            }
+        }
+
+        ready.sort();
+        ready
+    }
+}
+
+/// Move or copy a processed image, updating the result's path to where the image now is
+fn apply_action(
+    image: &mut CamTrapImageDetections,
+    action: &FileAction,
+    root: &Path,
+) -> Result<()> {
+    let (dir, keep_original) = match action {
+        FileAction::Keep => return Ok(()),
+        FileAction::Move(dir) => (dir, false),
+        FileAction::Copy(dir) => (dir, true),
+    };
+
+    let file = PathBuf::from(&image.file);
+    let relative = pathdiff::diff_paths(&file, root)
+        .filter(|path| path.is_relative())
+        .or_else(|| file.file_name().map(PathBuf::from))
+        .ok_or_else(|| Error::InvalidInput(format!("Invalid image path {}", image.file)))?;
+    let destination = root.join(dir).join(relative);
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if keep_original {
+        std::fs::copy(&file, &destination)?;
+    } else if std::fs::rename(&file, &destination).is_err() {
+        // Renaming fails across drives, so fall back to copying and removing
+        std::fs::copy(&file, &destination)?;
+        std::fs::remove_file(&file)?;
+    }
+
+    if !keep_original {
+        image.file = destination.to_string_lossy().to_string();
+    }
+
+    Ok(())
+}
+
+/// Append a result to a JSON Lines file, with its path relative to the watched folder
+fn append_result(output: &Path, image: &CamTrapImageDetections, root: &Path) -> Result<()> {
+    let mut image = image.clone();
+    if let Some(relative) = pathdiff::diff_paths(&image.file, root) {
+        image.file = relative.to_string_lossy().to_string();
+    }
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output)?;
+    write_jsonl([image], &CoordinateOptions::default(), file)
+}
+
+/// Watch a folder until `stop` is set, running detection on each new image as it arrives
+///
+/// Each image is moved or copied by its category, its result appended to the output file if one
+/// is given, then passed to `on_result`. Failures to move or record an image are passed to
+/// `on_error` and do not stop watching.
+pub fn watch(
+    options: &WatchOptions,
+    detector: &mut Detector,
+    stop: &AtomicBool,
+    mut on_result: impl FnMut(CamTrapImageDetections),
+    mut on_error: impl FnMut(String),
+) {
+    let mut watcher = FolderWatcher::new(
+        options.root.clone(),
+        options.recursive,
+        Duration::from_secs_f32(options.settle_secs.max(0.0)),
+    );
+    for dir in options.actions.destinations() {
+        watcher.exclude(dir.to_path_buf());
+    }
+    if !options.include_existing {
+        watcher.skip_existing();
+    }
+
+    let poll = Duration::from_secs_f32(options.poll_secs.max(0.1));
+    detector.set_confidence_threshold(options.confidence_threshold);
+
+    while !stop.load(Ordering::Relaxed) {
+        for file in watcher.poll() {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let mut image = detector
+                .detect_path(&file)
+                .unwrap_or_else(|err| CamTrapImageDetections::failed(&file, err));
+
+            let action = options.actions.get(&image).clone();
+            if let Err(err) = apply_action(&mut image, &action, &options.root) {
+                on_error(format!("Failed to move or copy {}: {}", image.file, err));
+            }
+
+            if let Some(output) = &options.output {
+                if let Err(err) = append_result(output, &image, &options.root) {
+                    on_error(format!("Failed to record {}: {}", image.file, err));
+                }
+            }
+
+            on_result(image);
+        }
+
+        std::thread::sleep(poll);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{CamTrapDetection, DetectionSource, ReviewStatus};
+
+    #[test]
+    fn test_folder_watcher() {
+        let root = std::env::temp_dir().join("camtrap-watch");
+        let excluded = root.join("empty");
+        std::fs::create_dir_all(&excluded).unwrap();
+        std::fs::write(root.join("existing.jpg"), b"image").unwrap();
+
+        let mut watcher = FolderWatcher::new(root.clone(), true, Duration::ZERO);
+        watcher.exclude(excluded.clone());
+        watcher.skip_existing();
+
+        std::fs::write(root.join("new.jpg"), b"image").unwrap();
+        std::fs::write(root.join("notes.txt"), b"text").unwrap();
+        std::fs::write(excluded.join("moved.jpg"), b"image").unwrap();
+
+        // First seen, then settled once unchanged
+        assert!(watcher.poll().is_empty());
+        assert_eq!(watcher.poll(), vec![root.join("new.jpg")]);
+        assert!(watcher.poll().is_empty());
+
+        // Still being written, so only returned once it stops changing
+        std::fs::write(root.join("partial.jpg"), b"ima").unwrap();
+        assert!(watcher.poll().is_empty());
+        std::fs::write(root.join("partial.jpg"), b"image").unwrap();
+        assert!(watcher.poll().is_empty());
+        assert_eq!(watcher.poll(), vec![root.join("partial.jpg")]);
+
+        // Relative directories are excluded within the root
+        let mut watcher = FolderWatcher::new(root.clone(), true, Duration::ZERO);
+        watcher.exclude(PathBuf::from("empty"));
+        assert!(watcher.poll().is_empty());
+        assert!(!watcher.poll().contains(&excluded.join("moved.jpg")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_apply_action() {
+        let root = std::env::temp_dir().join("camtrap-watch-action");
+        let empty_dir = std::env::temp_dir().join("camtrap-watch-action-empty");
+        std::fs::create_dir_all(root.join("site")).unwrap();
+        let file = root.join("site/a.jpg");
+        std::fs::write(&file, b"image").unwrap();
+
+        let mut image = CamTrapImageDetections {
+            file: file.to_str().unwrap().to_string(),
+            error: None,
+            image_width: Some(100),
+            image_height: Some(100),
+            detections: vec![],
+            review: ReviewStatus::Unreviewed,
+            stamp: None,
+            metadata: None,
+        };
+
+        let actions = CategoryActions {
+            empty: FileAction::Move(empty_dir.clone()),
+            ..Default::default()
+        };
+        let action = actions.get(&image).clone();
+        apply_action(&mut image, &action, &root).unwrap();
+
+        assert!(!file.exists());
+        assert!(empty_dir.join("site/a.jpg").exists());
+        assert_eq!(PathBuf::from(&image.file), empty_dir.join("site/a.jpg"));
+
+        // Images with detections are kept where they are
+        image.detections.push(CamTrapDetection {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            class_index: 0,
+            confidence: 0.9,
+            source: DetectionSource::Model,
+            review: ReviewStatus::Unreviewed,
+        });
+        assert_eq!(actions.get(&image), &FileAction::Keep);
+
+        // Relative directories are resolved against the watched folder
+        let file = root.join("site/b.jpg");
+        std::fs::write(&file, b"image").unwrap();
+        image.file = file.to_str().unwrap().to_string();
+        apply_action(&mut image, &FileAction::Move(PathBuf::from("empty")), &root).unwrap();
+        assert_eq!(PathBuf::from(&image.file), root.join("empty/site/b.jpg"));
+        assert!(root.join("empty/site/b.jpg").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&empty_dir).unwrap();
+    }
+}
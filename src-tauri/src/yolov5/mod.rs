@@ -4,4 +4,12 @@ pub mod model;
 
 pub use detections::YoloDetection;
 pub use detections::YoloImageDetections;
+pub use model::ExecutionOptions;
+pub use model::ExecutionProviderKind;
+pub use model::InferenceOptions;
+pub use model::MergeMethod;
+pub use model::StageTimings;
+pub use model::SystemInfo;
+pub use model::TileOptions;
+pub use model::TtaOptions;
 pub use model::YoloModel;
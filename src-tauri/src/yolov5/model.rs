@@ -1,18 +1,45 @@
+use crate::error::{Error, Result};
+use half::f16;
 use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
 use ndarray::{s, Array, ArrayViewD, Axis, IxDyn};
 use ort::execution_providers::{
     coreml::ComputeUnits, CUDAExecutionProvider, CoreMLExecutionProvider,
-    DirectMLExecutionProvider, ExecutionProvider, TensorRTExecutionProvider,
+    DirectMLExecutionProvider, ExecutionProvider, ExecutionProviderDispatch,
+    TensorRTExecutionProvider,
 };
 use ort::session::Session;
-use ort::value::Tensor;
+use ort::value::{Outlet, Tensor, TensorElementType, ValueType};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 pub struct YoloModel {
     model: Session,
     accelerator_availability: AcceleratorAvailability,
+    provider: ExecutionProviderKind,
+    options: ExecutionOptions,
+    input_precision: ModelPrecision,
+    output_precision: ModelPrecision,
     input_size: (usize, usize),
+    dynamic_input: bool,
+    inference: InferenceOptions,
+    timings: StageTimings,
+}
+
+/// Time spent in each stage of the last detection
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct StageTimings {
+    /// Resizing and normalizing the image into the input tensor
+    pub preprocess: Duration,
+
+    /// Running the model
+    pub inference: Duration,
+
+    /// Decoding the output and non-maximum suppression
+    pub postprocess: Duration,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,7 +49,7 @@ pub struct Detection {
     pub bbox: BBox,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct BBox {
     pub x: f32,
     pub y: f32,
@@ -31,7 +58,7 @@ pub struct BBox {
 }
 
 /// Availability of different accelerators
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct AcceleratorAvailability {
     pub coreml: bool,
     pub tensor_rt: bool,
@@ -39,90 +66,573 @@ pub struct AcceleratorAvailability {
     pub direct_ml: bool,
 }
 
-impl YoloModel {
-    pub fn new_from_file(
-        model_path: &str,
-        input_size: (usize, usize),
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        println!("Loading model");
-
-        let coreml = CoreMLExecutionProvider::default()
-            .with_compute_units(ComputeUnits::CPUAndNeuralEngine)
-            .with_subgraphs(true);
-        println!("CoreML available: {:?}", coreml.is_available().unwrap());
-
-        let tensor_rt = TensorRTExecutionProvider::default();
-        println!(
-            "TensorRT available: {:?}",
-            tensor_rt.is_available().unwrap()
-        );
+/// The element type of a model's input or output tensor
+///
+/// INT8-quantized models keep floating point inputs and outputs, so they are `Float32` here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelPrecision {
+    Float32,
+    Float16,
+}
 
-        let cuda = CUDAExecutionProvider::default();
-        println!("CUDA available: {:?}", cuda.is_available().unwrap());
+impl ModelPrecision {
+    fn of(outlet: &Outlet) -> Result<Self> {
+        match outlet.dtype().tensor_type() {
+            Some(TensorElementType::Float32) => Ok(ModelPrecision::Float32),
+            Some(TensorElementType::Float16) => Ok(ModelPrecision::Float16),
+            other => Err(Error::Model(format!(
+                "Unsupported element type {:?} for {}, expected float32 or float16",
+                other,
+                outlet.name()
+            ))),
+        }
+    }
+}
 
-        let direct_ml = DirectMLExecutionProvider::default();
-        println!(
-            "DirectML available: {:?}",
-            direct_ml.is_available().unwrap()
-        );
+impl fmt::Display for ModelPrecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModelPrecision::Float32 => "float32",
+            ModelPrecision::Float16 => "float16",
+        })
+    }
+}
+
+/// The execution provider that runs the model
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionProviderKind {
+    /// The first available accelerator, falling back to the CPU
+    #[default]
+    Auto,
+    Cpu,
+    CoreML,
+    TensorRT,
+    Cuda,
+    DirectML,
+}
+
+impl ExecutionProviderKind {
+    /// The accelerators tried in order when the provider is `Auto`
+    const ACCELERATORS: [ExecutionProviderKind; 4] = [
+        ExecutionProviderKind::CoreML,
+        ExecutionProviderKind::TensorRT,
+        ExecutionProviderKind::Cuda,
+        ExecutionProviderKind::DirectML,
+    ];
+
+    fn is_available(self, availability: &AcceleratorAvailability) -> bool {
+        match self {
+            ExecutionProviderKind::Auto | ExecutionProviderKind::Cpu => true,
+            ExecutionProviderKind::CoreML => availability.coreml,
+            ExecutionProviderKind::TensorRT => availability.tensor_rt,
+            ExecutionProviderKind::Cuda => availability.cuda,
+            ExecutionProviderKind::DirectML => availability.direct_ml,
+        }
+    }
+
+    /// The provider to register with the session, `None` for the CPU
+    fn dispatch(self) -> Option<ExecutionProviderDispatch> {
+        match self {
+            ExecutionProviderKind::Auto | ExecutionProviderKind::Cpu => None,
+            ExecutionProviderKind::CoreML => Some(
+                CoreMLExecutionProvider::default()
+                    .with_compute_units(ComputeUnits::CPUAndNeuralEngine)
+                    .with_subgraphs(true)
+                    .build(),
+            ),
+            ExecutionProviderKind::TensorRT => Some(TensorRTExecutionProvider::default().build()),
+            ExecutionProviderKind::Cuda => Some(CUDAExecutionProvider::default().build()),
+            ExecutionProviderKind::DirectML => Some(DirectMLExecutionProvider::default().build()),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExecutionProviderKind::Auto => "auto",
+            ExecutionProviderKind::Cpu => "cpu",
+            ExecutionProviderKind::CoreML => "coreml",
+            ExecutionProviderKind::TensorRT => "tensorrt",
+            ExecutionProviderKind::Cuda => "cuda",
+            ExecutionProviderKind::DirectML => "directml",
+        })
+    }
+}
+
+impl FromStr for ExecutionProviderKind {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        [ExecutionProviderKind::Auto, ExecutionProviderKind::Cpu]
+            .into_iter()
+            .chain(ExecutionProviderKind::ACCELERATORS)
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(value))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "Unknown execution provider `{}`, expected auto, cpu, coreml, tensorrt, cuda or directml",
+                    value
+                ))
+            })
+    }
+}
+
+/// How the model is run
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutionOptions {
+    /// The execution provider to use, a specific accelerator fails to load if it is unavailable
+    pub provider: ExecutionProviderKind,
+
+    /// The number of threads used within each operation, defaults to ONNX Runtime's choice
+    pub intra_threads: Option<usize>,
+
+    /// The number of threads used to run independent operations in parallel, enables parallel
+    /// execution when set
+    pub inter_threads: Option<usize>,
+}
+
+/// Slicing of large images into overlapping tiles, each run at the model's input size
+///
+/// Objects too small to detect once the whole image is downscaled, e.g. distant birds and
+/// rodents, are detected in the tiles. The whole image is still run for large objects.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TileOptions {
+    /// The width and height of each tile in pixels of the original image
+    pub size: u32,
+
+    /// The fraction of each tile that overlaps its neighbour, so objects on an edge are whole in
+    /// at least one tile
+    pub overlap: f32,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            size: 1280,
+            overlap: 0.2,
+        }
+    }
+}
+
+impl TileOptions {
+    /// The smallest tile size accepted, as smaller tiles hold too little of the image to detect
+    /// anything and there are too many of them to run
+    pub const MIN_SIZE: u32 = 32;
+
+    /// Check the tiles are at least the minimum size
+    pub fn validate(&self) -> Result<()> {
+        if self.size < Self::MIN_SIZE {
+            return Err(Error::InvalidInput(format!(
+                "Tile size {} is smaller than the minimum of {} pixels",
+                self.size,
+                Self::MIN_SIZE
+            )));
+        }
+        Ok(())
+    }
+
+    /// The start of each tile along one side of the image, none if the side fits in one tile
+    fn origins(&self, length: u32) -> Vec<u32> {
+        if length <= self.size {
+            return vec![];
+        }
+
+        let stride = ((self.size as f32 * (1.0 - self.overlap.clamp(0.0, 0.9))) as u32).max(1);
+        let last = length - self.size;
+        let mut origins: Vec<u32> = (0..last).step_by(stride as usize).collect();
+        origins.push(last);
+        origins
+    }
+
+    /// The tiles of an image as `(x, y, width, height)`, none if it fits in one tile
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+        let (xs, ys) = (self.origins(width), self.origins(height));
+        if xs.is_empty() && ys.is_empty() {
+            return vec![];
+        }
+
+        // A side that fits is covered by a single full length tile
+        let side = |origins: Vec<u32>, length: u32| match origins.is_empty() {
+            true => (vec![0], length),
+            false => (origins, self.size),
+        };
+        let (xs, tile_width) = side(xs, width);
+        let (ys, tile_height) = side(ys, height);
+
+        ys.iter()
+            .flat_map(|y| xs.iter().map(move |x| (*x, *y, tile_width, tile_height)))
+            .collect()
+    }
+}
+
+/// How the detections of several passes over an image are combined
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeMethod {
+    /// Keep the most confident of overlapping detections
+    Nms,
+
+    /// Average overlapping detections of the same class, weighted by confidence
+    #[default]
+    WeightedBoxFusion,
+}
+
+/// Test-time augmentation, running each image several times with small changes
+///
+/// Helps recall on difficult images such as night IR, at the cost of a run per pass.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TtaOptions {
+    /// Also run the horizontally flipped image
+    pub flip: bool,
+
+    /// The input sizes to run, as multiples of the model's input size. Sizes other than 1.0
+    /// need a model with dynamic input dimensions, such as the bundled model.
+    pub scales: Vec<f32>,
+
+    pub merge: MergeMethod,
+}
+
+impl Default for TtaOptions {
+    fn default() -> Self {
+        Self {
+            flip: true,
+            scales: vec![1.0, 0.83, 1.17],
+            merge: MergeMethod::default(),
+        }
+    }
+}
+
+impl TtaOptions {
+    /// The input size of each scale, rounded to the model's stride of 32 pixels
+    fn input_sizes(&self, input_size: u32) -> Vec<u32> {
+        let mut sizes: Vec<u32> = self
+            .scales
+            .iter()
+            .map(|scale| ((input_size as f32 * scale / 32.0).round() as u32).max(1) * 32)
+            .collect();
+        sizes.dedup();
+        sizes
+    }
+
+    /// The number of times each image is run
+    fn passes(&self, input_size: u32) -> usize {
+        self.input_sizes(input_size).len() * if self.flip { 2 } else { 1 }
+    }
+}
+
+/// Optional inference modes that trade speed for recall
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InferenceOptions {
+    /// Also run overlapping tiles of the image
+    pub tiling: Option<TileOptions>,
+
+    /// Run each image, and each tile, with test-time augmentation
+    pub tta: Option<TtaOptions>,
+}
+
+/// Diagnostics about how the model is being run
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemInfo {
+    /// The accelerators ONNX Runtime was built with and can find drivers for
+    pub available: AcceleratorAvailability,
+
+    /// The execution provider actually running the model, never `Auto`
+    pub provider: ExecutionProviderKind,
+
+    pub input_precision: ModelPrecision,
+    pub output_precision: ModelPrecision,
+
+    /// The ONNX Runtime build, including its version
+    pub onnx_runtime: String,
+
+    pub intra_threads: Option<usize>,
+    pub inter_threads: Option<usize>,
+
+    /// The number of CPU threads available to the process
+    pub available_parallelism: usize,
+}
+
+impl fmt::Display for SystemInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let threads = |threads: Option<usize>| {
+            threads.map_or_else(|| String::from("default"), |threads| threads.to_string())
+        };
+
+        writeln!(f, "Execution provider: {}", self.provider)?;
+        writeln!(
+            f,
+            "Model precision: {} input, {} output",
+            self.input_precision, self.output_precision
+        )?;
+        writeln!(
+            f,
+            "Available: coreml={} tensorrt={} cuda={} directml={}",
+            self.available.coreml,
+            self.available.tensor_rt,
+            self.available.cuda,
+            self.available.direct_ml
+        )?;
+        writeln!(f, "Intra-op threads: {}", threads(self.intra_threads))?;
+        writeln!(f, "Inter-op threads: {}", threads(self.inter_threads))?;
+        writeln!(f, "CPU threads: {}", self.available_parallelism)?;
+        write!(f, "{}", self.onnx_runtime)
+    }
+}
+
+fn model_error(err: impl fmt::Display) -> Error {
+    Error::Model(err.to_string())
+}
+
+/// Create a session for the model, on the CPU when no provider is given
+fn load_session(
+    model_path: &str,
+    options: &ExecutionOptions,
+    provider: Option<ExecutionProviderDispatch>,
+) -> Result<Session> {
+    let mut builder = Session::builder()?;
+    if let Some(threads) = options.intra_threads {
+        builder = builder.with_intra_threads(threads).map_err(model_error)?;
+    }
+    if let Some(threads) = options.inter_threads {
+        builder = builder
+            .with_parallel_execution(true)
+            .map_err(model_error)?
+            .with_inter_threads(threads)
+            .map_err(model_error)?;
+    }
+    if let Some(provider) = provider {
+        builder = builder
+            .with_execution_providers([provider.error_on_failure()])
+            .map_err(model_error)?;
+    }
+    Ok(builder.commit_from_file(model_path)?)
+}
+
+impl YoloModel {
+    pub fn new_from_file(model_path: &str, input_size: (usize, usize)) -> Result<Self> {
+        Self::new_with_options(model_path, input_size, &ExecutionOptions::default())
+    }
 
+    /// Load the model with the given execution provider and threads
+    ///
+    /// With the `Auto` provider each available accelerator is tried in turn before the CPU.
+    pub fn new_with_options(
+        model_path: &str,
+        input_size: (usize, usize),
+        options: &ExecutionOptions,
+    ) -> Result<Self> {
         let accelerator_availability = AcceleratorAvailability {
-            coreml: coreml.is_available().unwrap(),
-            tensor_rt: tensor_rt.is_available().unwrap(),
-            cuda: cuda.is_available().unwrap(),
-            direct_ml: direct_ml.is_available().unwrap(),
+            coreml: CoreMLExecutionProvider::default().is_available()?,
+            tensor_rt: TensorRTExecutionProvider::default().is_available()?,
+            cuda: CUDAExecutionProvider::default().is_available()?,
+            direct_ml: DirectMLExecutionProvider::default().is_available()?,
         };
 
-        let accelerated_model: Result<Session, Box<dyn std::error::Error>> = (|| {
-            let builder = Session::builder()?;
-            let mut builder = builder
-                .with_execution_providers(vec![
-                    coreml.build(),
-                    tensor_rt.build(),
-                    cuda.build(),
-                    direct_ml.build(),
-                ])
-                .map_err(|err| std::io::Error::other(err.to_string()))?;
-            Ok(builder.commit_from_file(model_path)?)
-        })();
-
-        let model = match accelerated_model {
-            Ok(model) => model,
-            Err(err) => {
-                eprintln!(
-                    "Failed to load model with hardware acceleration, falling back to CPU: {err}"
-                );
-                Session::builder()?.commit_from_file(model_path)?
+        let candidates = match options.provider {
+            ExecutionProviderKind::Auto => ExecutionProviderKind::ACCELERATORS
+                .into_iter()
+                .filter(|kind| kind.is_available(&accelerator_availability))
+                .collect(),
+            ExecutionProviderKind::Cpu => vec![],
+            forced if !forced.is_available(&accelerator_availability) => {
+                return Err(Error::Model(format!("{} is not available", forced)));
             }
+            forced => vec![forced],
         };
 
-        println!("Model loaded");
+        let mut loaded = None;
+        for provider in candidates {
+            match load_session(model_path, options, provider.dispatch()) {
+                Ok(model) => {
+                    loaded = Some((model, provider));
+                    break;
+                }
+                Err(err) if options.provider == ExecutionProviderKind::Auto => {
+                    eprintln!(
+                        "Failed to load model with {provider}, trying the next provider: {err}"
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let (model, provider) = match loaded {
+            Some(loaded) => loaded,
+            None => (
+                load_session(model_path, options, None)?,
+                ExecutionProviderKind::Cpu,
+            ),
+        };
+
+        // FP16 models may take and return either precision depending on how they were converted
+        let input = model
+            .inputs()
+            .first()
+            .ok_or_else(|| Error::Model(String::from("The model has no inputs")))?;
+        let output = model
+            .outputs()
+            .first()
+            .ok_or_else(|| Error::Model(String::from("The model has no outputs")))?;
+        let input_precision = ModelPrecision::of(input)?;
+        let output_precision = ModelPrecision::of(output)?;
+        let dynamic_input = match input.dtype() {
+            ValueType::Tensor { shape, .. } => shape.iter().skip(2).any(|dim| *dim < 0),
+            _ => false,
+        };
 
         Ok(Self {
             model,
-            input_size,
             accelerator_availability,
+            provider,
+            options: options.clone(),
+            input_precision,
+            output_precision,
+            input_size,
+            dynamic_input,
+            inference: InferenceOptions::default(),
+            timings: StageTimings::default(),
         })
     }
 
+    /// The inference modes used by [`YoloModel::detect`]
+    pub fn inference_options(&self) -> &InferenceOptions {
+        &self.inference
+    }
+
+    /// Set the inference modes, failing if tiles are smaller than [`TileOptions::MIN_SIZE`]
+    pub fn set_inference_options(&mut self, options: InferenceOptions) -> Result<()> {
+        if let Some(tiling) = &options.tiling {
+            tiling.validate()?;
+        }
+        self.inference = options;
+        Ok(())
+    }
+
+    /// Time spent in each stage of the last call to [`YoloModel::detect`]
+    pub fn timings(&self) -> StageTimings {
+        self.timings
+    }
+
+    /// The element types the model takes and returns
+    pub fn precision(&self) -> (ModelPrecision, ModelPrecision) {
+        (self.input_precision, self.output_precision)
+    }
+
     pub fn accelerator_availability(&self) -> &AcceleratorAvailability {
         &self.accelerator_availability
     }
 
+    /// The execution provider running the model
+    pub fn provider(&self) -> ExecutionProviderKind {
+        self.provider
+    }
+
+    /// Diagnostics about how the model is being run
+    pub fn system_info(&self) -> SystemInfo {
+        SystemInfo {
+            available: self.accelerator_availability.clone(),
+            provider: self.provider,
+            input_precision: self.input_precision,
+            output_precision: self.output_precision,
+            onnx_runtime: ort::info().to_string(),
+            intra_threads: self.options.intra_threads,
+            inter_threads: self.options.inter_threads,
+            available_parallelism: std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+        }
+    }
+
     pub fn detect(
         &mut self,
         original_img: &DynamicImage,
         conf_threshold: Option<f32>,
         nms_threshold: Option<f32>,
-    ) -> Result<Vec<Detection>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<Detection>> {
         let conf_threshold = conf_threshold.unwrap_or(0.3);
-        println!("Confidence threshold: {:?}", conf_threshold);
         let nms_threshold = nms_threshold.unwrap_or(0.45);
-        println!("NMS threshold: {:?}", nms_threshold);
 
-        let target_size = self.input_size.0 as u32;
+        self.timings = StageTimings::default();
+
+        let mut boxes = self.infer_augmented(original_img, conf_threshold)?;
+
+        // Small objects are detected in tiles at a higher resolution than the full frame
+        if let Some(tiling) = self.inference.tiling {
+            for (x, y, width, height) in tiling.tiles(original_img.width(), original_img.height()) {
+                let tile = original_img.crop_imm(x, y, width, height);
+                boxes.extend(
+                    self.infer_augmented(&tile, conf_threshold)?
+                        .into_iter()
+                        .map(|mut detection| {
+                            detection.bbox.x += x as f32;
+                            detection.bbox.y += y as f32;
+                            detection
+                        }),
+                );
+            }
+        }
 
-        let start = std::time::Instant::now();
+        // Non-maximum suppression, or fusion of the augmented passes
+        let nms_start = Instant::now();
+        boxes = match &self.inference.tta {
+            Some(tta) if tta.merge == MergeMethod::WeightedBoxFusion => {
+                let passes = tta.passes(self.input_size.0 as u32);
+                weighted_box_fusion(boxes, nms_threshold, passes)
+            }
+            _ => non_max_suppression(boxes, nms_threshold),
+        };
+        self.timings.postprocess += nms_start.elapsed();
+
+        Ok(boxes)
+    }
+
+    /// Run the model on an image once, or once per pass with test-time augmentation
+    fn infer_augmented(
+        &mut self,
+        img: &DynamicImage,
+        conf_threshold: f32,
+    ) -> Result<Vec<Detection>> {
+        let input_size = self.input_size.0 as u32;
+        let tta = match &self.inference.tta {
+            Some(tta) => tta.clone(),
+            None => return self.infer(img, conf_threshold, input_size),
+        };
+
+        let flipped = tta.flip.then(|| img.fliph());
+        let mut boxes = vec![];
+        for size in tta.input_sizes(input_size) {
+            if size != input_size && !self.dynamic_input {
+                return Err(Error::Model(String::from(
+                    "The model has a fixed input size, so cannot be run at other scales",
+                )));
+            }
+
+            boxes.extend(self.infer(img, conf_threshold, size)?);
+            if let Some(flipped) = &flipped {
+                boxes.extend(self.infer(flipped, conf_threshold, size)?.into_iter().map(
+                    |mut detection| {
+                        detection.bbox.x = img.width() as f32 - detection.bbox.x - detection.bbox.w;
+                        detection
+                    },
+                ));
+            }
+        }
+        Ok(boxes)
+    }
+
+    /// Run the model on an image at a square input size, returning boxes in its pixels before
+    /// non-maximum suppression
+    fn infer(
+        &mut self,
+        original_img: &DynamicImage,
+        conf_threshold: f32,
+        target_size: u32,
+    ) -> Result<Vec<Detection>> {
+        let start = Instant::now();
 
         let (img_width, img_height) = (original_img.width(), original_img.height());
         let img = original_img.resize_exact(target_size, target_size, FilterType::CatmullRom);
@@ -138,16 +648,40 @@ impl YoloModel {
 
         let (input_data, input_offset) = input.into_raw_vec_and_offset();
         debug_assert!(input_offset.is_none() || input_offset == Some(0));
-        let input = Tensor::from_array((
-            [1, 3, target_size as usize, target_size as usize],
-            input_data,
-        ))?;
-        let outputs = self.model.run(ort::inputs!["images" => input])?;
+        let input_shape = [1, 3, target_size as usize, target_size as usize];
+        self.timings.preprocess += start.elapsed();
+
+        let inference_start = Instant::now();
+        let outputs = match self.input_precision {
+            ModelPrecision::Float32 => {
+                let input = Tensor::from_array((input_shape, input_data))?;
+                self.model.run(ort::inputs!["images" => input])?
+            }
+            ModelPrecision::Float16 => {
+                let input_data: Vec<f16> = input_data.into_iter().map(f16::from_f32).collect();
+                let input = Tensor::from_array((input_shape, input_data))?;
+                self.model.run(ort::inputs!["images" => input])?
+            }
+        };
+        self.timings.inference += inference_start.elapsed();
 
         // Postprocessing
-        let (output_shape, output_data) = outputs[0].try_extract_tensor::<f32>()?;
+        let postprocess_start = Instant::now();
+        let (output_shape, output_data) = match self.output_precision {
+            ModelPrecision::Float32 => {
+                let (shape, data) = outputs[0].try_extract_tensor::<f32>()?;
+                (shape, Cow::Borrowed(data))
+            }
+            ModelPrecision::Float16 => {
+                let (shape, data) = outputs[0].try_extract_tensor::<f16>()?;
+                (
+                    shape,
+                    Cow::Owned(data.iter().map(|value| value.to_f32()).collect()),
+                )
+            }
+        };
         let output_shape: Vec<_> = output_shape.iter().map(|dim| *dim as usize).collect();
-        let output = ArrayViewD::from_shape(IxDyn(&output_shape), output_data)?
+        let output = ArrayViewD::from_shape(IxDyn(&output_shape), &output_data[..])?
             .t()
             .into_owned();
 
@@ -191,12 +725,7 @@ impl YoloModel {
             });
         }
 
-        // Non-maximum suppression
-        boxes = non_max_suppression(boxes, nms_threshold);
-
-        println!("Boxes: {:?}", boxes);
-
-        println!("{:?}", start.elapsed());
+        self.timings.postprocess += postprocess_start.elapsed();
 
         Ok(boxes)
     }
@@ -251,12 +780,158 @@ fn non_max_suppression(detections: Vec<Detection>, nms_threshold: f32) -> Vec<De
     suppressed_detections
 }
 
+/// Weighted box fusion
+///
+/// Clusters overlapping detections of the same class and averages their boxes weighted by
+/// confidence, so the passes of test-time augmentation refine each other rather than only the
+/// most confident being kept. Clusters found by fewer than `passes` detections lose confidence.
+fn weighted_box_fusion(
+    detections: Vec<Detection>,
+    iou_threshold: f32,
+    passes: usize,
+) -> Vec<Detection> {
+    let mut sorted_detections = detections;
+    sorted_detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut clusters: Vec<(Detection, Vec<Detection>)> = vec![];
+    for detection in sorted_detections {
+        let cluster = clusters.iter_mut().find(|(fused, _)| {
+            fused.class == detection.class && fused.bbox.iou(&detection.bbox) > iou_threshold
+        });
+
+        match cluster {
+            Some((fused, members)) => {
+                members.push(detection);
+                *fused = fuse(members);
+            }
+            None => clusters.push((detection.clone(), vec![detection])),
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|(mut fused, members)| {
+            fused.score *= members.len().min(passes) as f32 / passes.max(1) as f32;
+            fused
+        })
+        .collect()
+}
+
+/// The confidence weighted average of a cluster of detections
+fn fuse(members: &[Detection]) -> Detection {
+    let total: f32 = members.iter().map(|detection| detection.score).sum();
+    let average = |value: fn(&BBox) -> f32| {
+        members
+            .iter()
+            .map(|detection| value(&detection.bbox) * detection.score)
+            .sum::<f32>()
+            / total
+    };
+
+    Detection {
+        class: members[0].class,
+        score: total / members.len() as f32,
+        bbox: BBox {
+            x: average(|bbox| bbox.x),
+            y: average(|bbox| bbox.y),
+            w: average(|bbox| bbox.w),
+            h: average(|bbox| bbox.h),
+        },
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
     use tracing_test::traced_test;
 
+    #[test]
+    fn test_weighted_box_fusion() {
+        let detection = |class, score, x| Detection {
+            class,
+            score,
+            bbox: BBox {
+                x,
+                y: 0.0,
+                w: 100.0,
+                h: 100.0,
+            },
+        };
+
+        let fused = weighted_box_fusion(
+            vec![
+                detection(0, 0.6, 10.0),
+                detection(0, 0.2, 20.0),
+                detection(1, 0.9, 10.0),
+                detection(0, 0.5, 500.0),
+            ],
+            0.45,
+            2,
+        );
+
+        assert_eq!(fused.len(), 3);
+        assert_eq!(fused[0].class, 1);
+        assert_eq!(fused[0].score, 0.45);
+        assert_eq!(fused[1].class, 0);
+        assert!((fused[1].score - 0.4).abs() < 1e-6);
+        assert!((fused[1].bbox.x - 12.5).abs() < 1e-4);
+        assert_eq!(fused[2].score, 0.25);
+
+        let tta = TtaOptions::default();
+        assert_eq!(tta.input_sizes(640), vec![640, 544, 736]);
+        assert_eq!(tta.passes(640), 6);
+    }
+
+    #[test]
+    fn test_tiles() {
+        let tiling = TileOptions {
+            size: 1000,
+            overlap: 0.2,
+        };
+
+        assert!(tiling.tiles(1000, 800).is_empty());
+        assert_eq!(
+            tiling.tiles(4000, 900),
+            vec![
+                (0, 0, 1000, 900),
+                (800, 0, 1000, 900),
+                (1600, 0, 1000, 900),
+                (2400, 0, 1000, 900),
+                (3000, 0, 1000, 900),
+            ]
+        );
+
+        let tiles = tiling.tiles(4000, 3000);
+        assert_eq!(tiles.len(), 5 * 4);
+        assert_eq!(tiles.last(), Some(&(3000, 2000, 1000, 1000)));
+
+        assert!(tiling.validate().is_ok());
+        assert!(matches!(
+            TileOptions { size: 0, ..tiling }.validate(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_execution_provider_kind() {
+        assert_eq!(
+            "CUDA".parse::<ExecutionProviderKind>().unwrap(),
+            ExecutionProviderKind::Cuda
+        );
+        assert_eq!(
+            "directml".parse::<ExecutionProviderKind>().unwrap(),
+            ExecutionProviderKind::DirectML
+        );
+        assert!("gpu".parse::<ExecutionProviderKind>().is_err());
+
+        let options: ExecutionOptions =
+            serde_json::from_str(r#"{"provider": "tensorrt", "intra_threads": 4}"#).unwrap();
+        assert_eq!(options.provider, ExecutionProviderKind::TensorRT);
+        assert_eq!(options.intra_threads, Some(4));
+        assert_eq!(options.inter_threads, None);
+    }
+
     #[traced_test]
     #[test]
     fn test_model() {
@@ -30,19 +30,138 @@ export async function listenProgress(
   });
 }
 
+export interface IncrementalOptions {
+  /** Skip images unchanged since they were last processed */
+  incremental: boolean;
+  /**
+   * A previous JSON or JSON Lines export to take the results of unchanged images from, otherwise
+   * the results of this session are used
+   */
+  previousResults?: string;
+}
+
+export type ExecutionProvider =
+  | "auto"
+  | "cpu"
+  | "coreml"
+  | "tensorrt"
+  | "cuda"
+  | "directml";
+
+/** How the model is run, the defaults use the first available accelerator */
+export interface ExecutionOptions {
+  /** A specific provider fails to load if it is unavailable, rather than falling back to the CPU */
+  provider?: ExecutionProvider;
+  /** The number of threads used within each operation */
+  intra_threads?: number;
+  /** The number of threads used to run operations in parallel */
+  inter_threads?: number;
+}
+
+/** Overlapping tiles run alongside the full image, to detect small or distant animals */
+export interface TileOptions {
+  /** The width and height of each tile in pixels of the original image */
+  size?: number;
+  /** The fraction of each tile that overlaps its neighbour */
+  overlap?: number;
+}
+
+/** How the detections of several passes over an image are combined */
+export type MergeMethod = "Nms" | "WeightedBoxFusion";
+
+/** Test-time augmentation, running each image several times with small changes */
+export interface TtaOptions {
+  /** Also run the horizontally flipped image */
+  flip?: boolean;
+  /** The input sizes to run, as multiples of the model's input size */
+  scales?: number[];
+  merge?: MergeMethod;
+}
+
+/** Slower inference modes for better recall */
+export interface InferenceOptions {
+  tiling?: TileOptions;
+  tta?: TtaOptions;
+}
+
+export interface SystemInfo {
+  available: {
+    coreml: boolean;
+    tensor_rt: boolean;
+    cuda: boolean;
+    direct_ml: boolean;
+  };
+  /** The provider actually running the model */
+  provider: Exclude<ExecutionProvider, "auto">;
+  input_precision: "float32" | "float16";
+  output_precision: "float32" | "float16";
+  /** The ONNX Runtime build info, including its version */
+  onnx_runtime: string;
+  intra_threads: number | null;
+  inter_threads: number | null;
+  available_parallelism: number;
+}
+
+/**
+ * Load the model and report how it would be run
+ */
+export async function systemInfo(
+  execution?: ExecutionOptions
+): Promise<SystemInfo> {
+  return await invoke("system_info", { execution });
+}
+
+/**
+ * Where a camera prints its information bar and how it prints dates: `Generic` at the bottom or
+ * top with dates day first, `MonthFirst` at the bottom with dates month first
+ */
+export type ImprintLayout = "Generic" | "MonthFirst";
+
+/** An area of the frame, in fractions (0-1) of the image width and height */
+export type MaskShape =
+  | { Rectangle: { x: number; y: number; width: number; height: number } }
+  | { Polygon: { points: [number, number][] } };
+
+/** Areas of the frame in which detections are dropped, e.g. a camera's information bar */
+export interface ExclusionMask {
+  /** The serial number or model of the camera the mask applies to, every camera if unset */
+  camera?: string;
+  shapes: MaskShape[];
+}
+
+export interface MaskOptions {
+  masks: ExclusionMask[];
+  /** The fraction of a detection inside a shape above which it is dropped, defaults to 0.5 */
+  max_overlap?: number;
+}
+
 /**
  * Run detection
+ *
+ * With an `imprint` layout, timestamps and temperatures missing from EXIF data are read from the
+ * information bar the camera prints on its photos.
  */
 export async function process(
   path: string,
   confidenceThreshold: number,
   recursive: boolean,
-  onProgress?: (report: ProgressReport) => void
+  onProgress?: (report: ProgressReport) => void,
+  options: IncrementalOptions = { incremental: false },
+  execution?: ExecutionOptions,
+  inference?: InferenceOptions,
+  imprint?: ImprintLayout,
+  masks?: MaskOptions
 ) {
   await invoke("process", {
     path,
     confidenceThreshold,
     recursive,
+    incremental: options.incremental,
+    previousResults: options.previousResults,
+    execution,
+    inference,
+    imprint,
+    masks,
   });
 
   if (onProgress) {
@@ -55,15 +174,154 @@ export async function process(
   }
 }
 
-export type ExportFormat = "json" | "csv";
-export type ImageExportFormat = "image-dir";
+export type ExportFormat =
+  | "json"
+  | "jsonl"
+  | "csv"
+  | "summary-csv"
+  | "sqlite"
+  | "parquet";
+export type ImageExportFormat = "image-dir" | "contact-sheets" | "html";
 export type AllExportFormat = ExportFormat | ImageExportFormat;
 
+/** Which detections to include in an export */
+export type LabelSelection = "Both" | "Model" | "Reviewed";
+
+/** How the box of a detection is written in an export */
+export type CoordinateSystem =
+  | "Pixels"
+  | "Normalized"
+  | "NormalizedCenter"
+  | "Xyxy";
+
+export interface CoordinateOptions {
+  system?: CoordinateSystem;
+  /** Round each value to this many decimal places, full precision if unset */
+  decimals?: number;
+}
+
 export async function createExport(
   format: ExportFormat,
-  outputPath: string
+  outputPath: string,
+  labels?: LabelSelection,
+  coordinates?: CoordinateOptions
+) {
+  return await invoke("export", { format, outputPath, labels, coordinates });
+}
+
+export type DetectionSource = "Model" | "Human";
+export type ReviewStatus = "Unreviewed" | "Verified" | "Rejected";
+
+export interface ReviewBox {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface Detection extends ReviewBox {
+  class_index: number;
+  confidence: number;
+  source: DetectionSource;
+  review: ReviewStatus;
+}
+
+export type MoonPhase =
+  | "New"
+  | "WaxingCrescent"
+  | "FirstQuarter"
+  | "WaxingGibbous"
+  | "Full"
+  | "WaningGibbous"
+  | "LastQuarter"
+  | "WaningCrescent";
+export type Trigger = "Motion" | "TimeLapse" | "External";
+
+/** How an image was captured, as recorded by the camera in its EXIF data */
+export interface ImageMetadata {
+  timestamp: string | null;
+  make: string | null;
+  model: string | null;
+  serial_number: string | null;
+  temperature: number | null;
+  moon_phase: MoonPhase | null;
+  trigger: Trigger | null;
+  sequence_index: number | null;
+  sequence_length: number | null;
+}
+
+export interface ImageDetections {
+  file: string;
+  error: string | null;
+  image_width: number | null;
+  image_height: number | null;
+  detections: Detection[];
+  review: ReviewStatus;
+  metadata: ImageMetadata | null;
+}
+
+/**
+ * Get the detections of an image, including any review
+ */
+export async function imageDetections(file: string): Promise<ImageDetections> {
+  return await invoke("image_detections", { file });
+}
+
+/**
+ * Mark a whole image as verified or rejected, e.g. to confirm it is empty
+ */
+export async function reviewImage(file: string, status: ReviewStatus) {
+  return await invoke("review_image", { file, status });
+}
+
+/**
+ * Mark a detection as verified or rejected
+ */
+export async function reviewDetection(
+  file: string,
+  index: number,
+  status: ReviewStatus
 ) {
-  return await invoke("export", { format, outputPath });
+  return await invoke("review_detection", { file, index, status });
+}
+
+/**
+ * Add a detection drawn by the reviewer, resolving to its index
+ */
+export async function addDetection(
+  file: string,
+  bbox: ReviewBox,
+  classIndex: number
+): Promise<number> {
+  return await invoke("add_detection", { file, bbox, classIndex });
+}
+
+/**
+ * Correct the box and/or class of a detection, resolving to the index of the
+ * corrected detection
+ */
+export async function correctDetection(
+  file: string,
+  index: number,
+  bbox?: ReviewBox,
+  classIndex?: number
+): Promise<number> {
+  return await invoke("correct_detection", { file, index, bbox, classIndex });
+}
+
+/**
+ * Delete a detection, model detections are kept as rejected
+ */
+export async function deleteDetection(file: string, index: number) {
+  return await invoke("delete_detection", { file, index });
+}
+
+/**
+ * Apply exclusion masks to the results of this session, returning how many unreviewed model
+ * detections were dropped
+ */
+export async function applyMasks(masks: MaskOptions): Promise<number> {
+  return await invoke("apply_masks", { masks });
 }
 
 export type FilterCriteriaOption = "Include" | "Intersect" | "Exclude";
@@ -89,7 +347,42 @@ export function createFilterCriteria(
   };
 }
 
-interface DrawCriteria {
+export type RgbColor = [number, number, number];
+
+export interface CategoryColors {
+  animals: RgbColor;
+  humans: RgbColor;
+  vehicles: RgbColor;
+}
+
+export type RedactMethod = "Blur" | "Pixelate";
+
+export interface RedactCriteria {
+  humans: boolean;
+  vehicles: boolean;
+  method?: RedactMethod;
+  /** Padding added to each side of a detection as a fraction of its size */
+  padding?: number;
+  /** Remove GPS location, camera/lens serial numbers and the maker note from the EXIF data */
+  strip_private_exif?: boolean;
+}
+
+export interface DrawOptions {
+  /** JPEG quality (1-100) used when an image has to be re-encoded */
+  jpeg_quality?: number;
+  /** Outline thickness as a fraction of the image's shorter side */
+  line_thickness?: number;
+  /** Draw the category and confidence above each box */
+  labels?: boolean;
+  /** Colours to draw each category with */
+  colors?: CategoryColors;
+  /** Opacity (0-1) of the fill drawn inside each box */
+  fill_opacity?: number;
+  /** Regions of the image to obscure for privacy */
+  redact?: RedactCriteria;
+}
+
+interface DrawCriteria extends DrawOptions {
   animals: boolean;
   humans: boolean;
   vehicles: boolean;
@@ -98,23 +391,196 @@ interface DrawCriteria {
 export function createDrawCriteria(
   animals: boolean,
   humans: boolean,
-  vehicles: boolean
-) {
+  vehicles: boolean,
+  options: DrawOptions = {}
+): DrawCriteria {
   return {
     animals: animals,
     humans: humans,
     vehicles: vehicles,
+    ...options,
   };
 }
 
+export interface ImageExportFailure {
+  file: string;
+  error: string;
+}
+
+export interface ImageExportReport {
+  exported: number;
+  failures: ImageExportFailure[];
+  cancelled: boolean;
+}
+
 export function exportImageSet(
   outputPath: string,
   filterCriteria: FilterCriteria,
   drawCriteria: DrawCriteria
-) {
+): Promise<ImageExportReport> {
   return invoke("export_image_set", {
     outputPath,
     filterCriteria: filterCriteria,
     drawCriteria: drawCriteria,
   });
 }
+
+export type ContactSheetGrouping = "Folder" | "Category";
+
+export interface ContactSheetCriteria {
+  group_by?: ContactSheetGrouping;
+  /** Maximum number of tiles on each sheet */
+  tiles_per_sheet?: number;
+  /** Number of tiles in each row */
+  columns?: number;
+  /** Width and height of each tile in pixels */
+  tile_size?: number;
+  /** Crop each tile to its top detection */
+  crop?: boolean;
+}
+
+/**
+ * Export contact sheets, resolving to the number of sheets written
+ */
+export function exportContactSheetSet(
+  outputPath: string,
+  filterCriteria: FilterCriteria,
+  sheetCriteria: ContactSheetCriteria
+): Promise<number> {
+  return invoke("export_contact_sheet_set", {
+    outputPath,
+    filterCriteria,
+    sheetCriteria,
+  });
+}
+
+/**
+ * Export a static HTML gallery for reviewing results in a browser
+ */
+export async function exportHtmlGallery(outputPath: string) {
+  return await invoke("export_html_gallery", { outputPath });
+}
+
+/**
+ * Cancel the image or contact sheet export in progress
+ */
+export async function cancelExport() {
+  return await invoke("cancel_export");
+}
+
+export async function listenExportProgress(
+  onProgress: (report: ProgressReport) => void
+) {
+  return await listen("export-progress", (event) => {
+    const report = event.payload as ProgressReport;
+    onProgress(report);
+  });
+}
+
+export interface ClassMetrics {
+  category: string;
+  /** Ground truth objects, or images containing the category for image level labels */
+  ground_truth: number;
+  /** Detections at or above the confidence threshold */
+  detections: number;
+  precision: number;
+  recall: number;
+  average_precision: number | null;
+}
+
+export interface CurvePoint {
+  threshold: number;
+  precision: number;
+  recall: number;
+  empty_accuracy: number;
+}
+
+export interface EvaluationReport {
+  images: number;
+  /** Ground truth images without results, which are not evaluated */
+  missing: number;
+  /** Whether detections were matched to boxes, rather than categories present in each image */
+  box_level: boolean;
+  confidence_threshold: number;
+  classes: ClassMetrics[];
+  map50: number | null;
+  empty_accuracy: number;
+  /** Precision, recall and empty accuracy from 0.05 to 0.95 confidence */
+  curve: CurvePoint[];
+}
+
+/**
+ * Evaluate the processed results against ground truth annotations, in COCO Camera Traps,
+ * MegaDetector or reviewed JSON export format
+ */
+export async function evaluateResults(
+  groundTruth: string,
+  confidenceThreshold: number
+): Promise<EvaluationReport> {
+  return await invoke("evaluate_results", { groundTruth, confidenceThreshold });
+}
+
+/** What to do with an image once it has been processed while watching */
+export type FileAction = "Keep" | { Move: string } | { Copy: string };
+
+export interface WatchOptions {
+  root: string;
+  recursive: boolean;
+  confidence_threshold: number;
+  /** How long a file must be unchanged before it is processed, in seconds */
+  settle_secs?: number;
+  /** How often to look for new files, in seconds */
+  poll_secs?: number;
+  /** Also process the images already in the folder */
+  include_existing?: boolean;
+  /** A JSON Lines file to append each result to */
+  output?: string;
+  actions?: {
+    empty?: FileAction;
+    animal?: FileAction;
+    human?: FileAction;
+    vehicle?: FileAction;
+  };
+}
+
+/**
+ * Watch a folder, running detection on new images as they arrive
+ *
+ * The `imprint` layout and exclusion `masks` apply to each new image as they do in `process`.
+ */
+export async function startWatch(
+  options: WatchOptions,
+  execution?: ExecutionOptions,
+  inference?: InferenceOptions,
+  imprint?: ImprintLayout,
+  masks?: MaskOptions
+) {
+  return await invoke("start_watch", {
+    options,
+    execution,
+    inference,
+    imprint,
+    masks,
+  });
+}
+
+/**
+ * Stop watching a folder
+ */
+export async function stopWatch() {
+  return await invoke("stop_watch");
+}
+
+export async function listenWatchResult(
+  onResult: (image: ImageDetections) => void
+) {
+  return await listen("watch-result", (event) => {
+    onResult(event.payload as ImageDetections);
+  });
+}
+
+export async function listenWatchError(onError: (error: string) => void) {
+  return await listen("watch-error", (event) => {
+    onError(event.payload as string);
+  });
+}
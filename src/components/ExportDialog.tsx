@@ -5,11 +5,16 @@ import { useEffect, useState } from "react";
 import { PulseLoader } from "react-spinners";
 import {
   AllExportFormat,
+  cancelExport,
   createDrawCriteria,
   createExport,
   createFilterCriteria,
+  exportContactSheetSet,
+  exportHtmlGallery,
   exportImageSet,
   FilterCriteriaOption,
+  listenExportProgress,
+  ProgressReport,
 } from "../api";
 import "./ExportDialog.css";
 
@@ -41,6 +46,15 @@ const formatTypes: Format[] = [
     description:
       "Comma Separated Values (CSV) file containing a row for each detection from each in the dataset. Recommended for use with Excel, R, etc.",
   },
+  {
+    id: "summary-csv",
+    name: "Image Summary CSV",
+    pathType: "file",
+    defaultPath: "ct.5.1.0a.summary.csv",
+    disabled: false,
+    description:
+      "CSV file with a row for each image, counting detections and giving the highest confidence of each category, the top category, and the capture timestamp, camera and temperature from EXIF. Recommended for occupancy analysis.",
+  },
   {
     id: "json",
     name: "CamTrap JSON",
@@ -50,6 +64,33 @@ const formatTypes: Format[] = [
     description:
       "JavaScript Object Notation (JSON) file containing a row for each detection from each in the dataset. Recommended for use with Python, R, etc.",
   },
+  {
+    id: "jsonl",
+    name: "CamTrap JSON Lines",
+    pathType: "file",
+    defaultPath: "ct.5.1.0a.jsonl",
+    disabled: false,
+    description:
+      "JSON Lines file with one line per image, for tools that process results line by line or very large datasets.",
+  },
+  {
+    id: "sqlite",
+    name: "SQLite Database",
+    pathType: "file",
+    defaultPath: "ct.5.1.0a.sqlite",
+    disabled: false,
+    description:
+      "SQLite database with images, detections, categories and run tables, indexed by file and category. Recommended for querying millions of detections.",
+  },
+  {
+    id: "parquet",
+    name: "Parquet",
+    pathType: "dir",
+    defaultPath: "ct.5.1.0a.parquet",
+    disabled: false,
+    description:
+      "Folder of Parquet files, one per table of images, detections, categories and run information. Recommended for pandas, DuckDB, R etc.",
+  },
   {
     id: "image-dir",
     name: "Image Directory",
@@ -77,6 +118,24 @@ const formatTypes: Format[] = [
     ],
     disabled: false,
   },
+  {
+    id: "contact-sheets",
+    name: "Contact Sheets",
+    description:
+      "Directory of contact sheets, grids of thumbnails cropped to the top detection with the file name and category overlaid, one set per category. Uses the Image Directory filters.",
+    pathType: "dir",
+    defaultPath: "",
+    disabled: false,
+  },
+  {
+    id: "html",
+    name: "HTML Gallery",
+    description:
+      "Static website for reviewing detections in any browser without the app installed. Images are linked rather than copied, so keep the gallery alongside the images.",
+    pathType: "dir",
+    defaultPath: "",
+    disabled: false,
+  },
 ];
 
 export default function ExportDialog({ onReset }: { onReset: () => void }) {
@@ -97,9 +156,21 @@ export default function ExportDialog({ onReset }: { onReset: () => void }) {
     "Intersect" as FilterCriteriaOption
   );
 
+  const [imageExportLabels, setImageExportLabels] = useState(true);
+  const [imageExportRedact, setImageExportRedact] = useState(false);
+
   const [exportInProgress, setExportInProgress] = useState([] as string[]);
   const [exportError, setExportError] = useState<string | null>(null);
   const [exportSuccess, setExportSuccess] = useState<string | null>(null);
+  const [imageExportProgress, setImageExportProgress] =
+    useState<ProgressReport | null>(null);
+
+  useEffect(() => {
+    const unlisten = listenExportProgress(setImageExportProgress);
+    return () => {
+      unlisten.then((unlistenFunc) => unlistenFunc()).catch(console.error);
+    };
+  }, []);
 
   // Helper to handle export errors
   const handleExportError = (formatName: string, error: unknown) => {
@@ -135,7 +206,8 @@ export default function ExportDialog({ onReset }: { onReset: () => void }) {
             setExportInProgress((prev) => prev.filter((id) => id !== format.id));
             return;
           }
-          await exportImageSet(
+          setImageExportProgress(null);
+          const report = await exportImageSet(
             outputPath,
             createFilterCriteria(
               imageExportAnimalFilter,
@@ -143,12 +215,56 @@ export default function ExportDialog({ onReset }: { onReset: () => void }) {
               imageExportVehicleFilter,
               imageExportEmptyFilter
             ),
-            createDrawCriteria(true, true, true)
+            createDrawCriteria(true, true, true, {
+              labels: imageExportLabels,
+              redact: {
+                humans: imageExportRedact,
+                vehicles: imageExportRedact,
+                method: "Blur",
+                padding: 0.1,
+                strip_private_exif: imageExportRedact,
+              },
+            })
           );
+          if (report.cancelled) {
+            setExportError(
+              `Image export cancelled after ${report.exported} images.`
+            );
+            setTimeout(() => setExportError(null), 6000);
+            return;
+          }
+          if (report.failures.length > 0) {
+            handleExportError(
+              format.name,
+              `${report.failures.length} images could not be exported, e.g. ${report.failures[0].file}: ${report.failures[0].error}`
+            );
+            return;
+          }
+        } else if (format.id === "contact-sheets") {
+          const outputPath = await open({ directory: true });
+          if (!outputPath || Array.isArray(outputPath)) {
+            setExportInProgress((prev) => prev.filter((id) => id !== format.id));
+            return;
+          }
+          await exportContactSheetSet(
+            outputPath,
+            createFilterCriteria(
+              imageExportAnimalFilter,
+              imageExportHumanFilter,
+              imageExportVehicleFilter,
+              imageExportEmptyFilter
+            ),
+            { group_by: "Category", crop: true }
+          );
+        } else if (format.id === "html") {
+          const outputPath = await open({ directory: true });
+          if (!outputPath || Array.isArray(outputPath)) {
+            setExportInProgress((prev) => prev.filter((id) => id !== format.id));
+            return;
+          }
+          await exportHtmlGallery(outputPath);
         } else {
-          const defaultFileName =
-            format.id === "json" ? "ct.0.1.0.json" : "ct.0.1.0.csv";
-          const outputPath = await save({ defaultPath: defaultFileName });
+          const outputPath = await save({ defaultPath: format.defaultPath });
           if (!outputPath || Array.isArray(outputPath)) {
             setExportInProgress((prev) => prev.filter((id) => id !== format.id));
             return;
@@ -343,6 +459,26 @@ export default function ExportDialog({ onReset }: { onReset: () => void }) {
                 </tbody>
               </table>
             )}
+            {format.id === "image-dir" && (
+              <label style={{ fontSize: 12 }}>
+                <input
+                  type="checkbox"
+                  checked={imageExportLabels}
+                  onChange={(e) => setImageExportLabels(e.target.checked)}
+                />
+                Label boxes with category and confidence
+              </label>
+            )}
+            {format.id === "image-dir" && (
+              <label style={{ fontSize: 12, display: "block" }}>
+                <input
+                  type="checkbox"
+                  checked={imageExportRedact}
+                  onChange={(e) => setImageExportRedact(e.target.checked)}
+                />
+                Blur humans and vehicles, and remove GPS and serial numbers
+              </label>
+            )}
           </div>
           <div
             style={{
@@ -353,7 +489,19 @@ export default function ExportDialog({ onReset }: { onReset: () => void }) {
             }}
           >
             {exportInProgress.includes(format.id) ? (
-              <PulseLoader size={11} color={"#00bfff"} />
+              format.id === "image-dir" || format.id === "contact-sheets" ? (
+                <>
+                  <PulseLoader size={11} color={"#00bfff"} />
+                  {imageExportProgress && (
+                    <p style={{ fontSize: 12 }}>
+                      {imageExportProgress.current} / {imageExportProgress.total}
+                    </p>
+                  )}
+                  <button onClick={() => void cancelExport()}>Cancel</button>
+                </>
+              ) : (
+                <PulseLoader size={11} color={"#00bfff"} />
+              )
             ) : (
               <button
                 disabled={format.disabled}